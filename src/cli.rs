@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use crate::error::{FileError, TreeError};
+use crate::outline::{self, Format};
+use crate::query::Query;
+use crate::tree::id::{Id, UlidGen};
+use crate::tree::{NodeIterator, Tree};
+
+const USAGE: &str = "usage: termflowy
+       termflowy -
+       termflowy cat FILE
+       termflowy add FILE [--under PATH] TEXT
+       termflowy find FILE QUERY
+       termflowy complete FILE PATH
+       termflowy export FILE --format termflowy|text|markdown|opml
+
+With -, the outline is read from stdin and written to stdout on exit, in the format it was
+read in. It is written as it was read unless it was saved with :w, and not at all after :q!.
+PATH is the content of each bullet from the top level down, separated by /, where a unique
+prefix is enough. id:ID names a bullet by its id instead.
+QUERY is words to search for and predicates like tag:urgent, is:completed, depth:<3,
+has:children, under:\"Project X\" or modified:>=2026-10-01, combined with AND, OR, NOT
+and parentheses.";
+
+/// Nothing matched, or a path named no bullet
+const EXIT_NOT_FOUND: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+/// The file could not be read or written
+pub const EXIT_FILE: i32 = 3;
+
+/// Why a subcommand failed, with the exit code to report it with
+#[derive(Debug, PartialEq, Eq)]
+struct Failure {
+    code: i32,
+    msg: String,
+}
+
+impl Failure {
+    fn new(code: i32, msg: impl Into<String>) -> Failure {
+        Failure {
+            code,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl From<FileError> for Failure {
+    fn from(e: FileError) -> Failure {
+        Failure::new(EXIT_FILE, e.to_string())
+    }
+}
+
+impl From<TreeError> for Failure {
+    fn from(e: TreeError) -> Failure {
+        Failure::new(EXIT_NOT_FOUND, e.to_string())
+    }
+}
+
+/// Runs a subcommand that works on an outline file without starting the editor, for scripts.
+/// Returns the exit code, or None if `args` do not start with a subcommand.
+pub fn run(args: &[String]) -> Option<i32> {
+    match args.first().map(String::as_str) {
+        Some("cat") | Some("add") | Some("find") | Some("complete") | Some("export") => {}
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return Some(0);
+        }
+        _ => return None,
+    }
+    let mut out = String::new();
+    let code = match execute(args, &mut out) {
+        Ok(()) => 0,
+        Err(failure) => {
+            eprintln!("termflowy: {}", failure.msg);
+            if failure.code == EXIT_USAGE {
+                eprintln!("{}", USAGE);
+            }
+            failure.code
+        }
+    };
+    print!("{}", out);
+    Some(code)
+}
+
+fn execute(args: &[String], out: &mut String) -> Result<(), Failure> {
+    let (command, file, rest) = match args {
+        [command, file, rest @ ..] => (command.as_str(), Path::new(file), rest),
+        _ => return Err(Failure::new(EXIT_USAGE, "missing FILE")),
+    };
+    let (mut tree, repairs) = outline::load(file, Box::new(UlidGen::new()))?;
+    for repair in repairs {
+        eprintln!("termflowy: {}: repaired: {}", file.display(), repair);
+    }
+    match (command, rest) {
+        ("cat", []) => out.push_str(&outline::export(&tree, Format::Text)),
+        ("add", _) => {
+            let (under, text) = match rest {
+                [flag, path, text] if flag == "--under" => (path.as_str(), text),
+                [text] => ("", text),
+                _ => return Err(Failure::new(EXIT_USAGE, "add takes TEXT")),
+            };
+            let parent = resolve(&tree, under)?;
+            let id = append_child(&mut tree, parent)?;
+            tree.get_mut_active_content().push_str(text);
+            outline::save(&tree, file)?;
+            out.push_str(&format!("{}\n", id));
+        }
+        ("find", [query]) => {
+            let query = Query::parse(query).map_err(|e| Failure::new(EXIT_USAGE, e.to_string()))?;
+            let mut found = false;
+            for child in tree.root_iter().children_iter() {
+                find(child, &query, "", out, &mut found);
+            }
+            if !found {
+                return Err(Failure::new(EXIT_NOT_FOUND, "no bullet matches"));
+            }
+        }
+        ("complete", [path]) => {
+            let id = resolve(&tree, path)?;
+            if id == Id::ROOT {
+                return Err(Failure::new(EXIT_USAGE, "PATH names no bullet"));
+            }
+            tree.activate(id)?;
+            tree.set_completed(true);
+            outline::save(&tree, file)?;
+        }
+        ("export", [flag, name]) if flag == "--format" => {
+            let format = Format::from_name(name)
+                .ok_or_else(|| Failure::new(EXIT_USAGE, format!("unknown format: {}", name)))?;
+            out.push_str(&outline::export(&tree, format));
+        }
+        _ => {
+            let msg = format!("invalid arguments for {}", command);
+            return Err(Failure::new(EXIT_USAGE, msg));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the bullet at `path`. The empty path is the root.
+fn resolve(tree: &Tree, path: &str) -> Result<Id, Failure> {
+    if let Some(id) = path.strip_prefix("id:") {
+        let id = id
+            .parse()
+            .map_err(|e: String| Failure::new(EXIT_USAGE, e))?;
+        return match tree.contains(id) {
+            true => Ok(id),
+            false => Err(TreeError::NotFound(id).into()),
+        };
+    }
+    let mut itr = tree.root_iter();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let children: Vec<NodeIterator> = itr.children_iter().collect();
+        let exact: Vec<&NodeIterator> =
+            children.iter().filter(|c| c.content() == segment).collect();
+        let matches = match exact.len() {
+            0 => children
+                .iter()
+                .filter(|c| c.content().starts_with(segment))
+                .collect(),
+            _ => exact,
+        };
+        itr = match matches.as_slice() {
+            [only] => **only,
+            [] => {
+                let msg = format!("no bullet at {}", path);
+                return Err(Failure::new(EXIT_NOT_FOUND, msg));
+            }
+            _ => {
+                let msg = format!("{} matches several bullets in {}", segment, path);
+                return Err(Failure::new(EXIT_NOT_FOUND, msg));
+            }
+        };
+    }
+    Ok(itr.id())
+}
+
+/// Makes a new last child of `parent` and activates it
+fn append_child(tree: &mut Tree, parent: Id) -> Result<Id, TreeError> {
+    let last = tree
+        .node_iter(parent)
+        .ok_or(TreeError::NotFound(parent))?
+        .children_iter()
+        .last()
+        .map(|c| c.id());
+    match last {
+        Some(last) => {
+            tree.activate(last)?;
+            tree.create_sibling();
+        }
+        None => {
+            tree.activate(parent)?;
+            tree.create_sibling();
+            tree.indent(false)?;
+        }
+    }
+    Ok(tree.get_active_id())
+}
+
+/// Lists the path of every bullet that matches `query`
+fn find(itr: NodeIterator, query: &Query, parent: &str, out: &mut String, found: &mut bool) {
+    let path = match parent.is_empty() {
+        true => itr.content().clone(),
+        false => format!("{}/{}", parent, itr.content()),
+    };
+    if query.matches(&itr) {
+        out.push_str(&path);
+        out.push('\n');
+        *found = true;
+    }
+    for child in itr.children_iter() {
+        find(child, query, &path, out, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    /// A file for one test holding `text`
+    fn test_file(name: &str, text: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("termflowy-cli-{}-{}", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn exec(args: &[&str], file: &Path) -> (Result<(), Failure>, String) {
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        args.insert(1, file.to_str().unwrap().to_string());
+        let mut out = String::new();
+        (execute(&args, &mut out), out)
+    }
+
+    const OUTLINE: &str = "Projects\n  Termflowy\n    release\n  Garden\nInbox\n";
+
+    #[test]
+    fn cat_find_and_export() {
+        let file = test_file("cat", OUTLINE);
+        let (result, out) = exec(&["cat"], &file);
+        assert_eq!(result, Ok(()));
+        assert_eq!(out, "Projects\n\tTermflowy\n\t\trelease\n\tGarden\nInbox\n");
+
+        let (result, out) = exec(&["find", "R"], &file);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            out,
+            "Projects\nProjects/Termflowy\nProjects/Termflowy/release\nProjects/Garden\n"
+        );
+        let (result, _) = exec(&["find", "nothing"], &file);
+        assert_eq!(result.unwrap_err().code, EXIT_NOT_FOUND);
+        let (_, out) = exec(&["find", "under:projects NOT has:children"], &file);
+        assert_eq!(out, "Projects/Termflowy/release\nProjects/Garden\n");
+        let (result, _) = exec(&["find", "(release"], &file);
+        assert_eq!(result.unwrap_err().code, EXIT_USAGE);
+
+        let (_, out) = exec(&["export", "--format", "markdown"], &file);
+        assert!(out.starts_with("- Projects\n  - Termflowy\n"));
+        let (result, _) = exec(&["export", "--format", "pdf"], &file);
+        assert_eq!(result.unwrap_err().code, EXIT_USAGE);
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn add_and_complete() {
+        let file = test_file("add", OUTLINE);
+        let (result, id) = exec(&["add", "--under", "Projects/Term", "write docs"], &file);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            exec(&["add", "--under", "Projects/Garden", "weed"], &file).0,
+            Ok(())
+        );
+        assert_eq!(exec(&["add", "top"], &file).0, Ok(()));
+        assert_eq!(
+            exec(&["complete", "Projects/Termflowy/write"], &file).0,
+            Ok(())
+        );
+
+        let (tree, _) = outline::load(&file, Box::new(UlidGen::new())).unwrap();
+        assert_eq!(
+            outline::export(&tree, Format::Text),
+            "Projects\n\tTermflowy\n\t\trelease\n\t\t[x] write docs\n\tGarden\n\t\tweed\nInbox\ntop\n"
+        );
+        let path = format!("id:{}", id.trim());
+        assert_eq!(resolve(&tree, &path).map(|id| tree.contains(id)), Ok(true));
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn reports_failures() {
+        let file = test_file("fail", OUTLINE);
+        let code = |args: &[&str]| exec(args, &file).0.unwrap_err().code;
+        assert_eq!(code(&["complete", "Projects/Nope"]), EXIT_NOT_FOUND);
+        assert_eq!(code(&["add", "--under", "Projects/", "x", "y"]), EXIT_USAGE);
+        assert_eq!(code(&["complete", ""]), EXIT_USAGE);
+        assert_eq!(code(&["complete", "id:1"]), EXIT_NOT_FOUND);
+
+        let missing = env::temp_dir().join("termflowy-cli-missing");
+        assert_eq!(exec(&["cat"], &missing).0.unwrap_err().code, EXIT_FILE);
+        assert_eq!(run(&["edit".to_string()]), None);
+        fs::remove_file(&file).unwrap();
+    }
+}
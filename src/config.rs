@@ -0,0 +1,315 @@
+use std::{env, fs, io, path::PathBuf, time::Duration};
+
+use crate::handlers::{COMMAND_ACTIONS, INSERT_ACTIONS};
+use crate::keymap::{parse_keys, KeyMap, DEFAULT_LEADER};
+use crate::render::RenderOptions;
+use crate::theme::{self, Color, Theme};
+
+/// How long to wait for the next key of an ambiguous sequence, like vim's `timeoutlen`
+const DEFAULT_KEY_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// User configuration read from `config.toml`. Problems with the file never abort startup,
+/// they are collected in `errors` and the defaults are used instead.
+///
+/// ```toml
+/// [keys]
+/// leader = "<Space>"
+/// timeout = 500  # ms
+///
+/// [keys.command]
+/// delete_subtree = "dd"
+/// paste_below = ["p", "<C-p>"]
+/// undo = ""  # unbound
+///
+/// [keys.insert]
+/// exit_insert = ["<C-c>", "jk"]
+///
+/// [render]
+/// conceal_markup = true
+///
+/// [theme]
+/// base = "plain"  # or "default", what the other options start from
+/// bullets = ["blue", "bright-magenta", 208]  # by depth
+/// active = "yellow"
+/// completed = "bright-black"
+/// overdue = "red"
+/// tag = "green"
+/// link = "cyan"
+/// command_mode = "default"
+/// insert_mode = "green"
+/// ```
+pub struct Config {
+    pub command_keys: KeyMap,
+    pub insert_keys: KeyMap,
+    pub key_timeout: Duration,
+    pub render: RenderOptions,
+    pub errors: Vec<String>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            command_keys: KeyMap::new(COMMAND_ACTIONS, DEFAULT_LEADER),
+            insert_keys: KeyMap::new(INSERT_ACTIONS, DEFAULT_LEADER),
+            key_timeout: DEFAULT_KEY_TIMEOUT,
+            render: RenderOptions::default(),
+            errors: vec![],
+        }
+    }
+
+    /// Loads the config from the user's config directory, if there is one.
+    pub fn load() -> Config {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Config::new(),
+        };
+        match fs::read_to_string(&path) {
+            Ok(s) => Config::parse(&s),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Config::new(),
+            Err(e) => {
+                let mut config = Config::new();
+                config.errors.push(format!("could not read {}: {}", path.display(), e));
+                config
+            }
+        }
+    }
+
+    pub fn parse(s: &str) -> Config {
+        let mut config = Config::new();
+        let table: toml::Table = match s.parse() {
+            Ok(table) => table,
+            Err(e) => {
+                config.errors.push(format!("invalid config: {}", e.message()));
+                return config;
+            }
+        };
+        if let Some(keys) = table.get("keys") {
+            config.apply_keys(keys);
+        }
+        if let Some(render) = table.get("render") {
+            config.apply_render(render);
+        }
+        if let Some(theme) = table.get("theme") {
+            config.apply_theme(theme);
+        }
+        config
+    }
+
+    fn apply_keys(&mut self, keys: &toml::Value) {
+        let keys = match keys.as_table() {
+            Some(keys) => keys,
+            None => return self.errors.push(String::from("[keys] should be a table")),
+        };
+        // The leader has to be known before any binding is parsed
+        match keys.get("leader") {
+            Some(toml::Value::String(leader)) if parse_keys(leader, "").is_ok() => {
+                self.command_keys = KeyMap::new(COMMAND_ACTIONS, leader);
+                self.insert_keys = KeyMap::new(INSERT_ACTIONS, leader);
+            }
+            Some(_) => self.errors.push(String::from("leader should be a key sequence")),
+            None => {}
+        }
+        for (mode, value) in keys {
+            let (map, actions) = match mode.as_str() {
+                "leader" => continue,
+                "timeout" => {
+                    match value.as_integer() {
+                        Some(ms) if ms >= 0 => self.key_timeout = Duration::from_millis(ms as u64),
+                        _ => self.errors.push(String::from("timeout should be a positive number of ms")),
+                    }
+                    continue;
+                }
+                "command" => (&mut self.command_keys, COMMAND_ACTIONS),
+                "insert" => (&mut self.insert_keys, INSERT_ACTIONS),
+                _ => {
+                    self.errors.push(format!("unknown mode in [keys]: {}", mode));
+                    continue;
+                }
+            };
+            match value.as_table() {
+                Some(table) => {
+                    let errors = map.apply_overrides(actions, table);
+                    self.errors.extend(errors);
+                }
+                None => self.errors.push(format!("[keys.{}] should be a table", mode)),
+            }
+        }
+    }
+
+    fn apply_render(&mut self, render: &toml::Value) {
+        let render = match render.as_table() {
+            Some(render) => render,
+            None => return self.errors.push(String::from("[render] should be a table")),
+        };
+        for (key, value) in render {
+            match (key.as_str(), value) {
+                ("conceal_markup", toml::Value::Boolean(conceal)) => {
+                    self.render.conceal_markup = *conceal
+                }
+                ("conceal_markup", _) => {
+                    self.errors.push(String::from("conceal_markup should be true or false"))
+                }
+                _ => self.errors.push(format!("unknown option in [render]: {}", key)),
+            }
+        }
+    }
+
+    fn apply_theme(&mut self, theme: &toml::Value) {
+        let table = match theme.as_table() {
+            Some(table) => table,
+            None => return self.errors.push(String::from("[theme] should be a table")),
+        };
+        // The base has to be known before any color is set
+        match table.get("base").map(|b| b.as_str()) {
+            Some(Some("plain")) => self.render.theme = Theme::plain(),
+            Some(Some("default")) => self.render.theme = Theme::default(),
+            Some(_) => self.errors.push(String::from("base should be \"default\" or \"plain\"")),
+            None => {}
+        }
+        for (key, value) in table {
+            let theme = &mut self.render.theme;
+            let color = match key.as_str() {
+                "base" => continue,
+                "bullets" => {
+                    let values = match value {
+                        toml::Value::Array(values) => values.iter().collect(),
+                        value => vec![value],
+                    };
+                    match values.into_iter().map(color_value).collect() {
+                        Ok(colors) => theme.bullets = colors,
+                        Err(e) => self.errors.push(format!("bullets: {}", e)),
+                    }
+                    continue;
+                }
+                "active" => &mut theme.active,
+                "completed" => &mut theme.completed,
+                "overdue" => &mut theme.overdue,
+                "tag" => &mut theme.tag,
+                "link" => &mut theme.link,
+                "command_mode" => &mut theme.command_mode,
+                "insert_mode" => &mut theme.insert_mode,
+                _ => {
+                    self.errors.push(format!("unknown option in [theme]: {}", key));
+                    continue;
+                }
+            };
+            match color_value(value) {
+                Ok(value) => *color = value,
+                Err(e) => self.errors.push(format!("{}: {}", key, e)),
+            }
+        }
+    }
+
+    /// Summary of the config errors that fits in the status bar
+    pub fn status_msg(&self) -> String {
+        match self.errors.len() {
+            0 => String::new(),
+            1 => format!("config: {}", self.errors[0]),
+            n => format!("config: {} (+{} more)", self.errors[0], n - 1),
+        }
+    }
+}
+
+/// A color given by name or number
+fn color_value(value: &toml::Value) -> Result<Color, String> {
+    match value {
+        toml::Value::String(name) => theme::parse_color(name),
+        toml::Value::Integer(number) => theme::parse_color(&number.to_string()),
+        _ => Err(String::from("colors should be a name or a number")),
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("termflowy").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Lookup;
+    use ncurses as n;
+
+    fn is_bound(map: &KeyMap, notation: &str) -> bool {
+        matches!(map.lookup(&parse_keys(notation, " ").unwrap()), Lookup::Action(_))
+    }
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config = Config::parse("");
+        assert!(config.errors.is_empty());
+        assert!(is_bound(&config.command_keys, "dd"));
+        assert!(is_bound(&config.insert_keys, "<C-c>"));
+    }
+
+    #[test]
+    fn keys_are_overridden_per_mode() {
+        let config = Config::parse(
+            "[keys.command]\ndelete_subtree = 'x'\n[keys.insert]\nexit_insert = 'jk'",
+        );
+        assert!(config.errors.is_empty());
+        assert!(is_bound(&config.command_keys, "x"));
+        assert!(!is_bound(&config.command_keys, "dd"));
+        assert!(is_bound(&config.insert_keys, "jk"));
+        assert!(!is_bound(&config.insert_keys, "<C-c>"));
+    }
+
+    #[test]
+    fn leader_and_timeout() {
+        let config = Config::parse(
+            "[keys]\ntimeout = 20\nleader = '<Space>'\n[keys.command]\nundo = '<leader>u'",
+        );
+        assert!(config.errors.is_empty());
+        assert_eq!(config.key_timeout, Duration::from_millis(20));
+        assert!(is_bound(&config.command_keys, "<leader>u"));
+
+        let config = Config::parse("[keys]\ntimeout = -1\nleader = '<nope>'");
+        assert_eq!(config.errors.len(), 2);
+    }
+
+    #[test]
+    fn render_options() {
+        let config = Config::parse("[render]\nconceal_markup = true");
+        assert!(config.errors.is_empty());
+        assert!(config.render.conceal_markup);
+
+        let config = Config::parse("[render]\nconceal_markup = 1\nfoo = true");
+        assert_eq!(config.errors.len(), 2);
+        assert!(!config.render.conceal_markup);
+    }
+
+    #[test]
+    fn theme_colors() {
+        let config = Config::parse("[theme]\nbullets = 'red'\ntag = 208\nactive = 'bright-red'");
+        assert!(config.errors.is_empty());
+        let theme = &config.render.theme;
+        assert_eq!(theme.bullets, [n::COLOR_RED]);
+        assert_eq!((theme.tag, theme.active), (208, 9));
+        assert_eq!(theme.completed, Theme::default().completed);
+
+        let config = Config::parse("[theme]\nbase = 'plain'\ntag = 'green'");
+        let expected = Theme {
+            tag: n::COLOR_GREEN,
+            ..Theme::plain()
+        };
+        assert_eq!(config.render.theme, expected);
+
+        let config = Config::parse("[theme]\nbullets = ['red', 'mauve']\ntag = true\nfoo = 1");
+        assert_eq!(config.errors.len(), 3);
+        assert_eq!(config.render.theme, Theme::default());
+    }
+
+    #[test]
+    fn errors_do_not_abort() {
+        let config = Config::parse("[keys");
+        assert_eq!(config.errors.len(), 1);
+        assert!(is_bound(&config.command_keys, "dd"));
+
+        let config = Config::parse("[keys.visual]\nfoo = 'x'\n[keys.command]\nfoo = 'x'");
+        assert_eq!(config.errors.len(), 2);
+        assert!(config.status_msg().ends_with("(+1 more)"));
+    }
+}
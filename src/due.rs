@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+use crate::time::Date;
+
+/// Full names of the days of the week, from Monday. Any prefix of three letters or more names
+/// the day too.
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+/// Left out around words, so that dates can end a sentence or sit in parentheses
+const PUNCTUATION: &[char] = &['.', ',', ';', '!', '?', '(', ')', '[', ']', '"', '\''];
+
+/// The day a bullet with `content` is due: the first date after a `due:`, or else the first
+/// date anywhere in it. Dates are written like `2026-10-20`, since relative ones are
+/// [resolved](resolve_dates) as they are written.
+pub fn due_date(content: &str) -> Option<Date> {
+    let words = words(content);
+    let after_due = (1..words.len())
+        .filter(|&i| words[i - 1].1 == "due:")
+        .find_map(|i| words[i].1.parse().ok());
+    after_due.or_else(|| words.iter().find_map(|(_, word)| word.parse().ok()))
+}
+
+/// Rewrites the relative dates in `content` as the days they stand for when written on
+/// `today`, so that they stay the same on later days. Relative dates are `today`, `tomorrow`
+/// and `next fri`, and after `due:` a weekday alone like `due:fri`. `next fri` is the first
+/// Friday after `today`, while `due:fri` can be `today` itself.
+///
+/// Returns None if there is nothing to rewrite.
+pub fn resolve_dates(content: &str, today: Date) -> Option<String> {
+    let words = words(content);
+    let mut rewrites = vec![];
+    let mut i = 0;
+    while i < words.len() {
+        let after_due = i > 0 && words[i - 1].1 == "due:";
+        match relative_date(&words[i..], today, after_due) {
+            Some((date, count)) => {
+                rewrites.push((words[i].0.start..words[i + count - 1].0.end, date));
+                i += count;
+            }
+            None => i += 1,
+        }
+    }
+    if rewrites.is_empty() {
+        return None;
+    }
+    let mut content = content.to_string();
+    for (range, date) in rewrites.into_iter().rev() {
+        content.replace_range(range, &date.to_string());
+    }
+    Some(content)
+}
+
+/// The lowercase words of `content` without the punctuation around them, with where they are.
+/// A `due:` at the start of a word is a word of its own.
+fn words(content: &str) -> Vec<(Range<usize>, String)> {
+    let mut words = vec![];
+    for word in content.split_whitespace() {
+        let trimmed = word.trim_matches(PUNCTUATION);
+        if trimmed.is_empty() {
+            continue;
+        }
+        let start = trimmed.as_ptr() as usize - content.as_ptr() as usize;
+        let lower = trimmed.to_lowercase();
+        match lower.strip_prefix("due:") {
+            Some(value) if !value.is_empty() && lower.len() == trimmed.len() => {
+                words.push((start..start + 4, "due:".to_string()));
+                words.push((start + 4..start + trimmed.len(), value.to_string()));
+            }
+            _ => words.push((start..start + trimmed.len(), lower)),
+        }
+    }
+    words
+}
+
+/// The relative date at the start of `words` and how many words it takes up
+fn relative_date(
+    words: &[(Range<usize>, String)],
+    today: Date,
+    after_due: bool,
+) -> Option<(Date, usize)> {
+    match words.first()?.1.as_str() {
+        "today" => Some((today, 1)),
+        "tomorrow" => Some((today.add_days(1), 1)),
+        "next" => Some((next_weekday(today, weekday(&words.get(1)?.1)?, 1), 2)),
+        first if after_due => Some((next_weekday(today, weekday(first)?, 0), 1)),
+        _ => None,
+    }
+}
+
+fn weekday(word: &str) -> Option<usize> {
+    match word.len() >= 3 {
+        true => WEEKDAYS.iter().position(|day| day.starts_with(word)),
+        false => None,
+    }
+}
+
+/// The first day on `weekday` that is at least `skip` days after `from`
+fn next_weekday(from: Date, weekday: usize, skip: i64) -> Date {
+    let from = from.add_days(skip);
+    let ahead = (weekday as i64 - from.weekday() as i64).rem_euclid(7);
+    from.add_days(ahead)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(content: &str) -> String {
+        // A Wednesday
+        let today = Date::new(2026, 10, 14).unwrap();
+        resolve_dates(content, today).unwrap_or_else(|| content.to_string())
+    }
+
+    #[test]
+    fn finds_dates() {
+        let due = |content| due_date(content).map(|d| d.to_string());
+        assert_eq!(due("pay rent 2026-11-01."), Some("2026-11-01".into()));
+        assert_eq!(due("call Bob tomorrow"), None);
+        assert_eq!(due("no date 2026-02-30"), None);
+        assert_eq!(
+            due("moved from 2026-10-01 due:2026-10-20"),
+            Some("2026-10-20".into())
+        );
+        assert_eq!(
+            due("due: 2026-10-20, not 2026-10-01"),
+            Some("2026-10-20".into())
+        );
+    }
+
+    #[test]
+    fn resolves_relative_dates() {
+        assert_eq!(resolve("call Bob tomorrow"), "call Bob 2026-10-15");
+        assert_eq!(resolve("standup (today)"), "standup (2026-10-14)");
+        assert_eq!(
+            resolve("review next Fri, or next wed"),
+            "review 2026-10-16, or 2026-10-21"
+        );
+        assert_eq!(resolve("report due:wed"), "report due:2026-10-14");
+        assert_eq!(
+            resolve("report Due: next thursday"),
+            "report Due: 2026-10-15"
+        );
+        assert_eq!(resolve("a friday b"), "a friday b");
+        assert_eq!(resolve("né today"), "né 2026-10-14");
+        assert_eq!(
+            resolve_dates("pay 2026-11-01", Date::new(2026, 10, 14).unwrap()),
+            None
+        );
+    }
+}
@@ -1,79 +1,318 @@
 use std::{
-    cell::Cell,
     collections::{HashMap, VecDeque},
+    mem,
+    path::Path,
+    time::Duration,
 };
 
 use render::Point;
 use Cursor::*;
 
-use crate::raster::Raster;
-use crate::render::{tree_render, Window};
-use crate::{handlers, tree};
+use crate::config::Config;
+use crate::error::{EditorError, Error, JournalError, TreeError};
+use crate::filter::Filter;
+use crate::journal::Journal;
+use crate::keymap::{Action, KeyMap, Lookup};
+use crate::outline::{self, Format};
+use crate::query::Query;
+use crate::raster::{PixelState, Raster};
+use crate::render::{tree_render, RenderOptions, Window};
+use crate::theme::Theme;
+use crate::tree;
+use crate::tree::id::{Id, UlidGen};
 use crate::{render, PanelUpdate};
 
 const ERR_BOUNDS: &str = "cursor position was out of bounds";
-
-struct IdGen {
-    current: Cell<i32>,
-}
-
-impl tree::IdGenerator for IdGen {
-    fn gen(&self) -> i32 {
-        (self.current.get(), self.current.set(self.current.get() + 1)).0
-    }
-}
+/// How deeply replayed keys can trigger more replays, e.g. a macro that plays itself
+const MAX_REPLAY_DEPTH: usize = 100;
+const MAX_JUMPS: usize = 100;
 
 pub struct Editor {
     bullet_tree: tree::Tree,
     cursor: Cursor,
     raster: Raster,
-    command_map: HashMap<String, Handler>,
-    insert_map: HashMap<String, Handler>,
-    sticky_key: Option<String>,
+    command_map: KeyMap,
+    insert_map: KeyMap,
+    /// Keys typed so far that are the beginning of a longer binding
+    pending_keys: Vec<String>,
+    key_timeout: Duration,
+    render_options: RenderOptions,
+    /// Count prefix typed in command mode, applied to the next action
+    count: Option<usize>,
+    /// The change being recorded, which lasts until the cursor returns to command mode
+    change: Option<Change>,
+    last_change: Option<Change>,
+    /// An action that asked for the next key as its argument
+    awaiting_key: Option<AwaitingKey>,
+    macros: Macros,
+    replay_depth: usize,
+    marks: Marks,
     clipboard: Option<Clipboard>,
     history: VecDeque<HistoryItem>,
+    journal: Option<Journal>,
+    pipe: Option<Pipe>,
+    should_quit: bool,
+    /// A [message](HandlerOutput::message) for the next status bar update
+    message: Option<String>,
+}
+
+/// Where `:w` writes the outline when editing a piped outline, see
+/// [pipe_output](Editor::pipe_output)
+struct Pipe {
+    format: Format,
+    /// The outline as it was read, which is written out as it is unless it was written since
+    original: String,
+    /// The outline as of the last write, or as it was read
+    saved: String,
+    written: bool,
+    /// Whether the editor quit with `:q!`
+    discarded: bool,
 }
 
 impl Editor {
-    pub fn new(win: &mut dyn Window) -> Editor {
-        let tree = tree::Tree::new(Box::new(IdGen {
-            current: Cell::new(1),
-        }));
-        let (raster, pos) = render::tree_render(win, tree.root_iter(), tree.get_active_id(), 0);
+    pub fn new(win: &mut dyn Window, config: Config) -> Editor {
+        Editor::with_tree(win, config, tree::Tree::new(Box::new(UlidGen::new())))
+    }
+
+    /// Starts editing an existing tree, like one that was recovered
+    pub fn with_tree(win: &mut dyn Window, mut config: Config, tree: tree::Tree) -> Editor {
+        config.render.today = Some(tree.today());
+        let (raster, pos) =
+            render::tree_render(win, tree.root_iter(), tree.get_active_id(), 0, &config.render);
         let cursor = Cursor::new_insert(pos);
         win.move_cursor(cursor.pos());
         Editor {
             bullet_tree: tree,
             cursor,
             raster,
-            command_map: handlers::new_command_map(),
-            insert_map: handlers::new_insert_map(),
-            sticky_key: None,
+            command_map: config.command_keys,
+            insert_map: config.insert_keys,
+            pending_keys: vec![],
+            key_timeout: config.key_timeout,
+            render_options: config.render,
+            count: None,
+            change: None,
+            last_change: None,
+            awaiting_key: None,
+            macros: Macros::new(),
+            replay_depth: 0,
+            marks: Marks::new(),
             clipboard: None,
             history: VecDeque::new(),
+            journal: None,
+            pipe: None,
+            should_quit: false,
+            message: None,
         }
     }
 
-    pub fn update(&mut self, key: &str, win: &mut dyn Window) -> PanelUpdate {
-        let mut status_msg = String::new();
-        match self.cursor {
-            Command(_) => {
-                if let Err(msg) = self.on_command_key_press(&key, win) {
-                    status_msg = msg;
+    /// Makes `:w` keep the outline in `format` to be written out once the editor exits.
+    /// `original` is the text the outline was read from.
+    pub fn pipe_output(&mut self, original: &str, format: Format) {
+        self.pipe = Some(Pipe {
+            format,
+            original: original.to_string(),
+            saved: outline::export(&self.bullet_tree, format),
+            written: false,
+            discarded: false,
+        });
+    }
+
+    /// What to write out once the editor exits: the outline as of the last `:w`, or else the
+    /// text given to [pipe_output](Editor::pipe_output) unless the editor quit with `:q!`
+    pub fn piped_outline(&self) -> Option<&str> {
+        match &self.pipe {
+            Some(pipe) if pipe.written => Some(&pipe.saved),
+            Some(pipe) if !pipe.discarded => Some(&pipe.original),
+            _ => None,
+        }
+    }
+
+    fn run_ex_command(&mut self, command: ExCommand, win: &mut dyn Window) -> Result<(), Error> {
+        match &command {
+            ExCommand::Filter(text) => {
+                let query = Query::parse(text)?;
+                let filter = Filter::new(text, &self.bullet_tree, |node| query.matches(node));
+                return self.set_filter(Some(filter), win);
+            }
+            ExCommand::OpenSearch(name) => return self.open_search(name, win),
+            ExCommand::Agenda => {
+                let today = self.bullet_tree.today();
+                let agenda = Filter::agenda(today, &self.bullet_tree);
+                if agenda.match_count(&self.bullet_tree) == 0 {
+                    return Err(EditorError::NothingDue.into());
                 }
+                return self.set_filter(Some(agenda), win);
             }
-            Insert(_) => {
-                if let Err(msg) = self.on_insert_key_press(&key, win) {
-                    status_msg = msg;
+            ExCommand::Backlinks => {
+                let id = match self.cursor_mark() {
+                    Some(mark) => mark.id,
+                    None => self.bullet_tree.get_active_id(),
+                };
+                let backlinks = Filter::backlinks(id, &self.bullet_tree);
+                if backlinks.match_count(&self.bullet_tree) == 0 {
+                    return Err(EditorError::NoBacklinks.into());
                 }
+                return self.set_filter(Some(backlinks), win);
+            }
+            ExCommand::Tags => {
+                let tags = Filter::tags(&mut self.bullet_tree);
+                return self.set_filter(Some(tags), win);
+            }
+            ExCommand::Searches => {
+                let searches = Filter::searches(&self.bullet_tree);
+                return self.set_filter(Some(searches), win);
+            }
+            ExCommand::ClearFilter => return self.set_filter(None, win),
+            _ => {}
+        }
+        if let ExCommand::Write | ExCommand::WriteQuit = command {
+            let pipe = self.pipe.as_mut().ok_or(EditorError::NoOutput)?;
+            pipe.saved = outline::export(&self.bullet_tree, pipe.format);
+            pipe.written = true;
+        }
+        if let ExCommand::Quit = command {
+            if let Some(pipe) = &self.pipe {
+                if outline::export(&self.bullet_tree, pipe.format) != pipe.saved {
+                    return Err(EditorError::UnsavedChanges.into());
+                }
+            }
+        }
+        if let (ExCommand::ForceQuit, Some(pipe)) = (&command, self.pipe.as_mut()) {
+            pipe.discarded = true;
+        }
+        self.should_quit = command != ExCommand::Write;
+        Ok(())
+    }
+
+    /// Zooms into the live results of a [saved search](tree::searches::SavedSearch).
+    fn open_search(&mut self, name: &str, win: &mut dyn Window) -> Result<(), Error> {
+        let search = self
+            .bullet_tree
+            .saved_search(name)
+            .ok_or_else(|| EditorError::UnknownSearch(name.to_string()))?;
+        let query = Query::parse(&search.query)?;
+        let filter = Filter::zoomed(name, &search.query, query, &self.bullet_tree);
+        self.set_filter(Some(filter), win)
+    }
+
+    /// Shows only the bullets of the filter, or all of them again if None. The cursor stays on
+    /// its bullet unless that is filtered out, in which case it goes to the first match and
+    /// the jumplist records where it was.
+    fn set_filter(&mut self, filter: Option<Filter>, win: &mut dyn Window) -> Result<(), Error> {
+        let from = self.cursor_mark().unwrap_or(Mark {
+            id: self.bullet_tree.get_active_id(),
+            offset: 0,
+        });
+        let mut mark = from;
+        if let Some(filter) = &filter {
+            let count = filter.match_count(&self.bullet_tree);
+            if count == 0 {
+                return Err(EditorError::NoMatches(filter.query.clone()).into());
+            }
+            let visible = filter.visible(self.bullet_tree.root_iter(), None);
+            if !visible.contains(&mark.id) {
+                let id = filter.first_match(&self.bullet_tree).unwrap();
+                mark = Mark { id, offset: 0 };
+            }
+            self.message = Some(format!("{} matches", count));
+        }
+        if mark != from {
+            self.marks.jumps.push(from);
+        }
+        self.render_options.filter = filter;
+        self.render_at(mark, win)
+    }
+
+    /// Renders the tree with a command cursor on the content at `mark`.
+    fn render_at(&mut self, mark: Mark, win: &mut dyn Window) -> Result<(), Error> {
+        self.bullet_tree.activate(mark.id)?;
+        let len = self.bullet_tree.get_active_content().len();
+        let (raster, pos) = tree_render(
+            win,
+            self.bullet_tree.root_iter(),
+            mark.id,
+            len.saturating_sub(mark.offset),
+            &self.render_options,
+        );
+        self.raster = raster;
+        self.cursor = Command(CommandState { pos, col: pos.1 });
+        Ok(())
+    }
+
+    /// Journals every edit from now on to `dir`, see [Journal].
+    pub fn start_journal(&mut self, dir: &Path) -> Result<(), JournalError> {
+        self.journal = Some(Journal::start(dir, &mut self.bullet_tree)?);
+        Ok(())
+    }
+
+    /// Marks the session as cleanly ended
+    pub fn close_journal(&mut self) -> Result<(), JournalError> {
+        if let Some(journal) = self.journal.take() {
+            journal.close()?;
+        }
+        Ok(())
+    }
+
+    fn write_journal(&mut self) -> Result<(), JournalError> {
+        match &mut self.journal {
+            Some(journal) => journal.record(&mut self.bullet_tree),
+            None => Ok(()),
+        }
+    }
+
+    pub fn update(&mut self, key: &str, win: &mut dyn Window) -> PanelUpdate {
+        self.macros.record_key(key);
+        self.render_options.today = Some(self.bullet_tree.today());
+        let result = self.process_key(key, win);
+        self.make_panel_update(result, win)
+    }
+
+    /// Called when no key arrived within [pending_timeout](Editor::pending_timeout). Resolves the
+    /// pending keys to the longest binding they contain.
+    pub fn timeout(&mut self, win: &mut dyn Window) -> PanelUpdate {
+        let result = self.flush_pending_keys(win);
+        self.finish_change();
+        self.make_panel_update(result, win)
+    }
+
+    /// How long to wait for the next key before calling [timeout](Editor::timeout)
+    pub fn pending_timeout(&self) -> Option<Duration> {
+        match self.pending_keys.is_empty() {
+            true => None,
+            false => Some(self.key_timeout),
+        }
+    }
+
+    fn make_panel_update(&mut self, result: Result<(), Error>, win: &mut dyn Window) -> PanelUpdate {
+        let journaled = self.write_journal().map_err(Error::from);
+        let result = result.and(journaled);
+        let message = self.message.take();
+        let mut status_msg = String::new();
+        if let Err(e) = result {
+            status_msg = e.to_string();
+        }
+        if status_msg.is_empty() {
+            if let Some(count) = self.count {
+                status_msg.push_str(&count.to_string());
+            }
+            if let Some(awaiting) = &self.awaiting_key {
+                status_msg.push_str(&awaiting.keys.concat());
+                status_msg.push_str(&awaiting.args.concat());
             }
+            status_msg.push_str(&self.pending_keys.concat());
         }
-        if self.sticky_key.is_some() && status_msg.is_empty() {
-            status_msg = self.sticky_key.clone().unwrap();
+        if status_msg.is_empty() {
+            status_msg = message.unwrap_or_default();
+        }
+        if status_msg.is_empty() {
+            if let Some(register) = self.macros.recording_register() {
+                status_msg = format!("recording @{}", register);
+            }
         }
         win.move_cursor(self.cursor.pos());
         PanelUpdate {
-            should_quit: false,
+            should_quit: self.should_quit,
             status_msg,
         }
     }
@@ -81,68 +320,268 @@ impl Editor {
     pub fn cursor(&self) -> Cursor {
         self.cursor
     }
+
+    /// When the active bullet was created and last modified, for the status bar
+    pub fn bullet_info(&self) -> String {
+        let active = self.bullet_tree.active_iter();
+        let times = [("created", active.created()), ("modified", active.modified())];
+        let known: Vec<String> = times
+            .iter()
+            .filter_map(|(what, time)| {
+                Some(format!("{} {}", what, self.bullet_tree.local_time_string((*time)?)))
+            })
+            .collect();
+        known.join("  ")
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.render_options.theme
+    }
     
     pub fn get_tree(&self) -> &tree::Tree {
         &self.bullet_tree
     }
 
-    fn on_command_key_press(&mut self, key: &str, win: &mut dyn Window) -> Result<(), String> {
-        if let Some(handler) = self.command_map.get(key) {
-            let output = (*handler)(self.make_handler_input(key, win))?;
-            self.absorb_handler_output(output);
-            Ok(())
-        } else {
-            Err(format!("unknown command key: {}", key))
+    fn key_map(&self) -> &KeyMap {
+        match self.cursor {
+            Command(_) => &self.command_map,
+            Insert(_) => &self.insert_map,
+        }
+    }
+
+    fn process_key(&mut self, key: &str, win: &mut dyn Window) -> Result<(), Error> {
+        if let (Insert(_), Some(change)) = (self.cursor, self.change.as_mut()) {
+            change.keys.push(key.to_string());
         }
+        let result = self.dispatch_key(key, win);
+        self.finish_change();
+        result
     }
 
-    fn on_insert_key_press(&mut self, key: &str, win: &mut dyn Window) -> Result<(), String> {
-        if let Some(handler) = self.insert_map.get(key) {
-            let output = (*handler)(self.make_handler_input(key, win))?;
-            self.absorb_handler_output(output);
+    /// Passes the key to the action waiting for it, the count prefix or the key map.
+    fn dispatch_key(&mut self, key: &str, win: &mut dyn Window) -> Result<(), Error> {
+        if let Some(mut awaiting) = self.awaiting_key.take() {
+            self.count = awaiting.count;
+            awaiting.args.push(key.to_string());
+            self.run_action(awaiting.action, &awaiting.keys, &awaiting.args, win)
+        } else if self.is_count_key(key) {
+            let digit = key.parse::<usize>().unwrap();
+            self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
             Ok(())
         } else {
-            let cursor = self.cursor.insert_state();
-            {
-                let mut content = self.bullet_tree.get_mut_active_content();
-                let length = content.len();
-                content.insert_str(length - cursor.offset, &key);
+            self.pending_keys.push(key.to_string());
+            self.on_key_press(win)
+        }
+    }
+
+    /// Digits in command mode are a count prefix unless they are bound, and 0 can only continue a
+    /// count.
+    fn is_count_key(&self, key: &str) -> bool {
+        let is_digit = key.len() == 1 && key.chars().all(|c| c.is_ascii_digit());
+        matches!(self.cursor, Command(_))
+            && self.pending_keys.is_empty()
+            && is_digit
+            && (key != "0" || self.count.is_some())
+            && matches!(self.command_map.lookup(&[key.to_string()]), Lookup::None)
+    }
+
+    /// Makes the change being recorded the last change once the cursor is back in command mode.
+    fn finish_change(&mut self) {
+        if let Command(_) = self.cursor {
+            if let Some(change) = self.change.take() {
+                self.last_change = Some(change);
             }
-            let (raster, pos) = tree_render(
-                win,
-                self.bullet_tree.root_iter(),
-                self.bullet_tree.get_active_id(),
-                cursor.offset,
-            );
-            self.raster = raster;
-            self.cursor = Insert(InsertState {
-                pos,
-                offset: cursor.offset,
-            });
-            win.move_cursor(pos);
-            win.refresh();
-            Ok(())
         }
     }
 
+    /// Dispatches the pending keys to the handler bound to them in the map of the current mode.
+    /// Keys that could still be the beginning of a longer binding are kept pending.
+    fn on_key_press(&mut self, win: &mut dyn Window) -> Result<(), Error> {
+        match self.key_map().lookup(&self.pending_keys) {
+            Lookup::Action(action) => {
+                let keys = mem::take(&mut self.pending_keys);
+                self.run_action(action, &keys, &[], win)
+            }
+            Lookup::Ambiguous(_) | Lookup::Prefix => Ok(()),
+            Lookup::None => self.flush_pending_keys(win),
+        }
+    }
+
+    /// Runs the longest binding at the start of the pending keys and dispatches the rest again.
+    /// In insert mode, keys which do not start a binding are text.
+    fn flush_pending_keys(&mut self, win: &mut dyn Window) -> Result<(), Error> {
+        let mut keys = mem::take(&mut self.pending_keys);
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let rest = match (self.key_map().longest_match(&keys), self.cursor) {
+            (Some((action, len)), _) => {
+                let rest = keys.split_off(len);
+                self.run_action(action, &keys, &[], win)?;
+                rest
+            }
+            (None, Command(_)) => {
+                self.count = None;
+                return Err(EditorError::UnknownKey(keys.concat()).into());
+            }
+            (None, Insert(_)) => {
+                let rest = keys.split_off(1);
+                self.on_insert_text(&keys[0], win);
+                rest
+            }
+        };
+        for key in rest {
+            self.dispatch_key(&key, win)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the action bound to `keys`, once per count unless the action takes the count itself.
+    /// `args` are the keys typed after the binding if the action asked for them.
+    fn run_action(
+        &mut self,
+        action: &'static Action,
+        keys: &[String],
+        args: &[String],
+        win: &mut dyn Window,
+    ) -> Result<(), Error> {
+        let count = self.count.take();
+        let was_command = matches!(self.cursor, Command(_));
+        let (times, handler_count) = match action.takes_count {
+            true => (1, count),
+            false => (count.unwrap_or(1), None),
+        };
+        for _ in 0..times {
+            let awaited = self.run_handler(action.handler, keys, args, handler_count, win)?;
+            if let Some(args) = awaited {
+                self.awaiting_key = Some(AwaitingKey {
+                    action,
+                    keys: keys.to_vec(),
+                    args,
+                    count,
+                });
+                return Ok(());
+            }
+        }
+        if was_command && action.is_change {
+            let mut keys = keys.to_vec();
+            keys.extend_from_slice(args);
+            self.change = Some(Change { count, keys });
+        }
+        Ok(())
+    }
+
+    /// Runs a handler and then the keys it asked to replay. Returns the arguments to call the
+    /// handler again with once the next key is added to them, if it asked for that.
+    fn run_handler(
+        &mut self,
+        handler: Handler,
+        keys: &[String],
+        args: &[String],
+        count: Option<usize>,
+        win: &mut dyn Window,
+    ) -> Result<Option<Vec<String>>, Error> {
+        let mut input = self.make_handler_input(keys, win);
+        let from = input.current_mark;
+        input.args = args;
+        input.count = count;
+        let mut output = (handler)(input)?;
+        let awaited = match output.awaits_key {
+            true => Some(output.args.take().unwrap_or_else(|| args.to_vec())),
+            false => None,
+        };
+        if let Some(command) = output.ex_command.take() {
+            self.run_ex_command(command, win)?;
+        }
+        if let (true, Some(from)) = (output.is_jump, from) {
+            self.marks.jumps.push(from);
+        }
+        let replay = self.absorb_handler_output(output);
+        if cfg!(debug_assertions) {
+            if let Some(violation) = self.bullet_tree.check_invariants().into_iter().next() {
+                return Err(TreeError::Inconsistent(violation).into());
+            }
+        }
+        if let Some(replay) = replay {
+            if self.replay_depth >= MAX_REPLAY_DEPTH {
+                return Err(EditorError::ReplayTooDeep.into());
+            }
+            self.replay_depth += 1;
+            let result = replay.iter().try_for_each(|key| self.process_key(key, win));
+            self.replay_depth -= 1;
+            result?;
+        }
+        Ok(awaited)
+    }
+
+    /// The bullet and content offset under the cursor
+    fn cursor_mark(&self) -> Option<Mark> {
+        match self.cursor {
+            Command(state) => match self.raster.get(state.pos)? {
+                PixelState::Text { id, offset } => Some(Mark { id, offset }),
+                PixelState::Placeholder(id) => Some(Mark { id, offset: 0 }),
+                _ => None,
+            },
+            Insert(state) => Some(Mark {
+                id: self.bullet_tree.get_active_id(),
+                offset: self
+                    .bullet_tree
+                    .get_active_content()
+                    .len()
+                    .saturating_sub(state.offset),
+            }),
+        }
+    }
+
+    fn on_insert_text(&mut self, key: &str, win: &mut dyn Window) {
+        let cursor = self.cursor.insert_state();
+        {
+            let content = self.bullet_tree.get_mut_active_content();
+            let length = content.len();
+            content.insert_str(length - cursor.offset, key);
+        }
+        let (raster, pos) = tree_render(
+            win,
+            self.bullet_tree.root_iter(),
+            self.bullet_tree.get_active_id(),
+            cursor.offset,
+            &self.render_options,
+        );
+        self.raster = raster;
+        self.cursor = Insert(InsertState {
+            pos,
+            offset: cursor.offset,
+        });
+        win.move_cursor(pos);
+        win.refresh();
+    }
+
     fn make_handler_input<'a>(
         &'a mut self,
-        key: &'a str,
+        keys: &'a [String],
         win: &'a mut dyn Window,
     ) -> HandlerInput<'a> {
+        let current_mark = self.cursor_mark();
         HandlerInput {
-            key,
-            sticky_key: self.sticky_key.as_deref(),
+            keys,
+            args: &[],
+            count: None,
             cursor: self.cursor,
             tree: &mut self.bullet_tree,
             raster: &self.raster,
+            render_options: &self.render_options,
             win,
             clipboard: self.clipboard.as_ref(),
             history: &mut self.history,
+            last_change: self.last_change.as_ref(),
+            macros: &mut self.macros,
+            marks: &mut self.marks,
+            current_mark,
         }
     }
 
-    fn absorb_handler_output(&mut self, output: HandlerOutput) {
+    /// Applies the output to the editor state and returns the keys the handler asked to replay.
+    fn absorb_handler_output(&mut self, output: HandlerOutput) -> Option<Vec<String>> {
         if let Some(cursor) = output.cursor {
             self.cursor = cursor;
         }
@@ -153,10 +592,13 @@ impl Editor {
             // TODO limit how much history is stored
             self.history.push_back(item);
         }
-        self.sticky_key = output.sticky_key;
         if output.clipboard.is_some() {
             self.clipboard = output.clipboard;
         }
+        if output.message.is_some() {
+            self.message = output.message;
+        }
+        output.replay
     }
 }
 
@@ -208,25 +650,44 @@ impl Cursor {
     }
 }
 
-pub type Handler = fn(HandlerInput) -> Result<HandlerOutput, String>;
+pub type Handler = fn(HandlerInput) -> Result<HandlerOutput, Error>;
 
 pub struct HandlerInput<'a> {
-    pub key: &'a str,
-    pub sticky_key: Option<&'a str>,
+    /// The keys of the binding that triggered the handler
+    pub keys: &'a [String],
+    /// The keys typed after the binding, if the handler [asked for them](HandlerOutput::await_key)
+    pub args: &'a [String],
+    /// The count prefix, only given to actions which [take it](crate::keymap::Action::takes_count)
+    pub count: Option<usize>,
     pub cursor: Cursor,
     pub tree: &'a mut tree::Tree,
     pub raster: &'a Raster,
+    pub render_options: &'a RenderOptions,
     pub win: &'a mut dyn Window,
     pub clipboard: Option<&'a Clipboard>,
     pub history: &'a mut VecDeque<HistoryItem>,
+    pub last_change: Option<&'a Change>,
+    pub macros: &'a mut Macros,
+    pub marks: &'a mut Marks,
+    /// The bullet and content offset under the cursor, if there is one
+    pub current_mark: Option<Mark>,
 }
 
 pub struct HandlerOutput {
     pub cursor: Option<Cursor>,
     pub raster: Option<Raster>,
-    pub sticky_key: Option<String>,
     pub clipboard: Option<Clipboard>,
     pub history_item: Option<HistoryItem>,
+    /// Keys to feed back through the dispatcher after the handler returns
+    pub replay: Option<Vec<String>>,
+    pub awaits_key: bool,
+    /// Replaces the arguments the handler is called with next when it awaits a key
+    pub args: Option<Vec<String>>,
+    pub ex_command: Option<ExCommand>,
+    /// Whether the cursor moved far enough to be recorded in the jumplist
+    pub is_jump: bool,
+    /// Shown in the status bar unless something more pressing is
+    pub message: Option<String>,
 }
 
 impl HandlerOutput {
@@ -234,9 +695,14 @@ impl HandlerOutput {
         HandlerOutput {
             cursor: None,
             raster: None,
-            sticky_key: None,
             clipboard: None,
             history_item: None,
+            replay: None,
+            awaits_key: false,
+            args: None,
+            ex_command: None,
+            is_jump: false,
+            message: None,
         }
     }
 
@@ -250,11 +716,6 @@ impl HandlerOutput {
         self
     }
 
-    pub fn set_sticky_key(mut self, key: String) -> HandlerOutput {
-        self.sticky_key = Some(key);
-        self
-    }
-
     pub fn set_clipboard(mut self, clipboard: Clipboard) -> HandlerOutput {
         self.clipboard = Some(clipboard);
         self
@@ -264,10 +725,226 @@ impl HandlerOutput {
         self.history_item = Some(item);
         self
     }
+
+    pub fn set_replay(mut self, keys: Vec<String>) -> HandlerOutput {
+        self.replay = Some(keys);
+        self
+    }
+
+    /// Asks for the handler to be called again with the next key added to
+    /// [args](HandlerInput::args).
+    pub fn await_key(mut self) -> HandlerOutput {
+        self.awaits_key = true;
+        self
+    }
+
+    /// Like [await_key](HandlerOutput::await_key) but with `args` instead of the current ones,
+    /// e.g. to take back a character of a command line.
+    pub fn await_key_with(mut self, args: Vec<String>) -> HandlerOutput {
+        self.awaits_key = true;
+        self.args = Some(args);
+        self
+    }
+
+    pub fn set_ex_command(mut self, command: ExCommand) -> HandlerOutput {
+        self.ex_command = Some(command);
+        self
+    }
+
+    /// Records the position before the handler ran in the jumplist.
+    pub fn set_jump(mut self) -> HandlerOutput {
+        self.is_jump = true;
+        self
+    }
+
+    pub fn set_message(mut self, message: String) -> HandlerOutput {
+        self.message = Some(message);
+        self
+    }
+}
+
+/// A command typed on the `:` command line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExCommand {
+    Write,
+    /// Fails if the outline was changed since it was last written
+    Quit,
+    ForceQuit,
+    WriteQuit,
+    /// Shows only the bullets matching the query, see [Filter]
+    Filter(String),
+    /// Zooms into the saved search with this name
+    OpenSearch(String),
+    /// Lists what is due this week or overdue, see [Filter::agenda]
+    Agenda,
+    /// Lists the bullets linking to the one under the cursor, see [Filter::backlinks]
+    Backlinks,
+    /// Lists the bullets with each tag under the tag, see [Filter::tags]
+    Tags,
+    /// Lists the matches of each saved search under its name, see [Filter::searches]
+    Searches,
+    /// Leaves a filter or saved search
+    ClearFilter,
+}
+
+/// A change to the tree that can be repeated with `.`: the keys of a change action and, if it
+/// entered insert mode, every key typed until the cursor returned to command mode.
+pub struct Change {
+    pub count: Option<usize>,
+    pub keys: Vec<String>,
+}
+
+impl Change {
+    pub fn keys_with_count(&self) -> Vec<String> {
+        let mut keys: Vec<String> = match self.count {
+            Some(count) => count.to_string().chars().map(String::from).collect(),
+            None => vec![],
+        };
+        keys.extend(self.keys.iter().cloned());
+        keys
+    }
+}
+
+struct AwaitingKey {
+    action: &'static Action,
+    keys: Vec<String>,
+    args: Vec<String>,
+    count: Option<usize>,
+}
+
+/// Registers of keys recorded with `q{register}`
+pub struct Macros {
+    registers: HashMap<char, Vec<String>>,
+    recording: Option<(char, Vec<String>)>,
+    last_played: Option<char>,
+}
+
+impl Macros {
+    fn new() -> Macros {
+        Macros {
+            registers: HashMap::new(),
+            recording: None,
+            last_played: None,
+        }
+    }
+
+    fn record_key(&mut self, key: &str) {
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key.to_string());
+        }
+    }
+
+    pub fn recording_register(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, vec![]));
+    }
+
+    /// Stores the recorded keys, minus the last `binding_len` keys which stopped the recording.
+    pub fn stop(&mut self, binding_len: usize) {
+        if let Some((register, mut keys)) = self.recording.take() {
+            keys.truncate(keys.len().saturating_sub(binding_len));
+            self.registers.insert(register, keys);
+        }
+    }
+
+    pub fn get(&self, register: char) -> Option<&Vec<String>> {
+        self.registers.get(&register)
+    }
+
+    pub fn last_played(&self) -> Option<char> {
+        self.last_played
+    }
+
+    pub fn set_last_played(&mut self, register: char) {
+        self.last_played = Some(register);
+    }
+}
+
+/// A position in the tree that survives re-rendering and edits to other bullets
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mark {
+    pub id: Id,
+    pub offset: usize,
+}
+
+/// Marks set with `m{a-z}` and the jumplist walked with Ctrl-O and Ctrl-I
+pub struct Marks {
+    marks: HashMap<char, Mark>,
+    pub jumps: JumpList,
+}
+
+impl Marks {
+    fn new() -> Marks {
+        Marks {
+            marks: HashMap::new(),
+            jumps: JumpList::new(),
+        }
+    }
+
+    pub fn set(&mut self, name: char, mark: Mark) {
+        self.marks.insert(name, mark);
+    }
+
+    pub fn get(&self, name: char) -> Option<Mark> {
+        self.marks.get(&name).copied()
+    }
+}
+
+pub struct JumpList {
+    entries: Vec<Mark>,
+    /// Position while walking the list. Equal to the length when not walking.
+    index: usize,
+}
+
+impl JumpList {
+    fn new() -> JumpList {
+        JumpList {
+            entries: vec![],
+            index: 0,
+        }
+    }
+
+    /// Records the position a jump started from. Entries after the current one are dropped.
+    pub fn push(&mut self, from: Mark) {
+        self.entries.truncate(self.index);
+        if self.entries.last() != Some(&from) {
+            self.entries.push(from);
+        }
+        if self.entries.len() > MAX_JUMPS {
+            self.entries.remove(0);
+        }
+        self.index = self.entries.len();
+    }
+
+    /// Moves to the previous entry. `current` is remembered so that [forward](JumpList::forward)
+    /// can return to it.
+    pub fn back(&mut self, current: Mark) -> Option<Mark> {
+        if self.index == 0 {
+            return None;
+        }
+        if self.index == self.entries.len() {
+            self.entries.push(current);
+        }
+        self.index -= 1;
+        Some(self.entries[self.index])
+    }
+
+    pub fn forward(&mut self) -> Option<Mark> {
+        if self.index + 1 >= self.entries.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.entries[self.index])
+    }
 }
 
 pub enum Clipboard {
-    Tree(tree::Subtree),
+    /// Subtrees which are pasted as siblings, in order
+    Tree(Vec<tree::Subtree>),
+    Text(String),
 }
 
 pub enum HistoryItem {
@@ -277,8 +954,777 @@ pub enum HistoryItem {
         cursor: Cursor,
     },
     Text {
-        id: i32,
+        id: Id,
         index: usize,
         length: usize,
     },
+    /// The children of each bullet that was [sorted](tree::Tree::sort), in their order before
+    Order {
+        orders: Vec<(Id, Vec<Id>)>,
+        cursor: Cursor,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::TestWindow;
+    use crate::time::FixedClock;
+    use crate::tree::id::CounterGen;
+
+    fn new_test_editor(config: Config) -> (Editor, TestWindow) {
+        let mut win = TestWindow::new((10, 20), false);
+        let tree = tree::Tree::new(Box::new(CounterGen::new()));
+        let editor = Editor::with_tree(&mut win, config, tree);
+        (editor, win)
+    }
+
+    fn type_keys(editor: &mut Editor, win: &mut TestWindow, keys: &[&str]) -> PanelUpdate {
+        let mut update = None;
+        for key in keys {
+            update = Some(editor.update(key, win));
+        }
+        update.unwrap()
+    }
+
+    fn contents(editor: &Editor) -> Vec<String> {
+        editor
+            .get_tree()
+            .root_iter()
+            .traverse(tree::TraversalType::Level)
+            .skip(1)
+            .map(|n| n.content().clone())
+            .collect()
+    }
+
+    #[test]
+    fn multi_key_binding_waits_for_all_keys() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        type_keys(&mut editor, &mut win, &["a", "^J", "b", "^C"]);
+        let update = type_keys(&mut editor, &mut win, &["d"]);
+        assert_eq!(update.status_msg, "d");
+        assert_eq!(editor.pending_timeout(), Some(Duration::from_millis(1000)));
+        type_keys(&mut editor, &mut win, &["d"]);
+        assert_eq!(contents(&editor), ["a"]);
+        assert_eq!(editor.pending_timeout(), None);
+    }
+
+    #[test]
+    fn ambiguous_binding_resolves_on_timeout() {
+        let (mut editor, mut win) =
+            new_test_editor(Config::parse("[keys.insert]\nexit_insert = ['j', 'jk']"));
+        type_keys(&mut editor, &mut win, &["a", "j"]);
+        assert!(editor.pending_timeout().is_some());
+        assert!(matches!(editor.cursor(), Insert(_)));
+        editor.timeout(&mut win);
+        assert!(matches!(editor.cursor(), Command(_)));
+    }
+
+    #[test]
+    fn unfinished_insert_sequence_is_text() {
+        let (mut editor, mut win) =
+            new_test_editor(Config::parse("[keys.insert]\nexit_insert = 'jk'"));
+        type_keys(&mut editor, &mut win, &["j", "a", "j"]);
+        editor.timeout(&mut win);
+        assert_eq!(contents(&editor), ["jaj"]);
+        type_keys(&mut editor, &mut win, &["j", "k"]);
+        assert_eq!(contents(&editor), ["jaj"]);
+        assert!(matches!(editor.cursor(), Command(_)));
+    }
+
+    fn new_list_editor(items: &[&str]) -> (Editor, TestWindow) {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        if items.is_empty() {
+            type_keys(&mut editor, &mut win, &["^C"]);
+            return (editor, win);
+        }
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                type_keys(&mut editor, &mut win, &["^J"]);
+            }
+            for c in item.chars() {
+                type_keys(&mut editor, &mut win, &[&c.to_string()]);
+            }
+        }
+        type_keys(&mut editor, &mut win, &["^C"]);
+        (editor, win)
+    }
+
+    #[test]
+    fn count_repeats_action() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b", "c", "d"]);
+        type_keys(&mut editor, &mut win, &["k", "k"]);
+        let update = type_keys(&mut editor, &mut win, &["2", "d"]);
+        assert_eq!(update.status_msg, "2d");
+        type_keys(&mut editor, &mut win, &["d"]);
+        assert_eq!(contents(&editor), ["a", "d"]);
+    }
+
+    #[test]
+    fn dot_repeats_delete() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b", "c", "d", "e", "f", "g"]);
+        type_keys(&mut editor, &mut win, &["k", "k", "k", "k", "k", "k"]);
+        type_keys(&mut editor, &mut win, &["d", "d", "."]);
+        assert_eq!(contents(&editor), ["c", "d", "e", "f", "g"]);
+        type_keys(&mut editor, &mut win, &["2", "d", "d", "."]);
+        assert_eq!(contents(&editor), ["g"]);
+    }
+
+    #[test]
+    fn dot_repeats_insert_session_with_count() {
+        let (mut editor, mut win) = new_list_editor(&["a"]);
+        type_keys(&mut editor, &mut win, &["o", "x", "^C", "."]);
+        assert_eq!(contents(&editor), ["a", "x", "x"]);
+        type_keys(&mut editor, &mut win, &["2", "."]);
+        assert_eq!(contents(&editor), ["a", "x", "x", "x", "x"]);
+    }
+
+    #[test]
+    fn dot_without_change() {
+        let (mut editor, mut win) = new_list_editor(&["a"]);
+        let update = type_keys(&mut editor, &mut win, &["."]);
+        assert_eq!(update.status_msg, "no change to repeat");
+    }
+
+    #[test]
+    fn macro_record_and_play() {
+        let (mut editor, mut win) = new_list_editor(&["a"]);
+        let update = type_keys(&mut editor, &mut win, &["q", "a"]);
+        assert_eq!(update.status_msg, "recording @a");
+        let update = type_keys(&mut editor, &mut win, &["o", "x", "^C", "q"]);
+        assert_eq!(update.status_msg, "");
+        assert_eq!(editor.macros.get('a').unwrap(), &["o", "x", "^C"]);
+
+        type_keys(&mut editor, &mut win, &["@", "a"]);
+        assert_eq!(contents(&editor), ["a", "x", "x"]);
+        type_keys(&mut editor, &mut win, &["2", "@", "@"]);
+        assert_eq!(contents(&editor), ["a", "x", "x", "x", "x"]);
+    }
+
+    #[test]
+    fn macro_stops_on_error() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b"]);
+        type_keys(&mut editor, &mut win, &["q", "z", "d", "d", "q"]);
+        type_keys(&mut editor, &mut win, &["o", "y", "^C"]);
+        let update = type_keys(&mut editor, &mut win, &["3", "@", "z"]);
+        assert_eq!(update.status_msg, "cannot delete last node");
+        assert_eq!(contents(&editor), ["a"]);
+    }
+
+    #[test]
+    fn macro_errors() {
+        let (mut editor, mut win) = new_list_editor(&["a"]);
+        let update = type_keys(&mut editor, &mut win, &["@", "@"]);
+        assert_eq!(update.status_msg, "no previous macro");
+        let update = type_keys(&mut editor, &mut win, &["@", "b"]);
+        assert_eq!(update.status_msg, "register b is empty");
+        let update = type_keys(&mut editor, &mut win, &["q", "^J"]);
+        assert_eq!(update.status_msg, "invalid register: ^J");
+    }
+
+    fn active_content(editor: &Editor) -> String {
+        editor.get_tree().get_active_content().clone()
+    }
+
+    fn content_under_cursor(editor: &Editor) -> String {
+        let id = editor.raster.get(editor.cursor.pos()).unwrap().id();
+        editor
+            .get_tree()
+            .root_iter()
+            .traverse(tree::TraversalType::Level)
+            .find(|n| n.id() == id)
+            .map(|n| n.content().clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn marks_survive_edits() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["k", "m", "x", "k", "m", "y"]);
+        type_keys(&mut editor, &mut win, &["O", "n", "e", "w", "^C"]);
+        type_keys(&mut editor, &mut win, &["'", "x"]);
+        assert_eq!(active_content(&editor), "b");
+        assert_eq!(content_under_cursor(&editor), "b");
+
+        type_keys(&mut editor, &mut win, &["'", "y", "d", "d"]);
+        let update = type_keys(&mut editor, &mut win, &["'", "y"]);
+        assert_eq!(update.status_msg, "mark y was on a bullet that has been deleted");
+        let update = type_keys(&mut editor, &mut win, &["'", "q"]);
+        assert_eq!(update.status_msg, "mark q is not set");
+    }
+
+    #[test]
+    fn jumplist_walks_back_and_forth() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["m", "c", "k", "k", "m", "a"]);
+        type_keys(&mut editor, &mut win, &["'", "c"]);
+        assert_eq!(content_under_cursor(&editor), "c");
+        type_keys(&mut editor, &mut win, &["k", "'", "a"]);
+        assert_eq!(content_under_cursor(&editor), "a");
+
+        type_keys(&mut editor, &mut win, &["^O"]);
+        assert_eq!(content_under_cursor(&editor), "b");
+        type_keys(&mut editor, &mut win, &["^O"]);
+        assert_eq!(content_under_cursor(&editor), "a");
+        let update = type_keys(&mut editor, &mut win, &["^O"]);
+        assert_eq!(update.status_msg, "at start of jumplist");
+        type_keys(&mut editor, &mut win, &["^I", "^I"]);
+        assert_eq!(content_under_cursor(&editor), "a");
+        let update = type_keys(&mut editor, &mut win, &["^I"]);
+        assert_eq!(update.status_msg, "at end of jumplist");
+    }
+
+    #[test]
+    fn structural_motions() {
+        // a
+        //   b
+        //     c
+        //   d
+        // e
+        let (mut editor, mut win) = new_list_editor(&[]);
+        #[rustfmt::skip]
+        type_keys(&mut editor, &mut win, &[
+            "i", "a", "^J", "^I", "b", "^J", "^I", "c", "^J", "KEY_BTAB", "d", "^J",
+            "KEY_BTAB", "e", "^C",
+        ]);
+        assert_eq!(contents(&editor), ["a", "e", "b", "d", "c"]);
+        let mut motion = |keys: &[&str]| {
+            let update = type_keys(&mut editor, &mut win, keys);
+            assert_eq!(update.status_msg, "");
+            content_under_cursor(&editor)
+        };
+        assert_eq!(motion(&["g", "g"]), "a");
+        assert_eq!(motion(&["g", "c"]), "b");
+        assert_eq!(motion(&["J"]), "d");
+        assert_eq!(motion(&["K"]), "b");
+        assert_eq!(motion(&["g", "C"]), "c");
+        assert_eq!(motion(&["g", "p"]), "b");
+        assert_eq!(motion(&["G"]), "e");
+        assert_eq!(motion(&["{"]), "a");
+        assert_eq!(motion(&["}"]), "e");
+        assert_eq!(motion(&["k", "{"]), "a");
+        assert_eq!(motion(&["^O"]), "b");
+
+        let update = type_keys(&mut editor, &mut win, &["g", "g", "g", "p"]);
+        assert_eq!(update.status_msg, "bullet has no parent");
+        let update = type_keys(&mut editor, &mut win, &["K"]);
+        assert_eq!(update.status_msg, "no sibling above");
+    }
+
+    #[test]
+    fn unknown_command_key() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        type_keys(&mut editor, &mut win, &["^C"]);
+        let update = type_keys(&mut editor, &mut win, &["g", "z"]);
+        assert_eq!(update.status_msg, "unknown command key: gz");
+    }
+
+    #[test]
+    fn errors_do_not_panic() {
+        let (mut editor, mut win) = new_list_editor(&["ab", ""]);
+        let update = type_keys(&mut editor, &mut win, &["u"]);
+        assert_eq!(update.status_msg, "already at oldest change");
+        let update = type_keys(&mut editor, &mut win, &["k", "w", "w"]);
+        assert_eq!(update.status_msg, "");
+        assert_eq!(content_under_cursor(&editor), "");
+    }
+
+    fn new_nested_editor() -> (Editor, TestWindow) {
+        // ab cd
+        //   b
+        //   c
+        // d
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        #[rustfmt::skip]
+        type_keys(&mut editor, &mut win, &[
+            "a", "b", " ", "c", "d", "^J", "^I", "b", "^J", "c", "^J", "KEY_BTAB", "d", "^C",
+        ]);
+        (editor, win)
+    }
+
+    #[test]
+    fn bullet_text_objects() {
+        let (mut editor, mut win) = new_nested_editor();
+        type_keys(&mut editor, &mut win, &["g", "g", "l", "d", "a", "b"]);
+        assert_eq!(contents(&editor), ["d"]);
+
+        let (mut editor, mut win) = new_nested_editor();
+        type_keys(&mut editor, &mut win, &["g", "g", "c", "i", "b", "x", "^C"]);
+        assert_eq!(contents(&editor), ["x", "d", "b", "c"]);
+
+        let update = type_keys(&mut editor, &mut win, &["G", "d", "i", "t"]);
+        assert_eq!(update.status_msg, "bullet has no children");
+        let update = type_keys(&mut editor, &mut win, &["d", "x"]);
+        assert_eq!(update.status_msg, "unknown text object: x");
+    }
+
+    #[test]
+    fn tree_text_objects() {
+        let (mut editor, mut win) = new_nested_editor();
+        type_keys(&mut editor, &mut win, &["g", "g", "y", "i", "t", "G", "p"]);
+        assert_eq!(contents(&editor), ["ab cd", "d", "b", "c", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["g", "g", "d", "i", "t"]);
+        assert_eq!(contents(&editor), ["ab cd", "d", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["c", "i", "t", "z", "^C"]);
+        assert_eq!(contents(&editor), ["ab cd", "d", "b", "c", "z"]);
+    }
+
+    #[test]
+    fn word_text_objects() {
+        let (mut editor, mut win) = new_list_editor(&["ab cd ef"]);
+        type_keys(&mut editor, &mut win, &["d", "a", "w"]);
+        assert_eq!(contents(&editor), ["ab cd"]);
+        type_keys(&mut editor, &mut win, &["y", "i", "w", "P"]);
+        assert_eq!(contents(&editor), ["ab ccdd"]);
+        type_keys(&mut editor, &mut win, &["b", "b", "d", "i", "w", "."]);
+        assert_eq!(contents(&editor), ["ccdd"]);
+    }
+
+    #[test]
+    fn word_text_objects_in_unicode() {
+        let (mut editor, mut win) = new_list_editor(&["éé ab ç"]);
+        type_keys(&mut editor, &mut win, &["d", "a", "w"]);
+        assert_eq!(contents(&editor), ["éé ab"]);
+        type_keys(&mut editor, &mut win, &["y", "i", "w", "P"]);
+        assert_eq!(contents(&editor), ["éé aabb"]);
+        type_keys(&mut editor, &mut win, &["d", "i", "w"]);
+        assert_eq!(contents(&editor), ["éé "]);
+        type_keys(&mut editor, &mut win, &["c", "i", "w", "ü", "^C"]);
+        assert_eq!(contents(&editor), ["ééü"]);
+
+        let (mut editor, mut win) = new_list_editor(&["é a"]);
+        type_keys(&mut editor, &mut win, &["d", "a", "w", "y", "i", "w", "p"]);
+        assert_eq!(contents(&editor), ["éé"]);
+    }
+
+    #[test]
+    fn visual_mode_applies_operators_to_text_objects() {
+        let (mut editor, mut win) = new_nested_editor();
+        let update = type_keys(&mut editor, &mut win, &["g", "g", "v", "i", "w"]);
+        assert_eq!(update.status_msg, "viw");
+        type_keys(&mut editor, &mut win, &["d"]);
+        assert_eq!(contents(&editor), [" cd", "d", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["v", "i", "b", "c", "x", "^C"]);
+        assert_eq!(contents(&editor), ["x", "d", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["v", "i", "t", "y", "G", "p"]);
+        assert_eq!(contents(&editor), ["x", "d", "b", "c", "b", "c"]);
+
+        // Whole bullets, with their children
+        type_keys(&mut editor, &mut win, &["g", "g", "V", "i", "w", "d"]);
+        assert_eq!(contents(&editor), ["d", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["V", "d"]);
+        assert_eq!(contents(&editor), ["b", "c"]);
+
+        let update = type_keys(&mut editor, &mut win, &["v", "a", "w", "x"]);
+        assert_eq!(update.status_msg, "unknown command key: x");
+        let update = type_keys(&mut editor, &mut win, &["v", "d"]);
+        assert_eq!(update.status_msg, "unknown text object: d");
+        type_keys(&mut editor, &mut win, &["v", "a", "^C", "d", "d"]);
+        assert_eq!(contents(&editor), ["c"]);
+    }
+
+    #[test]
+    fn dot_repeats_text_object() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b", "c"]);
+        type_keys(&mut editor, &mut win, &["g", "g", "d", "a", "b", "."]);
+        assert_eq!(contents(&editor), ["c"]);
+    }
+
+    #[test]
+    fn journal_recovers_edits() {
+        let dir = std::env::temp_dir().join(format!("termflowy-editor-{}", std::process::id()));
+        let (mut editor, mut win) = new_nested_editor();
+        editor.start_journal(&dir).unwrap();
+        type_keys(&mut editor, &mut win, &["g", "g", "y", "a", "b", "G", "p", "d", "d"]);
+        type_keys(&mut editor, &mut win, &["o", "x", "y", "^I", "^C"]);
+
+        let recovery = crate::journal::recover(&dir, Box::new(CounterGen::new())).unwrap();
+        assert_eq!(recovery.lost, 0);
+        assert_eq!(recovery.tree.to_string(), editor.get_tree().to_string());
+        editor.close_journal().unwrap();
+        assert!(!crate::journal::needs_recovery(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn motions_skip_concealed_markup() {
+        let (mut editor, mut win) =
+            new_test_editor(Config::parse("[render]\nconceal_markup = true"));
+        let keys: Vec<String> = "**ab** cd".chars().map(String::from).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        type_keys(&mut editor, &mut win, &keys);
+        type_keys(&mut editor, &mut win, &["^J", "x", "^C", "k"]);
+        let line: String = win.screen[0].iter().collect();
+        assert_eq!(line.trim_end(), "• ab cd");
+        assert_eq!(editor.cursor().pos(), (0, 2));
+
+        type_keys(&mut editor, &mut win, &["w"]);
+        assert_eq!(editor.cursor().pos(), (0, 5));
+        type_keys(&mut editor, &mut win, &["b"]);
+        assert_eq!(editor.cursor().pos(), (0, 2));
+        // Inserting shows the markers again
+        type_keys(&mut editor, &mut win, &["i", "z", "^C"]);
+        let line: String = win.screen[0].iter().collect();
+        assert_eq!(line.trim_end(), "• **zab** cd");
+        assert_eq!(contents(&editor)[0], "**zab** cd");
+    }
+
+    #[test]
+    fn command_line_writes_piped_outline() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        let update = type_keys(&mut editor, &mut win, &["^C", ":", "w", "^J"]);
+        assert_eq!(update.status_msg, "nowhere to write the outline to");
+        editor.pipe_output("\n", Format::Text);
+        type_keys(&mut editor, &mut win, &["i", "a", "^C"]);
+        let update = type_keys(&mut editor, &mut win, &[":", "q", "^J"]);
+        assert_eq!(update.status_msg, "outline has unsaved changes, :q! to discard");
+        assert!(!update.should_quit);
+        assert_eq!(editor.piped_outline(), Some("\n"));
+
+        let update = type_keys(&mut editor, &mut win, &[":", "w", "x", "KEY_BACKSPACE", "q"]);
+        assert_eq!(update.status_msg, ":wq");
+        let update = type_keys(&mut editor, &mut win, &["^J"]);
+        assert!(update.should_quit);
+        assert_eq!(editor.piped_outline(), Some("a\n"));
+    }
+
+    #[test]
+    fn piped_outline_is_kept_unless_discarded() {
+        let text = "- a {id=1}\n  - b {id=2}\n";
+        let (tree, _) = outline::parse(text, Box::new(CounterGen::new())).unwrap();
+        let mut win = TestWindow::new((10, 20), false);
+        let mut editor = Editor::with_tree(&mut win, Config::new(), tree);
+        editor.pipe_output(text, Format::Termflowy);
+        type_keys(&mut editor, &mut win, &["^C"]);
+        let update = run_command(&mut editor, &mut win, "q");
+        assert!(update.should_quit);
+        assert_eq!(editor.piped_outline(), Some(text));
+
+        let (tree, _) = outline::parse(text, Box::new(CounterGen::new())).unwrap();
+        let mut editor = Editor::with_tree(&mut win, Config::new(), tree);
+        editor.pipe_output(text, Format::Termflowy);
+        type_keys(&mut editor, &mut win, &["^C", "d", "d"]);
+        let update = run_command(&mut editor, &mut win, "q!");
+        assert!(update.should_quit);
+        assert_eq!(editor.piped_outline(), None);
+    }
+
+    #[test]
+    fn command_line_errors_and_cancels() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        let update = type_keys(&mut editor, &mut win, &["^C", ":", "e", "^J"]);
+        assert_eq!(update.status_msg, "unknown command: e");
+        let update = type_keys(&mut editor, &mut win, &[":", "q", "^C", "j"]);
+        assert!(!update.should_quit);
+        let update = type_keys(&mut editor, &mut win, &[":", "KEY_BACKSPACE", "i"]);
+        assert_eq!(update.status_msg, "");
+        assert!(matches!(editor.cursor(), Insert(_)));
+    }
+
+    #[test]
+    fn tag_commands_list_and_jump() {
+        let (mut editor, mut win) = new_list_editor(&["#a x", "@b", "y #a", "#c"]);
+        let update = type_keys(&mut editor, &mut win, &[":", "t", "a", "g", "s", "^J"]);
+        assert_eq!(update.status_msg, "4 matches");
+        assert_eq!(
+            screen_lines(&win),
+            ["#a 2", "• #a x", "• y #a", "#c 1", "• #c", "@b 1", "• @b"]
+        );
+        type_keys(&mut editor, &mut win, &["^J"]);
+
+        let jump = [":", "t", "a", "g", " ", "a", "^J"];
+        let update = type_keys(&mut editor, &mut win, &jump);
+        assert_eq!(update.status_msg, "#a 1/2");
+        assert_eq!(content_under_cursor(&editor), "#a x");
+        let update = type_keys(&mut editor, &mut win, &jump);
+        assert_eq!(update.status_msg, "#a 2/2");
+        assert_eq!(content_under_cursor(&editor), "y #a");
+        type_keys(&mut editor, &mut win, &["^O"]);
+        assert_eq!(content_under_cursor(&editor), "#a x");
+
+        let update = type_keys(&mut editor, &mut win, &[":", "t", "a", "g", " ", "d", "^J"]);
+        assert_eq!(update.status_msg, "no bullet is tagged #d");
+    }
+
+    #[test]
+    fn completes_tags_in_insert_mode() {
+        let (mut editor, mut win) = new_list_editor(&["#project #progress @ann", "#pr"]);
+        let update = type_keys(&mut editor, &mut win, &["A", "^N"]);
+        assert_eq!(update.status_msg, "");
+        assert_eq!(contents(&editor)[1], "#pro");
+        let update = type_keys(&mut editor, &mut win, &["^N"]);
+        assert_eq!(update.status_msg, "#progress  #project");
+        type_keys(&mut editor, &mut win, &["j", "^N", " ", "@", "^N"]);
+        assert_eq!(contents(&editor)[1], "#project @ann");
+        let update = type_keys(&mut editor, &mut win, &[" ", "#", "x", "^N"]);
+        assert_eq!(update.status_msg, "no tag starts with #x");
+    }
+
+    /// Types `line` on the command line and runs it
+    fn run_command(editor: &mut Editor, win: &mut TestWindow, line: &str) -> PanelUpdate {
+        let mut keys: Vec<String> = line.chars().map(String::from).collect();
+        keys.insert(0, String::from(":"));
+        keys.push(String::from("^J"));
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        type_keys(editor, win, &keys)
+    }
+
+    fn screen_lines(win: &TestWindow) -> Vec<String> {
+        win.screen
+            .iter()
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn filter_shows_matches_and_edits_tree() {
+        let (mut editor, mut win) = new_list_editor(&["a x", "b", "c", "d X"]);
+        // d under c under b
+        type_keys(&mut editor, &mut win, &[">", ">", "k", ">", ">", "k"]);
+        let update = run_command(&mut editor, &mut win, "filter x");
+        assert_eq!(update.status_msg, "2 matches");
+        assert_eq!(screen_lines(&win), ["• a x", "• b", "  • c", "    • d X"]);
+        // Ancestors of matches are shown, so the cursor stays
+        assert_eq!(content_under_cursor(&editor), "b");
+
+        type_keys(&mut editor, &mut win, &["G", "o", "e", "^C"]);
+        assert_eq!(screen_lines(&win), ["• a x", "• b", "  • c", "    • d X", "    • e"]);
+        assert_eq!(contents(&editor), ["a x", "b", "c", "d X", "e"]);
+
+        type_keys(&mut editor, &mut win, &["k"]);
+        run_command(&mut editor, &mut win, "filter");
+        assert_eq!(screen_lines(&win).len(), 5);
+        assert_eq!(content_under_cursor(&editor), "d X");
+        let update = run_command(&mut editor, &mut win, "filter z");
+        assert_eq!(update.status_msg, "no bullet matches z");
+        let update = run_command(&mut editor, &mut win, "filter is:open");
+        assert_eq!(update.status_msg, "invalid value for is: open");
+
+        // e does not match now that it is not new
+        type_keys(&mut editor, &mut win, &["G"]);
+        run_command(&mut editor, &mut win, "filter x");
+        assert_eq!(content_under_cursor(&editor), "a x");
+    }
+
+    #[test]
+    fn views_that_move_the_cursor_are_jumps() {
+        let (mut editor, mut win) = new_list_editor(&["a #x", "bb", "c #x"]);
+        type_keys(&mut editor, &mut win, &["k"]);
+        run_command(&mut editor, &mut win, "filter tag:x");
+        assert_eq!(content_under_cursor(&editor), "a #x");
+        type_keys(&mut editor, &mut win, &["^J", "^O"]);
+        assert_eq!(content_under_cursor(&editor), "bb");
+        assert_eq!(editor.cursor_mark().unwrap().offset, 1);
+
+        // Staying on the same bullet is not a jump
+        type_keys(&mut editor, &mut win, &["G"]);
+        run_command(&mut editor, &mut win, "tags");
+        type_keys(&mut editor, &mut win, &["^J", "^O"]);
+        assert_eq!(content_under_cursor(&editor), "bb");
+    }
+
+    #[test]
+    fn saved_search_zooms_into_live_results() {
+        let (mut editor, mut win) = new_list_editor(&["a #u", "b", "c #u"]);
+        // c under b
+        type_keys(&mut editor, &mut win, &[">", ">"]);
+        let update = run_command(&mut editor, &mut win, "search u tag:u NOT is:completed");
+        assert_eq!(update.status_msg, "2 matches");
+        assert_eq!(screen_lines(&win), ["u", "• a #u", "• c #u"]);
+        assert_eq!(content_under_cursor(&editor), "c #u");
+
+        // Edits go to the bullet in the tree
+        type_keys(&mut editor, &mut win, &["A", "!", "^C"]);
+        assert_eq!(contents(&editor), ["a #u", "b", "c #u!"]);
+        run_command(&mut editor, &mut win, "search");
+        assert_eq!(screen_lines(&win), ["• a #u", "• b", "  • c #u!"]);
+
+        // Results are found again as the outline changes
+        type_keys(&mut editor, &mut win, &["k", "A", " #u", "^C"]);
+        run_command(&mut editor, &mut win, "search u");
+        assert_eq!(screen_lines(&win), ["u", "• a #u", "• b #u", "• c #u!"]);
+        let update = run_command(&mut editor, &mut win, "searches");
+        assert_eq!(update.status_msg, "3 matches");
+        assert_eq!(
+            screen_lines(&win),
+            ["u: tag:u NOT is:com", "• a #u", "• b #u", "• c #u!"]
+        );
+        assert_eq!(editor.get_tree().saved_search("u").unwrap().query, "tag:u NOT is:completed");
+
+        run_command(&mut editor, &mut win, "delsearch u");
+        let update = run_command(&mut editor, &mut win, "search u");
+        assert_eq!(update.status_msg, "no search is saved as u");
+        let update = run_command(&mut editor, &mut win, "search v is:open");
+        assert_eq!(update.status_msg, "invalid value for is: open");
+        assert!(editor.get_tree().saved_searches().is_empty());
+    }
+
+    #[test]
+    fn status_shows_times_of_active_bullet() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        let clock = FixedClock::new("2026-10-18T09:00:00Z".parse().unwrap());
+        editor.bullet_tree.set_clock(Box::new(clock.clone()));
+        type_keys(&mut editor, &mut win, &["^J"]);
+        clock.set("2026-10-18T10:05:00Z".parse().unwrap());
+        type_keys(&mut editor, &mut win, &["a"]);
+        assert_eq!(
+            editor.bullet_info(),
+            "created 2026-10-18 09:00  modified 2026-10-18 10:05"
+        );
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        // Already Thursday two hours east of UTC
+        let clock = FixedClock::new("2026-10-14T23:00:00Z".parse().unwrap());
+        editor.bullet_tree.set_clock(Box::new(clock.with_offset(2 * 3600)));
+        type_keys(&mut editor, &mut win, &["^J", "a", " ", "t", "o", "d", "a", "y", "^C"]);
+        assert_eq!(contents(&editor), ["", "a 2026-10-15"]);
+        assert_eq!(
+            editor.bullet_info(),
+            "created 2026-10-15 01:00  modified 2026-10-15 01:00"
+        );
+        run_command(&mut editor, &mut win, "agenda");
+        assert_eq!(screen_lines(&win), ["Today", "• a 2026-10-15"]);
+    }
+
+    #[test]
+    fn agenda_lists_due_bullets_by_day() {
+        let (mut editor, mut win) = new_test_editor(Config::new());
+        // A Wednesday
+        let clock = FixedClock::new("2026-10-14T09:00:00Z".parse().unwrap());
+        editor.bullet_tree.set_clock(Box::new(clock.clone()));
+        #[rustfmt::skip]
+        type_keys(&mut editor, &mut win, &[
+            "w", "o", "r", "k", "^J", "^I", "r", "e", "n", "t", " ", "2", "0", "2", "6", "-", "1",
+            "0", "-", "1", "0", "^J", "c", "a", "l", "l", " ", "t", "o", "d", "a", "y", "^J",
+            "KEY_BTAB", "g", "o", " ", "d", "u", "e", ":", "f", "r", "i", "^J", "l",
+            "a", "t", "e", "r", " ", "2", "0", "2", "6", "-", "1", "2", "-", "0", "1", "^C",
+        ]);
+        let update = run_command(&mut editor, &mut win, "agenda");
+        assert_eq!(update.status_msg, "3 matches");
+        assert_eq!(
+            screen_lines(&win),
+            [
+                "Overdue",
+                "• rent 2026-10-10",
+                "Today",
+                "• call 2026-10-14",
+                "Fri 2026-10-16",
+                "• go due:2026-10-16",
+            ]
+        );
+        assert_eq!(content_under_cursor(&editor), "rent 2026-10-10");
+
+        // Headings are skipped, and the outline opens on the bullet under the cursor
+        type_keys(&mut editor, &mut win, &["j", "j"]);
+        assert_eq!(content_under_cursor(&editor), "go due:2026-10-16");
+        type_keys(&mut editor, &mut win, &["k", "^J"]);
+        assert_eq!(content_under_cursor(&editor), "call 2026-10-14");
+        assert_eq!(screen_lines(&win)[0], "• work");
+
+        // Dates stay the days they were written on, even after the bullet is moved later
+        clock.set("2026-10-15T09:00:00Z".parse().unwrap());
+        type_keys(&mut editor, &mut win, &["<", "<"]);
+        run_command(&mut editor, &mut win, "agenda");
+        assert_eq!(screen_lines(&win)[1..3], ["• rent 2026-10-10", "• call 2026-10-14"]);
+        type_keys(&mut editor, &mut win, &["^J"]);
+
+        type_keys(&mut editor, &mut win, &["g", "g", "d", "d", "d", "d", "d", "d"]);
+        assert_eq!(contents(&editor), ["later 2026-12-01"]);
+        let update = run_command(&mut editor, &mut win, "agenda");
+        assert_eq!(update.status_msg, "nothing is overdue or due this week");
+    }
+
+    #[test]
+    fn mirror_shows_yanked_bullet() {
+        let (mut editor, mut win) = new_list_editor(&["a", "b", "c"]);
+        // b under a
+        type_keys(&mut editor, &mut win, &["k", ">", ">", "k", "y", "y", "G", "g", "m"]);
+        assert_eq!(screen_lines(&win), ["• a", "  • b", "• c", "◈ a", "  • b"]);
+        assert_eq!(editor.cursor.pos().0, 3);
+
+        // Edits through the mirror show in both places
+        type_keys(&mut editor, &mut win, &["A", "!", "!", "^C", "j", "A", "?", "^C"]);
+        assert_eq!(screen_lines(&win), ["• a!!", "  • b?", "• c", "◈ a!!", "  • b?"]);
+        assert_eq!(editor.cursor.pos().0, 4);
+        assert_eq!(content_under_cursor(&editor), "b?");
+
+        // b would be under a mirror of a, which is under b
+        type_keys(&mut editor, &mut win, &["g", "g", "y", "y", "G"]);
+        let update = type_keys(&mut editor, &mut win, &["g", "m"]);
+        assert_eq!(update.status_msg, "a mirror cannot be inside what it shows");
+        assert_eq!(screen_lines(&win), ["• a!!", "  • b?", "• c", "◈ a!!", "  • b?"]);
+    }
+
+    #[test]
+    fn links_follow_renames() {
+        let (mut editor, mut win) = new_list_editor(&["food", "buy [[FOOD]]"]);
+        // Stored by id once typed, and drawn as the content of the bullet elsewhere
+        assert_eq!(contents(&editor), ["food", "buy [[1]]"]);
+        type_keys(&mut editor, &mut win, &["k", "A", "!", "^C"]);
+        assert_eq!(screen_lines(&win), ["• food!", "• buy [[food!]]"]);
+
+        type_keys(&mut editor, &mut win, &["j", "g", "f"]);
+        assert_eq!(content_under_cursor(&editor), "food!");
+        let update = type_keys(&mut editor, &mut win, &["g", "b"]);
+        assert_eq!(update.status_msg, "1 matches");
+        assert_eq!(screen_lines(&win), ["Links to food!", "• buy [[1]]"]);
+        let update = type_keys(&mut editor, &mut win, &["g", "f"]);
+        assert_eq!(update.status_msg, "no link under cursor");
+
+        type_keys(&mut editor, &mut win, &["^J", "g", "g", "d", "d", "w"]);
+        let update = type_keys(&mut editor, &mut win, &["g", "f"]);
+        assert_eq!(update.status_msg, "no bullet to link to for 1");
+        let update = run_command(&mut editor, &mut win, "backlinks");
+        assert_eq!(update.status_msg, "no bullet links here");
+    }
+
+    #[test]
+    fn sort_keeps_cursor() {
+        let (mut editor, mut win) = new_list_editor(&["list", "b10", "b9", "A"]);
+        #[rustfmt::skip]
+        type_keys(&mut editor, &mut win, &[
+            "k", "k", ">", ">", "j", ">", ">", "j", ">", ">", "g", "g", "l", "g", "s",
+        ]);
+        assert_eq!(screen_lines(&win), ["• list", "  • A", "  • b9", "  • b10"]);
+        assert_eq!(editor.cursor.pos(), (0, 3));
+
+        run_command(&mut editor, &mut win, "sort! alpha");
+        assert_eq!(screen_lines(&win), ["• list", "  • b9", "  • b10", "  • A"]);
+        assert_eq!(editor.cursor.pos(), (0, 3));
+        type_keys(&mut editor, &mut win, &["G", "u"]);
+        assert_eq!(screen_lines(&win), ["• list", "  • A", "  • b9", "  • b10"]);
+        assert_eq!(editor.cursor.pos(), (0, 3));
+        type_keys(&mut editor, &mut win, &["u"]);
+        assert_eq!(screen_lines(&win), ["• list", "  • b10", "  • b9", "  • A"]);
+        let update = type_keys(&mut editor, &mut win, &["u"]);
+        assert_eq!(update.status_msg, "already at oldest change");
+        let update = run_command(&mut editor, &mut win, "sort fast");
+        assert_eq!(update.status_msg, "unknown sort: fast");
+        type_keys(&mut editor, &mut win, &["G"]);
+        let update = run_command(&mut editor, &mut win, "sort");
+        assert_eq!(update.status_msg, "bullet has no children");
+    }
+
+    #[test]
+    fn sort_is_undone_after_other_changes() {
+        let (mut editor, mut win) = new_list_editor(&["list", "c", "a", "b"]);
+        #[rustfmt::skip]
+        type_keys(&mut editor, &mut win, &[
+            "k", "k", ">", ">", "j", ">", ">", "j", ">", ">", "g", "g",
+        ]);
+        run_command(&mut editor, &mut win, "sort");
+        assert_eq!(screen_lines(&win), ["• list", "  • a", "  • b", "  • c"]);
+        let update = type_keys(&mut editor, &mut win, &["G", "k", "d", "d"]);
+        assert_eq!(update.status_msg, "");
+        assert_eq!(screen_lines(&win), ["• list", "  • a", "  • c"]);
+        type_keys(&mut editor, &mut win, &["u"]);
+        assert_eq!(screen_lines(&win), ["• list", "  • c", "  • a"]);
+        assert_eq!(content_under_cursor(&editor), "c");
+        let update = type_keys(&mut editor, &mut win, &["u"]);
+        assert_eq!(update.status_msg, "already at oldest change");
+    }
 }
@@ -0,0 +1,292 @@
+use std::fmt::{Display, Formatter};
+
+use crate::raster::PixelState;
+use crate::render::Point;
+use crate::tree::check::Violation;
+use crate::tree::id::Id;
+
+/// Anything that can go wrong while handling a key. None of these are fatal, the editor shows
+/// the message in the status bar and carries on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Tree(TreeError),
+    Raster(RasterError),
+    Editor(EditorError),
+    Journal(JournalError),
+    File(FileError),
+    Query(QueryError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError {
+    NotFound(Id),
+    LastNode,
+    MaxIndent,
+    MinIndent,
+    /// A bullet would be drawn under itself through a mirror
+    MirrorCycle,
+    Inconsistent(Violation),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RasterError {
+    OutOfBounds(Point),
+    /// A move ran into the edge of the screen
+    HitBounds,
+    /// Only moves along a line can go while a condition holds
+    NotLinear,
+    NoText,
+    /// The cursor was on a pixel that does not belong to any text
+    InvalidCursor(Option<PixelState>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditorError {
+    UnknownKey(String),
+    UnknownTextObject(String),
+    ReplayTooDeep,
+    NoParent,
+    NoSiblingAbove,
+    NoSiblingBelow,
+    NoChildren,
+    NoTopLevelAbove,
+    NoTopLevelBelow,
+    NothingToPaste,
+    /// Only yanked bullets can be mirrored
+    NothingToMirror,
+    NoChange,
+    NoPreviousMacro,
+    EmptyRegister(char),
+    InvalidRegister(String),
+    InvalidMark(String),
+    MarkNotSet(char),
+    MarkDeleted(char),
+    JumpListStart,
+    JumpListEnd,
+    JumpTargetDeleted,
+    BackspaceFirstBullet,
+    UnknownCommand(String),
+    /// `:w` when the editor was not started with somewhere to write to
+    NoOutput,
+    UnsavedChanges,
+    UnknownTag(String),
+    NoTags,
+    NoTagBeforeCursor,
+    /// No tag in the outline starts with the one being typed
+    NoTagCompletion(String),
+    NoMatches(String),
+    UnknownSearch(String),
+    NoSearches,
+    /// The agenda would be empty
+    NothingDue,
+    NoLinkUnderCursor,
+    /// No bullet has the id or content that a link goes to
+    BrokenLink(String),
+    NoBacklinks,
+    /// `:sort` with a word that is not a sort key or `recursive`
+    UnknownSort(String),
+    /// No sort is left to undo
+    NothingToUndo,
+}
+
+/// Problems with the recovery journal, see [Journal](crate::journal::Journal)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    Io(String),
+    /// A line of a journal file could not be read. Lines are numbered from 1.
+    Corrupt {
+        file: &'static str,
+        line: usize,
+    },
+}
+
+/// Problems reading or writing an outline file, see [outline](crate::outline)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileError {
+    Io(String),
+    /// The metadata of a bullet has an id that cannot be read. Lines are numbered from 1.
+    InvalidId { line: usize, id: String },
+    InvalidTime { line: usize, time: String },
+}
+
+/// Problems with a search query, see [Query](crate::query::Query)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    Empty,
+    UnexpectedEnd,
+    Unexpected(String),
+    UnclosedQuote,
+    UnknownKey(String),
+    InvalidValue { key: String, value: String },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Tree(e) => e.fmt(f),
+            Error::Raster(e) => e.fmt(f),
+            Error::Editor(e) => e.fmt(f),
+            Error::Journal(e) => e.fmt(f),
+            Error::File(e) => e.fmt(f),
+            Error::Query(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Display for TreeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeError::NotFound(id) => write!(f, "could not find bullet {}", id),
+            TreeError::LastNode => write!(f, "cannot delete last node"),
+            TreeError::MaxIndent => write!(f, "already at max indentation level"),
+            TreeError::MinIndent => write!(f, "cannot unindent further"),
+            TreeError::MirrorCycle => write!(f, "a mirror cannot be inside what it shows"),
+            TreeError::Inconsistent(violation) => write!(f, "tree is inconsistent: {}", violation),
+        }
+    }
+}
+
+impl Display for RasterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RasterError::OutOfBounds(pos) => write!(f, "position {:?} is off screen", pos),
+            RasterError::HitBounds => write!(f, "could not browse past bounds"),
+            RasterError::NotLinear => write!(f, "can only browse left or right"),
+            RasterError::NoText => write!(f, "no text on target line"),
+            RasterError::InvalidCursor(state) => write!(f, "cursor is not on text: {:?}", state),
+        }
+    }
+}
+
+impl Display for EditorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditorError::UnknownKey(keys) => write!(f, "unknown command key: {}", keys),
+            EditorError::UnknownTextObject(keys) => write!(f, "unknown text object: {}", keys),
+            EditorError::ReplayTooDeep => write!(f, "replayed keys are nested too deeply"),
+            EditorError::NoParent => write!(f, "bullet has no parent"),
+            EditorError::NoSiblingAbove => write!(f, "no sibling above"),
+            EditorError::NoSiblingBelow => write!(f, "no sibling below"),
+            EditorError::NoChildren => write!(f, "bullet has no children"),
+            EditorError::NoTopLevelAbove => write!(f, "no top level bullet above"),
+            EditorError::NoTopLevelBelow => write!(f, "no top level bullet below"),
+            EditorError::NothingToPaste => write!(f, "nothing to paste"),
+            EditorError::NothingToMirror => write!(f, "no yanked bullet to mirror"),
+            EditorError::NoChange => write!(f, "no change to repeat"),
+            EditorError::NoPreviousMacro => write!(f, "no previous macro"),
+            EditorError::EmptyRegister(register) => write!(f, "register {} is empty", register),
+            EditorError::InvalidRegister(key) => write!(f, "invalid register: {}", key),
+            EditorError::InvalidMark(key) => write!(f, "invalid mark: {}", key),
+            EditorError::MarkNotSet(name) => write!(f, "mark {} is not set", name),
+            EditorError::MarkDeleted(name) => {
+                write!(f, "mark {} was on a bullet that has been deleted", name)
+            }
+            EditorError::JumpListStart => write!(f, "at start of jumplist"),
+            EditorError::JumpListEnd => write!(f, "at end of jumplist"),
+            EditorError::JumpTargetDeleted => write!(f, "jump target was deleted"),
+            EditorError::BackspaceFirstBullet => write!(f, "cannot backspace over first bullet"),
+            EditorError::UnknownCommand(command) => write!(f, "unknown command: {}", command),
+            EditorError::NoOutput => write!(f, "nowhere to write the outline to"),
+            EditorError::UnsavedChanges => write!(f, "outline has unsaved changes, :q! to discard"),
+            EditorError::UnknownTag(tag) => write!(f, "no bullet is tagged {}", tag),
+            EditorError::NoTags => write!(f, "outline has no tags"),
+            EditorError::NoTagBeforeCursor => write!(f, "no tag before cursor"),
+            EditorError::NoTagCompletion(tag) => write!(f, "no tag starts with {}", tag),
+            EditorError::NoMatches(query) => write!(f, "no bullet matches {}", query),
+            EditorError::UnknownSearch(name) => write!(f, "no search is saved as {}", name),
+            EditorError::NoSearches => write!(f, "outline has no saved searches"),
+            EditorError::NothingDue => write!(f, "nothing is overdue or due this week"),
+            EditorError::NoLinkUnderCursor => write!(f, "no link under cursor"),
+            EditorError::BrokenLink(text) => write!(f, "no bullet to link to for {}", text),
+            EditorError::NoBacklinks => write!(f, "no bullet links here"),
+            EditorError::UnknownSort(word) => write!(f, "unknown sort: {}", word),
+            EditorError::NothingToUndo => write!(f, "already at oldest change"),
+        }
+    }
+}
+
+impl Display for JournalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalError::Io(e) => write!(f, "journal: {}", e),
+            JournalError::Corrupt { file, line } => {
+                write!(f, "journal: {} is corrupt at line {}", file, line)
+            }
+        }
+    }
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileError::Io(e) => e.fmt(f),
+            FileError::InvalidId { line, id } => write!(f, "line {}: invalid id {}", line, id),
+            FileError::InvalidTime { line, time } => {
+                write!(f, "line {}: invalid time {}", line, time)
+            }
+        }
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Empty => write!(f, "query is empty"),
+            QueryError::UnexpectedEnd => write!(f, "query ends too early"),
+            QueryError::Unexpected(token) => write!(f, "unexpected {} in query", token),
+            QueryError::UnclosedQuote => write!(f, "unclosed quote in query"),
+            QueryError::UnknownKey(key) => write!(f, "unknown search key: {}", key),
+            QueryError::InvalidValue { key, value } => {
+                write!(f, "invalid value for {}: {}", key, value)
+            }
+        }
+    }
+}
+
+impl From<TreeError> for Error {
+    fn from(e: TreeError) -> Error {
+        Error::Tree(e)
+    }
+}
+
+impl From<RasterError> for Error {
+    fn from(e: RasterError) -> Error {
+        Error::Raster(e)
+    }
+}
+
+impl From<EditorError> for Error {
+    fn from(e: EditorError) -> Error {
+        Error::Editor(e)
+    }
+}
+
+impl From<JournalError> for Error {
+    fn from(e: JournalError) -> Error {
+        Error::Journal(e)
+    }
+}
+
+impl From<std::io::Error> for JournalError {
+    fn from(e: std::io::Error) -> JournalError {
+        JournalError::Io(e.to_string())
+    }
+}
+
+impl From<FileError> for Error {
+    fn from(e: FileError) -> Error {
+        Error::File(e)
+    }
+}
+
+impl From<QueryError> for Error {
+    fn from(e: QueryError) -> Error {
+        Error::Query(e)
+    }
+}
+
+impl From<std::io::Error> for FileError {
+    fn from(e: std::io::Error) -> FileError {
+        FileError::Io(e.to_string())
+    }
+}
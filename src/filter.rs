@@ -0,0 +1,423 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::query::Query;
+use crate::time::{Date, WEEKDAYS};
+use crate::tree::id::Id;
+use crate::tree::{NodeIterator, Tree};
+
+/// A view of the outline that only shows the bullets matching a query, each with its ancestors
+/// for context. The bullets are still edited in the tree itself, and those created while the
+/// filter is on are shown too so that they do not vanish as they are typed.
+///
+/// A filter opened from a [saved search](crate::tree::searches::SavedSearch) is zoomed instead:
+/// it lists the matches under the name of the search without their ancestors or children, and
+/// matches again on every draw so that it follows edits anywhere in the outline. The
+/// [agenda](Filter::agenda) and [backlinks](Filter::backlinks) are zoomed too.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The query as it was typed
+    pub query: String,
+    /// The name of the saved search or view, if the filter is zoomed
+    pub title: Option<String>,
+    matches: Matches,
+    /// Every bullet in the tree when the filter was made
+    known: HashSet<Id>,
+}
+
+#[derive(Clone, Debug)]
+enum Matches {
+    /// Found once when the filter was made
+    Fixed(HashSet<Id>),
+    Live(Query),
+    /// Not completed and due by a week from the day
+    Agenda(Date),
+    /// Found once when the filter was made, under the heading of each group
+    Grouped(Vec<(String, HashSet<Id>)>),
+}
+
+/// A heading of a zoomed filter and the bullets listed under it
+pub struct Section<'a> {
+    pub heading: String,
+    pub bullets: Vec<NodeIterator<'a>>,
+}
+
+impl Filter {
+    pub fn new<F>(query: &str, tree: &Tree, mut is_match: F) -> Filter
+    where
+        F: FnMut(&NodeIterator) -> bool,
+    {
+        let matches = bullets(tree)
+            .filter(|node| is_match(node))
+            .map(|node| node.id())
+            .collect();
+        Filter {
+            query: query.to_string(),
+            title: None,
+            matches: Matches::Fixed(matches),
+            known: bullets(tree).map(|node| node.id()).collect(),
+        }
+    }
+
+    /// A zoomed filter showing the live results of the saved search `name`
+    pub fn zoomed(name: &str, text: &str, query: Query, tree: &Tree) -> Filter {
+        Filter {
+            query: text.to_string(),
+            title: Some(name.to_string()),
+            matches: Matches::Live(query),
+            known: bullets(tree).map(|node| node.id()).collect(),
+        }
+    }
+
+    /// A zoomed filter listing the bullets that are overdue on `today` or due in the week
+    /// from it, by day and with the path of their ancestors
+    pub fn agenda(today: Date, tree: &Tree) -> Filter {
+        Filter {
+            query: "agenda".to_string(),
+            title: Some("agenda".to_string()),
+            matches: Matches::Agenda(today),
+            known: bullets(tree).map(|node| node.id()).collect(),
+        }
+    }
+
+    /// A zoomed filter listing the bullets that link to `id` when it is made
+    pub fn backlinks(id: Id, tree: &Tree) -> Filter {
+        let title = tree.node_iter(id).map_or(String::new(), |n| n.content().trim().to_string());
+        Filter {
+            query: "backlinks".to_string(),
+            title: Some(format!("Links to {}", title)),
+            matches: Matches::Fixed(tree.backlinks(id).into_iter().collect()),
+            known: bullets(tree).map(|node| node.id()).collect(),
+        }
+    }
+
+    /// A zoomed filter listing the bullets with each tag of the outline under the tag and how
+    /// many bullets have it
+    pub fn tags(tree: &mut Tree) -> Filter {
+        let index = tree.tag_index();
+        let groups = index
+            .counts()
+            .map(|(tag, count)| {
+                let ids = index.nodes(tag).collect();
+                (format!("{} {}", tag, count), ids)
+            })
+            .collect();
+        Filter {
+            query: "tags".to_string(),
+            title: Some("tags".to_string()),
+            matches: Matches::Grouped(groups),
+            known: bullets(tree).map(|node| node.id()).collect(),
+        }
+    }
+
+    /// A zoomed filter listing the matches of each saved search when it is made, under its name
+    /// and query
+    pub fn searches(tree: &Tree) -> Filter {
+        let groups = tree
+            .saved_searches()
+            .iter()
+            .map(|search| {
+                let ids = match Query::parse(&search.query) {
+                    Ok(query) => bullets(tree)
+                        .filter(|node| node.mirror_of().is_none() && query.matches(node))
+                        .map(|node| node.id())
+                        .collect(),
+                    Err(_) => HashSet::new(),
+                };
+                (format!("{}: {}", search.name, search.query), ids)
+            })
+            .collect();
+        Filter {
+            query: "searches".to_string(),
+            title: Some("searches".to_string()),
+            matches: Matches::Grouped(groups),
+            known: bullets(tree).map(|node| node.id()).collect(),
+        }
+    }
+
+    pub fn is_zoomed(&self) -> bool {
+        self.title.is_some()
+    }
+
+    /// Whether bullets are drawn with the path of their ancestors
+    pub fn shows_paths(&self) -> bool {
+        matches!(self.matches, Matches::Agenda(_))
+    }
+
+    /// Mirrors never match, so that each bullet is found once, at its original.
+    fn is_match(&self, node: &NodeIterator) -> bool {
+        if node.mirror_of().is_some() {
+            return false;
+        }
+        match &self.matches {
+            Matches::Fixed(ids) => ids.contains(&node.id()),
+            Matches::Live(query) => query.matches(node),
+            Matches::Agenda(today) => {
+                !node.is_completed() && node.due().is_some_and(|due| due <= today.add_days(6))
+            }
+            Matches::Grouped(groups) => groups.iter().any(|(_, ids)| ids.contains(&node.id())),
+        }
+    }
+
+    pub fn match_count(&self, tree: &Tree) -> usize {
+        bullets(tree).filter(|node| self.is_match(node)).count()
+    }
+
+    /// The first matching bullet as drawn that is still in the tree
+    pub fn first_match(&self, tree: &Tree) -> Option<Id> {
+        match self.is_zoomed() {
+            true => self
+                .sections(tree.root_iter(), None)
+                .iter()
+                .flat_map(|s| s.bullets.first())
+                .map(|n| n.id())
+                .next(),
+            false => bullets(tree).find(|node| self.is_match(node)).map(|n| n.id()),
+        }
+    }
+
+    /// What a zoomed filter draws of the tree under `root`: the matching and new bullets, and
+    /// `also` if given, under their headings. A bullet in several groups is listed under each.
+    pub fn sections<'a>(&self, root: NodeIterator<'a>, also: Option<Id>) -> Vec<Section<'a>> {
+        let shown_anyway = |id| !self.known.contains(&id) || also == Some(id);
+        let nodes = root.bullets();
+        let today = match &self.matches {
+            Matches::Agenda(today) => *today,
+            Matches::Grouped(groups) => {
+                let nodes: Vec<NodeIterator> = nodes.collect();
+                let mut sections: Vec<Section> = groups
+                    .iter()
+                    .map(|(heading, ids)| Section {
+                        heading: heading.clone(),
+                        bullets: nodes
+                            .iter()
+                            .filter(|n| n.mirror_of().is_none() && ids.contains(&n.id()))
+                            .copied()
+                            .collect(),
+                    })
+                    .collect();
+                let others: Vec<NodeIterator> = nodes
+                    .into_iter()
+                    .filter(|n| !self.is_match(n) && shown_anyway(n.id()))
+                    .collect();
+                if !others.is_empty() {
+                    sections.push(Section {
+                        heading: "Other".to_string(),
+                        bullets: others,
+                    });
+                }
+                return sections;
+            }
+            _ => {
+                return vec![Section {
+                    heading: self.title.clone().unwrap_or_default(),
+                    bullets: nodes
+                        .filter(|n| self.is_match(n) || shown_anyway(n.id()))
+                        .collect(),
+                }]
+            }
+        };
+        // Overdue bullets come first under None
+        let mut days: BTreeMap<Option<Date>, Vec<NodeIterator>> = BTreeMap::new();
+        let mut not_due = vec![];
+        for node in nodes {
+            if self.is_match(&node) {
+                let day = node.due().filter(|&due| due >= today);
+                days.entry(day).or_default().push(node);
+            } else if shown_anyway(node.id()) {
+                not_due.push(node);
+            }
+        }
+        let mut sections: Vec<Section> = days
+            .into_iter()
+            .map(|(day, bullets)| Section {
+                heading: day_heading(day, today),
+                bullets,
+            })
+            .collect();
+        if !not_due.is_empty() {
+            sections.push(Section {
+                heading: "Not due".to_string(),
+                bullets: not_due,
+            });
+        }
+        sections
+    }
+
+    /// The bullets under `root` to draw: the matching and new ones, `also` if given, and unless
+    /// zoomed the ancestors of all of those.
+    pub fn visible(&self, root: NodeIterator, also: Option<Id>) -> HashSet<Id> {
+        if self.is_zoomed() {
+            let sections = self.sections(root, also);
+            return sections.iter().flat_map(|s| &s.bullets).map(|n| n.id()).collect();
+        }
+        let mut visible = HashSet::new();
+        for child in root.children_iter() {
+            self.collect_visible(child, also, &mut visible);
+        }
+        visible
+    }
+
+    /// Adds the visible bullets of the subtree of `node` and returns whether `node` is one.
+    fn collect_visible(&self, node: NodeIterator, also: Option<Id>, out: &mut HashSet<Id>) -> bool {
+        let id = node.id();
+        let shown = self.is_match(&node) || !self.known.contains(&id) || also == Some(id);
+        let mut is_visible = shown;
+        for child in node.children_iter() {
+            is_visible |= self.collect_visible(child, also, out);
+        }
+        if is_visible {
+            out.insert(id);
+        }
+        is_visible
+    }
+}
+
+/// Like `Overdue`, `Today` or `Tue 2026-10-20`
+fn day_heading(day: Option<Date>, today: Date) -> String {
+    match day {
+        None => "Overdue".to_string(),
+        Some(day) if day == today => "Today".to_string(),
+        Some(day) if day == today.add_days(1) => "Tomorrow".to_string(),
+        Some(day) => format!("{} {}", WEEKDAYS[day.weekday()], day),
+    }
+}
+
+/// Every bullet of `tree` in pre-order
+fn bullets(tree: &Tree) -> impl Iterator<Item = NodeIterator<'_>> {
+    tree.root_iter().bullets()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::FixedClock;
+    use crate::tree::id::CounterGen;
+
+    #[test]
+    fn shows_matches_ancestors_and_new_bullets() {
+        // 1. a
+        //   2. b
+        //     3. match
+        //   4. c
+        // 5. d
+        let mut tree = Tree::new(Box::new(CounterGen::new()));
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap();
+        tree.create_sibling(); // id = 3
+        tree.indent(false).unwrap();
+        tree.get_mut_active_content().push_str("match");
+        tree.activate(Id::from(2)).unwrap();
+        tree.create_sibling(); // id = 4
+        tree.activate(Id::from(1)).unwrap();
+        tree.create_sibling(); // id = 5
+        let filter = Filter::new("match", &tree, |n| n.content() == "match");
+        let ids = |set: HashSet<Id>| {
+            let mut ids: Vec<u128> = set.into_iter().map(u128::from).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(filter.visible(tree.root_iter(), None)), [1, 2, 3]);
+        assert_eq!(
+            ids(filter.visible(tree.root_iter(), Some(Id::from(4)))),
+            [1, 2, 3, 4]
+        );
+
+        tree.create_sibling(); // id = 6
+        assert_eq!(ids(filter.visible(tree.root_iter(), None)), [1, 2, 3, 6]);
+        assert_eq!(filter.first_match(&tree), Some(Id::from(3)));
+    }
+
+    #[test]
+    fn zoomed_filter_follows_edits() {
+        // 1. #a
+        //   2. b
+        //     3. #a
+        let mut tree = Tree::new(Box::new(CounterGen::new()));
+        tree.get_mut_active_content().push_str("#a");
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap();
+        tree.create_sibling(); // id = 3
+        tree.indent(false).unwrap();
+        tree.get_mut_active_content().push_str("#a");
+        let query = Query::parse("tag:a").unwrap();
+        let filter = Filter::zoomed("a", "tag:a", query, &tree);
+        let ids = |filter: &Filter, tree: &Tree| {
+            let mut ids: Vec<u128> = filter
+                .visible(tree.root_iter(), None)
+                .into_iter()
+                .map(u128::from)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&filter, &tree), [1, 3]);
+
+        tree.activate(Id::from(2)).unwrap();
+        tree.get_mut_active_content().push_str("#a");
+        tree.activate(Id::from(1)).unwrap();
+        tree.get_mut_active_content().clear();
+        assert_eq!(ids(&filter, &tree), [2, 3]);
+        assert_eq!(filter.match_count(&tree), 2);
+    }
+
+    #[test]
+    fn agenda_groups_by_day() {
+        let mut tree = Tree::new(Box::new(CounterGen::new()));
+        tree.set_clock(Box::new(FixedClock::new(
+            "2026-10-14T09:00:00Z".parse().unwrap(),
+        )));
+        for content in ["a 2026-10-21", "b tomorrow", "c 2026-10-20", "d 2026-10-01", "e"] {
+            tree.get_mut_active_content().push_str(content);
+            tree.date_active();
+            tree.create_sibling();
+        }
+        tree.get_mut_active_content().push_str("f today");
+        tree.set_completed(true);
+        let today = tree.today();
+        let agenda = Filter::agenda(today, &tree);
+        assert_eq!(agenda.match_count(&tree), 3);
+
+        let sections: Vec<(String, Vec<String>)> = agenda
+            .sections(tree.root_iter(), Some(tree.get_active_id()))
+            .into_iter()
+            .map(|s| {
+                let contents = s.bullets.iter().map(|n| n.content().clone()).collect();
+                (s.heading, contents)
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                ("Overdue".to_string(), vec!["d 2026-10-01".to_string()]),
+                ("Tomorrow".to_string(), vec!["b 2026-10-15".to_string()]),
+                ("Tue 2026-10-20".to_string(), vec!["c 2026-10-20".to_string()]),
+                ("Not due".to_string(), vec!["f today".to_string()]),
+            ]
+        );
+        assert_eq!(agenda.first_match(&tree), Some(Id::from(4)));
+    }
+
+    #[test]
+    fn tags_list_bullets_under_each_tag() {
+        let mut tree = Tree::new(Box::new(CounterGen::new()));
+        for content in ["#a #b", "#b", "c"] {
+            tree.get_mut_active_content().push_str(content);
+            tree.create_sibling();
+        }
+        let tags = Filter::tags(&mut tree);
+        assert_eq!(tags.match_count(&tree), 2);
+        let sections: Vec<(String, Vec<u128>)> = tags
+            .sections(tree.root_iter(), Some(Id::from(3)))
+            .into_iter()
+            .map(|s| (s.heading, s.bullets.iter().map(|n| u128::from(n.id())).collect()))
+            .collect();
+        assert_eq!(
+            sections,
+            [
+                ("#a 1".to_string(), vec![1]),
+                ("#b 2".to_string(), vec![1, 2]),
+                ("Other".to_string(), vec![3]),
+            ]
+        );
+    }
+}
@@ -1,285 +1,955 @@
 /// Invariants:
 /// - Command handlers are always passed cursors which are [browsable](PixelState::is_browsable),
 ///   ecept the handler for <C-c>
-use std::collections::HashMap;
+use std::collections::HashSet;
 
-use crate::editor::{self, Clipboard, Cursor};
+use crate::editor::{Clipboard, Cursor, Mark};
 use crate::editor::{CommandState, HandlerInput, HandlerOutput, InsertState};
-use crate::editor::{Cursor::*, HistoryItem};
+use crate::editor::{Cursor::*, ExCommand, HistoryItem};
+use crate::error::{EditorError, Error, RasterError, TreeError};
+use crate::keymap::Action;
+use crate::markup;
+use crate::query::Query;
 use crate::raster::PixelState::*;
 use crate::raster::{Browser, Direction};
 use crate::render;
-use crate::render::{Point, Window};
+use crate::render::{Point, RenderOptions, Window};
+use crate::textobject::{Range, TextObject};
 use crate::tree::Dir::*;
-use crate::tree::Tree;
-
-const SEPARATORS: [char; 1] = [' '];
-
-pub fn new_command_map() -> HashMap<String, editor::Handler> {
-    let mut map: HashMap<String, editor::Handler> = HashMap::new();
-    map.insert(String::from("i"), command_i);
-    map.insert(String::from("h"), command_hl);
-    map.insert(String::from("l"), command_hl);
-    map.insert(String::from("j"), command_jk);
-    map.insert(String::from("k"), command_jk);
-    map.insert(String::from("b"), command_bwe);
-    map.insert(String::from("w"), command_bwe);
-    map.insert(String::from("e"), command_bwe);
-    map.insert(String::from("A"), command_shift_a);
-    map.insert(String::from("o"), command_o);
-    map.insert(String::from("O"), command_shift_o);
-    map.insert(String::from("d"), command_d);
-    map.insert(String::from("y"), command_y);
-    map.insert(String::from("p"), command_p_shift_p);
-    map.insert(String::from("P"), command_p_shift_p);
-    map.insert(String::from("u"), command_u);
-    map
-}
-
-pub fn new_insert_map() -> HashMap<String, editor::Handler> {
-    let mut map: HashMap<String, editor::Handler> = HashMap::new();
-    map.insert(String::from("^I"), insert_tab);
-    map.insert(String::from("KEY_BTAB"), insert_shift_tab);
-    map.insert(String::from("^J"), insert_enter);
-    map.insert(String::from("KEY_BACKSPACE"), insert_backspace);
-    map.insert(String::from("^?"), insert_backspace);
-    map.insert(String::from("^C"), insert_control_c);
-    // TODO this is temporary
-    map.insert(String::from("^D"), insert_control_c);
-    map.insert(String::from("KEY_LEFT"), insert_arrow_keys);
-    map.insert(String::from("KEY_RIGHT"), insert_arrow_keys);
-    map.insert(String::from("KEY_UP"), insert_arrow_keys);
-    map.insert(String::from("KEY_DOWN"), insert_arrow_keys);
-    map
-}
-
-pub fn command_i(p: HandlerInput) -> Result<HandlerOutput, String> {
-    let cursor = p.cursor.command_state();
-    let (id, offset) = match p.raster.get(cursor.pos).unwrap() {
-        Text { id, offset } => (id, offset),
-        Placeholder(id) => (id, 0),
-        err => panic!(
-            "handler should only be passed browsable pixel states but got: {:?}",
-            err
-        ),
-    };
+use crate::tree::check::Violation;
+use crate::tree::id::Id;
+use crate::tree::sort::{Sort, SortKey};
+use crate::tree::{Dir, NodeIterator, Subtree, TraversalType, Tree};
+
+pub const SEPARATORS: [char; 1] = [' '];
+
+/// Command mode actions. The names are stable since they are used by the config file.
+pub const COMMAND_ACTIONS: &[Action] = &[
+    Action::new("insert", command_i, &["i"]).change(),
+    Action::new("left", command_h, &["h"]),
+    Action::new("right", command_l, &["l"]),
+    Action::new("down", command_j, &["j"]),
+    Action::new("up", command_k, &["k"]),
+    Action::new("word_back", command_b, &["b"]),
+    Action::new("word_forward", command_w, &["w"]),
+    Action::new("word_end", command_e, &["e"]),
+    Action::new("parent", command_parent, &["gp"]),
+    Action::new("next_sibling", command_next_sibling, &["J"]),
+    Action::new("previous_sibling", command_previous_sibling, &["K"]),
+    Action::new("first_child", command_first_child, &["gc"]),
+    Action::new("last_child", command_last_child, &["gC"]),
+    Action::new("document_start", command_gg, &["gg"]),
+    Action::new("document_end", command_shift_g, &["G"]),
+    Action::new("next_top_level", command_right_brace, &["}"]),
+    Action::new("previous_top_level", command_left_brace, &["{"]),
+    Action::new("append_end", command_shift_a, &["A"]).change(),
+    Action::new("open_below", command_o, &["o"]).change(),
+    Action::new("open_above", command_shift_o, &["O"]).change(),
+    Action::new("indent", command_indent, &[">>"]).change(),
+    Action::new("unindent", command_unindent, &["<lt><lt>"]).change(),
+    Action::new("delete_subtree", command_dd, &["dd"]).change(),
+    Action::new("yank_subtree", command_yy, &["yy"]),
+    Action::new("delete", command_d, &["d"]).change(),
+    Action::new("change", command_c, &["c"]).change(),
+    Action::new("yank", command_y, &["y"]),
+    Action::new("visual", command_v, &["v"]).change(),
+    Action::new("visual_bullets", command_shift_v, &["V"]).change(),
+    Action::new("paste_below", command_p, &["p"]).change(),
+    Action::new("paste_above", command_shift_p, &["P"]).change(),
+    Action::new("mirror_below", command_gm, &["gm"]).change(),
+    Action::new("mirror_above", command_g_shift_m, &["gM"]).change(),
+    Action::new("undo", command_u, &["u"]),
+    Action::new("repeat_change", command_dot, &["."]).takes_count(),
+    Action::new("record_macro", command_q, &["q"]),
+    Action::new("play_macro", command_at, &["@"]).takes_count(),
+    Action::new("set_mark", command_m, &["m"]),
+    Action::new("go_to_mark", command_quote, &["'"]),
+    Action::new("jump_back", command_control_o, &["<C-o>"]),
+    Action::new("jump_forward", command_control_i, &["<C-i>"]),
+    Action::new("command_line", command_colon, &[":"]),
+    Action::new("open_in_outline", command_enter, &["<CR>"]),
+    Action::new("follow_link", command_gf, &["gf"]),
+    Action::new("backlinks", command_gb, &["gb"]),
+    Action::new("sort_children", command_gs, &["gs"]).change(),
+];
+
+/// Insert mode actions. Keys without a binding are inserted as text.
+pub const INSERT_ACTIONS: &[Action] = &[
+    Action::new("indent", insert_tab, &["<Tab>"]),
+    Action::new("unindent", insert_shift_tab, &["<S-Tab>"]),
+    Action::new("new_bullet", insert_enter, &["<CR>"]),
+    Action::new("backspace", insert_backspace, &["<BS>", "<C-?>"]),
+    Action::new("complete_tag", insert_complete_tag, &["<C-n>"]),
+    // TODO <C-d> is temporary
+    Action::new("exit_insert", insert_control_c, &["<C-c>", "<C-d>"]),
+    Action::new(
+        "arrow_key",
+        insert_arrow_keys,
+        &["<Left>", "<Right>", "<Up>", "<Down>"],
+    ),
+];
+
+pub fn command_i(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, offset) = text_under_cursor(&p)?;
     p.tree.activate(id)?;
-    Ok(HandlerOutput::new().set_cursor(Insert(InsertState {
-        pos: cursor.pos,
-        offset: p.tree.get_active_content().len() - offset,
-    })))
+    // Rendered again since concealed markup is shown on the active bullet
+    let offset = p.tree.get_active_content().len() - offset;
+    render_and_make_insert_output(p.tree, p.win, p.render_options, offset)
 }
 
-pub fn command_hl(p: HandlerInput) -> Result<HandlerOutput, String> {
-    let direction = match p.key {
-        "h" => Direction::Left,
-        _ => Direction::Right,
-    };
+pub fn command_h(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_hl(p, Direction::Left)
+}
+
+pub fn command_l(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_hl(p, Direction::Right)
+}
+
+fn command_hl(p: HandlerInput, direction: Direction) -> Result<HandlerOutput, Error> {
     let pos = p
         .raster
-        .browser(p.cursor.command_state().pos)
-        .expect("")
+        .browser(p.cursor.command_state().pos)?
         .go_while(direction, |state| !state.is_browsable())?
         .pos();
     Ok(HandlerOutput::new().set_cursor(Cursor::new_command(pos)))
 }
 
-pub fn command_jk(p: HandlerInput) -> Result<HandlerOutput, String> {
-    let direction = match p.key {
-        "j" => Direction::Down,
-        _ => Direction::Up,
-    };
+pub fn command_j(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_jk(p, Direction::Down)
+}
+
+pub fn command_k(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_jk(p, Direction::Up)
+}
+
+/// Lines without text, like the headings of a zoomed view, are skipped.
+fn command_jk(p: HandlerInput, direction: Direction) -> Result<HandlerOutput, Error> {
     let cursor = p.cursor.command_state();
-    let pos = p
-        .raster
-        .browser(cursor.pos)
-        .expect("")
-        .go_no_wrap(direction, 1)?
-        .go_no_wrap(
-            Direction::Right,
-            (cursor.col as u32)
-                .checked_sub(cursor.pos.1 as u32)
-                .expect("y pos should never be bigger than col"),
-        )?
-        .map(|b| find_left_text(b, cursor.pos.1 as u32))?;
+    let mut line = p.raster.browser(cursor.pos)?.go_no_wrap(direction, 1)?;
+    let pos = loop {
+        let found = line
+            .clone()
+            .go_no_wrap(
+                Direction::Right,
+                (cursor.col as u32).saturating_sub(cursor.pos.1 as u32),
+            )?
+            .map(|b| find_left_text(b, cursor.pos.1 as u32));
+        match found {
+            Err(RasterError::NoText) if !p.raster.row_is_browsable(line.pos().0) => {
+                line = line
+                    .go_no_wrap(direction, 1)
+                    .map_err(|_| RasterError::NoText)?;
+            }
+            found => break found?,
+        }
+    };
     Ok(HandlerOutput::new().set_cursor(Cursor::new_command(pos)))
 }
 
-pub fn command_bwe(p: HandlerInput) -> Result<HandlerOutput, String> {
+pub fn command_b(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_bwe(p, 'b')
+}
+
+pub fn command_w(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_bwe(p, 'w')
+}
+
+pub fn command_e(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_bwe(p, 'e')
+}
+
+fn command_bwe(p: HandlerInput, motion: char) -> Result<HandlerOutput, Error> {
     let cursor = p.cursor.command_state();
-    match p.raster.get(cursor.pos) {
-        Some(Text { id, .. }) => p.tree.activate(id)?,
-        err => return Err(format!("invalid pixel state: {:?}", err)),
+    let (id, offset) = match p.raster.get(cursor.pos) {
+        Some(Text { id, offset }) => (id, offset),
+        state => return Err(RasterError::InvalidCursor(state).into()),
     };
-    if let Some(Text { id, .. }) = p.raster.get(cursor.pos) {
-        p.tree.activate(id)?;
-    } else {
-        return Err(String::from("invalid pixel state"));
-    }
-    let content_len = p.tree.get_active_content().len();
-    let (dir, final_offset, skip_index) = match p.key {
-        "b" => (Direction::Left, 1, 0),
-        "w" => (Direction::Right, 1, content_len - 1),
-        "e" => (Direction::Right, -1, content_len - 1),
-        _ => panic!("check key handler mappings"),
+    p.tree.activate(id)?;
+    let (dir, final_offset) = match motion {
+        'b' => (Direction::Left, 1),
+        'w' => (Direction::Right, 1),
+        'e' => (Direction::Right, -1),
+        _ => panic!("unknown word motion: {}", motion),
     };
+    // The extremities are the first and last characters drawn, since markup can be concealed
+    let (first, last) = p.raster.text_bounds(id).unwrap_or((offset, offset));
+    let skip_index = if motion == 'b' { first } else { last };
     // Go to another bullet if we are on extremities
-    let browser = match p.raster.get(cursor.pos).unwrap() {
-        Text { offset, .. } if offset == skip_index => p
+    let browser = match offset == skip_index {
+        true => p
             .raster
-            .browser(cursor.pos)
-            .unwrap()
+            .browser(cursor.pos)?
             .go_while(dir, |state| !state.is_browsable())?,
-        Text { .. } => p.raster.browser(cursor.pos).unwrap(),
-        state => return Err(format!("invalid command pixel state: {:?}", state)),
+        false => p.raster.browser(cursor.pos)?,
     };
     if let Text { id, offset } = browser.state() {
         p.tree.activate(id)?;
-        let pos = jump_to_next_separator(
-            &p.tree.get_active_content(),
+        let target = word_motion_target(
+            p.tree.get_active_content(),
             offset,
             dir,
             final_offset,
             &SEPARATORS,
-            browser,
-        )?
-        .pos();
+        )?;
+        let pos = p
+            .raster
+            .find_text(id, target, browser.pos())
+            .unwrap_or_else(|| browser.pos());
         Ok(HandlerOutput::new().set_cursor(Cursor::new_command(pos)))
     } else {
-        panic!();
+        // Moved onto an empty bullet
+        Ok(HandlerOutput::new().set_cursor(Cursor::new_command(browser.pos())))
     }
 }
 
-pub fn command_shift_a(p: HandlerInput) -> Result<HandlerOutput, String> {
-    let cursor = p.cursor.command_state();
-    p.tree.activate(p.raster.get(cursor.pos).unwrap().id())?;
-    let pos = p
-        .raster
-        .browser(cursor.pos)
-        .unwrap()
-        .map(|b| match b.state() {
-            Placeholder(_) => b.pos(),
-            _ => b
-                .go_while(Direction::Right, |state| state.is_browsable())
-                .unwrap()
-                .pos(),
-        });
-    Ok(HandlerOutput::new().set_cursor(Cursor::new_insert(pos)))
-}
-
-pub fn command_o(p: HandlerInput) -> Result<HandlerOutput, String> {
-    p.tree
-        .activate(p.raster.get(p.cursor.pos()).unwrap().id())?;
+pub fn command_parent(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |mut itr| match itr.next_parent() {
+        Some(parent) if !parent.is_root() => Ok(parent),
+        _ => Err(EditorError::NoParent.into()),
+    })
+}
+
+pub fn command_next_sibling(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |mut itr| {
+        itr.next_sibling(Below)
+            .ok_or_else(|| EditorError::NoSiblingBelow.into())
+    })
+}
+
+pub fn command_previous_sibling(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |mut itr| {
+        itr.next_sibling(Above)
+            .ok_or_else(|| EditorError::NoSiblingAbove.into())
+    })
+}
+
+pub fn command_first_child(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |itr| {
+        itr.children_iter()
+            .next()
+            .ok_or_else(|| EditorError::NoChildren.into())
+    })
+}
+
+pub fn command_last_child(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |itr| {
+        itr.children_iter()
+            .last()
+            .ok_or_else(|| EditorError::NoChildren.into())
+    })
+}
+
+pub fn command_gg(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let id = match p.tree.root_iter().children_iter().next() {
+        Some(first) => first.id(),
+        None => return Err(TreeError::Inconsistent(Violation::Empty).into()),
+    };
+    Ok(go_to_mark(p, Mark { id, offset: 0 })?.set_jump())
+}
+
+pub fn command_shift_g(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let mut itr = p.tree.root_iter();
+    while let Some(last) = itr.children_iter().last() {
+        itr = last;
+    }
+    let id = itr.id();
+    Ok(go_to_mark(p, Mark { id, offset: 0 })?.set_jump())
+}
+
+pub fn command_right_brace(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |itr| {
+        top_level_ancestor(itr)
+            .next_sibling(Below)
+            .ok_or_else(|| EditorError::NoTopLevelBelow.into())
+    })
+}
+
+/// Goes to the top level bullet containing the cursor, or the one above if already there.
+pub fn command_left_brace(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    structural_motion(p, |itr| {
+        let id = itr.id();
+        let mut top = top_level_ancestor(itr);
+        match top.id() == id {
+            true => top
+                .next_sibling(Above)
+                .ok_or_else(|| EditorError::NoTopLevelAbove.into()),
+            false => Ok(top),
+        }
+    })
+}
+
+fn top_level_ancestor(mut itr: NodeIterator) -> NodeIterator {
+    while let Some(parent) = itr.next_parent() {
+        if parent.is_root() {
+            break;
+        }
+        itr = parent;
+    }
+    itr
+}
+
+/// Moves the command cursor to the start of the bullet that `motion` finds from the bullet
+/// under the cursor.
+fn structural_motion<F>(p: HandlerInput, motion: F) -> Result<HandlerOutput, Error>
+where
+    F: FnOnce(NodeIterator) -> Result<NodeIterator, Error>,
+{
+    let (id, _) = text_under_cursor(&p)?;
+    let target = match p.tree.node_iter(id) {
+        Some(itr) => motion(itr)?.id(),
+        None => return Err(TreeError::NotFound(id).into()),
+    };
+    go_to_mark(p, Mark { id: target, offset: 0 })
+}
+
+pub fn command_shift_a(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, _) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
+    render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
+}
+
+pub fn command_o(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, _) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
     p.tree.create_sibling();
-    render_and_make_insert_output(p.tree, p.win, 0)
+    render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
 }
 
-pub fn command_shift_o(p: HandlerInput) -> Result<HandlerOutput, String> {
-    p.tree
-        .activate(p.raster.get(p.cursor.pos()).unwrap().id())?;
+pub fn command_shift_o(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, _) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
     p.tree.create_sibling_above();
-    render_and_make_insert_output(p.tree, p.win, 0)
+    render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
 }
 
-pub fn command_d(p: HandlerInput) -> Result<HandlerOutput, String> {
+pub fn command_indent(p: HandlerInput) -> Result<HandlerOutput, Error> {
     let cursor = p.cursor.command_state();
-    match p.sticky_key {
-        Some("d") => {
-            let pixel_state = p.raster.get(cursor.pos).unwrap();
-            p.tree.activate(pixel_state.id())?;
-            let subtree = p.tree.get_subtree();
-            p.tree.delete()?; // default active selection matches 'dd'
-            let (raster, pos) =
-                render::tree_render(p.win, p.tree.root_iter(), p.tree.get_active_id(), 0);
-            let pos = find_left_text(raster.browser((pos.0, cursor.col))?, cursor.col as u32)?;
-            Ok(HandlerOutput::new()
-                .set_cursor(Cursor::new_command(pos))
-                .set_clipboard(Clipboard::Tree(subtree.clone()))
-                .set_history_item(HistoryItem::Tree {
-                    tree: subtree,
-                    cursor: p.cursor,
-                })
-                .set_raster(raster))
+    let (id, offset) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
+    p.tree.indent(false)?;
+    render_and_make_command_output(p.tree, p.win, p.render_options, offset, Some(cursor.col))
+}
+
+pub fn command_unindent(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let cursor = p.cursor.command_state();
+    let (id, offset) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
+    p.tree.unindent()?;
+    render_and_make_command_output(p.tree, p.win, p.render_options, offset, Some(cursor.col))
+}
+
+pub fn command_dd(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, _) = text_under_cursor(&p)?;
+    delete_subtree(p, id)
+}
+
+/// Deletes the bullet `id` with its children and puts the cursor on the bullet that became
+/// active, keeping the cursor's column.
+fn delete_subtree(p: HandlerInput, id: Id) -> Result<HandlerOutput, Error> {
+    let cursor = p.cursor.command_state();
+    p.tree.activate(id)?;
+    let subtree = p.tree.get_subtree();
+    p.tree.delete()?; // default active selection matches 'dd'
+    let active = p.tree.get_active_id();
+    let (raster, pos) = render::tree_render(p.win, p.tree.root_iter(), active, 0, p.render_options);
+    let pos = find_left_text(raster.browser((pos.0, cursor.col))?, cursor.col as u32)?;
+    Ok(HandlerOutput::new()
+        .set_cursor(Cursor::new_command(pos))
+        .set_clipboard(Clipboard::Tree(vec![subtree.clone()]))
+        .set_history_item(HistoryItem::Tree {
+            tree: subtree,
+            cursor: p.cursor,
+        })
+        .set_raster(raster))
+}
+
+pub fn command_yy(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, _) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
+    let subtree = p.tree.get_subtree();
+    Ok(HandlerOutput::new().set_clipboard(Clipboard::Tree(vec![subtree])))
+}
+
+#[derive(Copy, Clone)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+pub fn command_d(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_operator(p, Operator::Delete)
+}
+
+pub fn command_c(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_operator(p, Operator::Change)
+}
+
+pub fn command_y(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_operator(p, Operator::Yank)
+}
+
+/// Applies `operator` to the text object typed after it, e.g. `dab` or `cib`.
+fn command_operator(p: HandlerInput, operator: Operator) -> Result<HandlerOutput, Error> {
+    match TextObject::parse(p.args)? {
+        Some(object) => apply_operator(p, object, operator),
+        None => Ok(HandlerOutput::new().await_key()),
+    }
+}
+
+pub fn command_v(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_visual(p, false)
+}
+
+pub fn command_shift_v(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_visual(p, true)
+}
+
+/// Selects the text object typed after `v` and then applies the operator typed after it, e.g.
+/// `viwd`. `V` selects whole bullets: a text object inside a bullet selects the bullet with its
+/// children, and an operator right after `V` applies to the bullet under the cursor. `<C-c>`
+/// leaves without applying anything.
+fn command_visual(p: HandlerInput, whole_bullets: bool) -> Result<HandlerOutput, Error> {
+    let (last, keys) = match p.args.split_last() {
+        Some(split) => split,
+        None => return Ok(HandlerOutput::new().await_key()),
+    };
+    let operator = match last.as_str() {
+        "^C" => return Ok(HandlerOutput::new()),
+        "d" => Some(Operator::Delete),
+        "c" => Some(Operator::Change),
+        "y" => Some(Operator::Yank),
+        _ => None,
+    };
+    let object = match (TextObject::parse(keys), operator) {
+        (Ok(Some(object)), Some(operator)) => Some((object, operator)),
+        (Ok(None), Some(operator)) if keys.is_empty() && whole_bullets => {
+            Some((TextObject::ABullet, operator))
+        }
+        (Ok(Some(_)), None) => return Err(EditorError::UnknownKey(last.clone()).into()),
+        _ => None,
+    };
+    match object {
+        Some((object, operator)) => {
+            let object = match object {
+                TextObject::InnerTree => object,
+                _ if whole_bullets => TextObject::ABullet,
+                _ => object,
+            };
+            apply_operator(p, object, operator)
+        }
+        None => {
+            TextObject::parse(p.args)?;
+            Ok(HandlerOutput::new().await_key())
         }
-        Some(_) => Ok(HandlerOutput::new().set_cursor(p.cursor)),
-        None => Ok(HandlerOutput::new()
-            .set_cursor(p.cursor)
-            .set_sticky_key(String::from("d"))),
     }
 }
 
-pub fn command_y(p: HandlerInput) -> Result<HandlerOutput, String> {
-    let cursor = p.cursor.command_state();
-    match p.sticky_key {
-        Some("y") => {
-            let pixel_state = p.raster.get(cursor.pos).unwrap();
-            p.tree.activate(pixel_state.id())?;
-            let subtree = p.tree.get_subtree();
-            Ok(HandlerOutput::new().set_clipboard(Clipboard::Tree(subtree)))
+fn apply_operator(
+    p: HandlerInput,
+    object: TextObject,
+    operator: Operator,
+) -> Result<HandlerOutput, Error> {
+    let (id, offset) = text_under_cursor(&p)?;
+    match (object.resolve(p.tree, Mark { id, offset })?, operator) {
+        (Range::Text { id, start, end }, operator) => {
+            p.tree.activate(id)?;
+            let text = p.tree.get_active_content()[start..end].to_string();
+            let output = match operator {
+                Operator::Yank => HandlerOutput::new(),
+                Operator::Delete => {
+                    p.tree.get_mut_active_content().replace_range(start..end, "");
+                    let content = p.tree.get_active_content();
+                    let offset = content
+                        .char_indices()
+                        .map(|(i, _)| i)
+                        .take_while(|&i| i <= start)
+                        .last()
+                        .unwrap_or(0);
+                    render_and_make_command_output(p.tree, p.win, p.render_options, offset, None)?
+                }
+                Operator::Change => {
+                    p.tree.get_mut_active_content().replace_range(start..end, "");
+                    let len = p.tree.get_active_content().len();
+                    render_and_make_insert_output(p.tree, p.win, p.render_options, len - start)?
+                }
+            };
+            Ok(output.set_clipboard(Clipboard::Text(text)))
+        }
+        (Range::Subtree(id), Operator::Delete) => delete_subtree(p, id),
+        (Range::Subtree(id), Operator::Change) => {
+            delete_children(p.tree, id)?;
+            p.tree.get_mut_active_content().clear();
+            render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
+        }
+        (Range::Subtree(id), Operator::Yank) => {
+            p.tree.activate(id)?;
+            Ok(HandlerOutput::new().set_clipboard(Clipboard::Tree(vec![p.tree.get_subtree()])))
+        }
+        (Range::Children(id), Operator::Change) => {
+            let children = delete_children(p.tree, id)?;
+            p.tree.create_sibling();
+            p.tree.indent(false)?;
+            let output = render_and_make_insert_output(p.tree, p.win, p.render_options, 0)?;
+            match children.is_empty() {
+                true => Ok(output),
+                false => Ok(output.set_clipboard(Clipboard::Tree(children))),
+            }
+        }
+        (Range::Children(id), operator) => {
+            let children = child_subtrees(p.tree, id)?;
+            if children.is_empty() {
+                return Err(EditorError::NoChildren.into());
+            }
+            let output = match operator {
+                Operator::Yank => HandlerOutput::new(),
+                _ => {
+                    delete_children(p.tree, id)?;
+                    render_and_make_command_output(p.tree, p.win, p.render_options, offset, None)?
+                }
+            };
+            Ok(output.set_clipboard(Clipboard::Tree(children)))
         }
-        Some(_) => Ok(HandlerOutput::new().set_cursor(p.cursor)),
-        None => Ok(HandlerOutput::new()
-            .set_cursor(p.cursor)
-            .set_sticky_key(String::from("y"))),
     }
 }
 
-pub fn command_p_shift_p(p: HandlerInput) -> Result<HandlerOutput, String> {
-    let cursor = p.cursor.command_state();
-    p.tree.activate(p.raster.get(cursor.pos).unwrap().id())?;
-    let dir = match p.key {
-        "p" => Below,
-        "P" => Above,
-        _ => panic!("wrong key passed to handler, check table"),
+fn child_subtrees(tree: &mut Tree, id: Id) -> Result<Vec<Subtree>, TreeError> {
+    let child_ids: Vec<Id> = match tree.node_iter(id) {
+        Some(itr) => itr.children_iter().map(|c| c.id()).collect(),
+        None => return Err(TreeError::NotFound(id)),
     };
+    let mut subtrees = vec![];
+    for child_id in child_ids {
+        tree.activate(child_id)?;
+        subtrees.push(tree.get_subtree());
+    }
+    tree.activate(id)?;
+    Ok(subtrees)
+}
+
+/// Deletes the children of `id`, which is left active, and returns them.
+fn delete_children(tree: &mut Tree, id: Id) -> Result<Vec<Subtree>, TreeError> {
+    let subtrees = child_subtrees(tree, id)?;
+    for subtree in &subtrees {
+        tree.activate(subtree.root_itr().id())?;
+        tree.delete()?;
+    }
+    tree.activate(id)?;
+    Ok(subtrees)
+}
+
+pub fn command_p(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_p_shift_p(p, Below)
+}
+
+pub fn command_shift_p(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_p_shift_p(p, Above)
+}
+
+fn command_p_shift_p(p: HandlerInput, dir: Dir) -> Result<HandlerOutput, Error> {
+    let cursor = p.cursor.command_state();
+    let (id, offset) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
     match p.clipboard {
-        Some(Clipboard::Tree(subtree)) => {
-            p.tree.insert_subtree(subtree.clone(), dir);
+        Some(Clipboard::Tree(subtrees)) => {
+            let mut dir = dir;
+            let mut first = None;
+            for subtree in subtrees {
+                p.tree.insert_subtree(subtree.clone(), dir);
+                first = first.or(Some(p.tree.get_active_id()));
+                dir = Below;
+            }
+            if let Some(first) = first {
+                p.tree.activate(first)?;
+            }
+        }
+        Some(Clipboard::Text(text)) => {
+            let content = p.tree.get_active_content();
+            let at = match (dir, content.get(offset..).and_then(|c| c.chars().next())) {
+                (Below, Some(c)) => offset + c.len_utf8(),
+                (Below, None) => content.len(),
+                (Above, _) => offset,
+            };
+            p.tree.get_mut_active_content().insert_str(at, text);
+            let last = at + text.char_indices().last().map_or(0, |(i, _)| i);
+            return render_and_make_command_output(p.tree, p.win, p.render_options, last, None);
         }
         None => {
-            return Err(String::from("nothing to paste"));
+            return Err(EditorError::NothingToPaste.into());
         }
     };
+    let active = p.tree.get_active_id();
     let (raster, insert_pos) =
-        render::tree_render(p.win, p.tree.root_iter(), p.tree.get_active_id(), 0);
+        render::tree_render(p.win, p.tree.root_iter(), active, 0, p.render_options);
     let pos = (insert_pos.0, cursor.pos.1);
-    let pos = find_left_text(raster.browser(pos).unwrap(), pos.1 as u32)?;
+    let pos = find_left_text(raster.browser(pos)?, pos.1 as u32)?;
     Ok(HandlerOutput::new()
         .set_cursor(Cursor::new_command(pos))
         .set_raster(raster))
 }
 
-pub fn command_u(_p: HandlerInput) -> Result<HandlerOutput, String> {
-    todo!();
-    // match p.history.pop_back() {
-    //     Some(HistoryItem::Tree {
-    //         parent,
-    //         sibling,
-    //         tree,
-    //         cursor: history_cursor,
-    //     }) => {
-    //         match (parent, sibling) {
-    //             (_, Some(sibling)) => {
-    //                 p.tree.activate(sibling)?;
-    //                 p.tree.insert_subtree(tree, Below);
-    //             }
-    //             (parent, None) => {
-    //                 p.tree.activate(parent)?;
-    //                 p.tree.insert_subtree(tree, Below);
-    //                 p.tree.indent(true)?;
-    //             }
-    //         }
-    //         let (raster, _) = render::tree_render(p.win, p.tree.root_iter(), p.tree.get_active_id(), 0);
-    //         Ok(HandlerOutput::new()
-    //             .set_raster(raster)
-    //             .set_cursor(history_cursor))
-    //     }
-    //     Some(HistoryItem::Text { .. }) => todo!(),
-    //     None => return Ok(HandlerOutput::new()),
-    // }
-}
-
-fn find_left_text(b: Browser, col: u32) -> Result<Point, String> {
+pub fn command_gm(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_gm_g_shift_m(p, Below)
+}
+
+pub fn command_g_shift_m(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    command_gm_g_shift_m(p, Above)
+}
+
+/// Puts mirrors of the yanked bullets next to the one under the cursor, like pasting them
+/// except that they stay the same bullets.
+fn command_gm_g_shift_m(p: HandlerInput, dir: Dir) -> Result<HandlerOutput, Error> {
+    let cursor = p.cursor.command_state();
+    let (id, _) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
+    let subtrees = match p.clipboard {
+        Some(Clipboard::Tree(subtrees)) if !subtrees.is_empty() => subtrees,
+        _ => return Err(EditorError::NothingToMirror.into()),
+    };
+    let mut dir = dir;
+    let mut first = None;
+    for subtree in subtrees {
+        // A yanked mirror is mirrored again, which shows the same original
+        let root = subtree.root_itr();
+        p.tree.create_mirror(root.mirror_of().unwrap_or_else(|| root.id()), dir)?;
+        first = first.or(Some(p.tree.get_active_id()));
+        dir = Below;
+    }
+    let first = first.unwrap();
+    p.tree.activate(first)?;
+    let (raster, mirror_pos) =
+        render::tree_render(p.win, p.tree.root_iter(), first, 0, p.render_options);
+    let pos = (mirror_pos.0, cursor.pos.1);
+    let pos = find_left_text(raster.browser(pos)?, pos.1 as u32)?;
+    Ok(HandlerOutput::new()
+        .set_cursor(Cursor::new_command(pos))
+        .set_raster(raster))
+}
+
+/// Repeats the last change. With a count the change is repeated that many times, ignoring the
+/// count it was originally made with.
+pub fn command_dot(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let change = match p.last_change {
+        Some(change) => change,
+        None => return Err(EditorError::NoChange.into()),
+    };
+    let keys = match p.count {
+        Some(count) => repeat_keys(&change.keys, count),
+        None => change.keys_with_count(),
+    };
+    Ok(HandlerOutput::new().set_replay(keys))
+}
+
+/// Starts recording into the register typed next, or stops recording.
+pub fn command_q(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    if p.macros.recording_register().is_some() {
+        p.macros.stop(p.keys.len());
+        return Ok(HandlerOutput::new());
+    }
+    match p.args.first().map(String::as_str) {
+        None => Ok(HandlerOutput::new().await_key()),
+        Some(key) => {
+            p.macros.start(register(key)?);
+            Ok(HandlerOutput::new())
+        }
+    }
+}
+
+/// Plays the register typed next, or the last played one for `@@`.
+pub fn command_at(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let register = match p.args.first().map(String::as_str) {
+        None => return Ok(HandlerOutput::new().await_key()),
+        Some("@") => match p.macros.last_played() {
+            Some(register) => register,
+            None => return Err(EditorError::NoPreviousMacro.into()),
+        },
+        Some(key) => register(key)?,
+    };
+    let keys = match p.macros.get(register) {
+        Some(keys) => repeat_keys(keys, p.count.unwrap_or(1)),
+        None => return Err(EditorError::EmptyRegister(register).into()),
+    };
+    p.macros.set_last_played(register);
+    Ok(HandlerOutput::new().set_replay(keys))
+}
+
+pub fn command_m(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let name = match p.args.first().map(String::as_str) {
+        None => return Ok(HandlerOutput::new().await_key()),
+        Some(key) => mark_name(key)?,
+    };
+    let (id, offset) = text_under_cursor(&p)?;
+    p.marks.set(name, Mark { id, offset });
+    Ok(HandlerOutput::new())
+}
+
+pub fn command_quote(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let name = match p.args.first().map(String::as_str) {
+        None => return Ok(HandlerOutput::new().await_key()),
+        Some(key) => mark_name(key)?,
+    };
+    let mark = match p.marks.get(name) {
+        Some(mark) => mark,
+        None => return Err(EditorError::MarkNotSet(name).into()),
+    };
+    if !p.tree.contains(mark.id) {
+        return Err(EditorError::MarkDeleted(name).into());
+    }
+    Ok(go_to_mark(p, mark)?.set_jump())
+}
+
+pub fn command_control_o(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let current = match p.current_mark {
+        Some(mark) => mark,
+        None => return Err(RasterError::InvalidCursor(p.raster.get(p.cursor.pos())).into()),
+    };
+    match p.marks.jumps.back(current) {
+        Some(mark) => go_to_jump(p, mark),
+        None => Err(EditorError::JumpListStart.into()),
+    }
+}
+
+pub fn command_control_i(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    match p.marks.jumps.forward() {
+        Some(mark) => go_to_jump(p, mark),
+        None => Err(EditorError::JumpListEnd.into()),
+    }
+}
+
+/// Reads a command like `:wq` up to <CR>. <C-c>, or backspacing past the start, cancels it.
+pub fn command_colon(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (key, typed) = match p.args.split_last() {
+        Some((key, typed)) => (key.as_str(), typed),
+        None => return Ok(HandlerOutput::new().await_key()),
+    };
+    match key {
+        "^J" => {}
+        "^C" => return Ok(HandlerOutput::new()),
+        "KEY_BACKSPACE" | "^?" => {
+            let mut typed = typed.to_vec();
+            return match typed.pop() {
+                Some(_) => Ok(HandlerOutput::new().await_key_with(typed)),
+                None => Ok(HandlerOutput::new()),
+            };
+        }
+        // Keys like arrows have long names and are not text
+        _ if key.chars().count() > 1 => {
+            return Ok(HandlerOutput::new().await_key_with(typed.to_vec()))
+        }
+        _ => return Ok(HandlerOutput::new().await_key()),
+    }
+    let line = typed.concat();
+    let command = match line.trim().split_once(' ') {
+        Some(("tag", tag)) => return jump_to_tag(p, tag.trim()),
+        Some(("filter", query)) => ExCommand::Filter(query.trim().to_string()),
+        Some(("search", search)) => match search.trim().split_once(' ') {
+            Some((name, query)) => return save_search(p, name, query.trim()),
+            None => ExCommand::OpenSearch(search.trim().to_string()),
+        },
+        Some(("delsearch", name)) => return delete_search(p, name.trim()),
+        Some((name @ ("sort" | "sort!"), words)) => {
+            let sort = parse_sort(name == "sort!", words)?;
+            return sort_children(p, sort);
+        }
+        _ => match line.trim() {
+            "tags" => return list_tags(p),
+            "searches" => return list_searches(p),
+            "agenda" => ExCommand::Agenda,
+            name @ ("sort" | "sort!") => return sort_children(p, parse_sort(name == "sort!", "")?),
+            "backlinks" => ExCommand::Backlinks,
+            "filter" | "search" => ExCommand::ClearFilter,
+            "w" => ExCommand::Write,
+            "q" => ExCommand::Quit,
+            "q!" => ExCommand::ForceQuit,
+            "wq" | "x" => ExCommand::WriteQuit,
+            other => return Err(EditorError::UnknownCommand(other.to_string()).into()),
+        },
+    };
+    Ok(HandlerOutput::new().set_ex_command(command))
+}
+
+/// Leaves a filter or view like the agenda, keeping the cursor on its bullet.
+pub fn command_enter(_: HandlerInput) -> Result<HandlerOutput, Error> {
+    Ok(HandlerOutput::new().set_ex_command(ExCommand::ClearFilter))
+}
+
+/// Goes to the bullet that the `[[link]]` under the cursor links to.
+pub fn command_gf(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let (id, offset) = text_under_cursor(&p)?;
+    let node = p.tree.node_iter(id).ok_or(TreeError::NotFound(id))?;
+    let content = node.content();
+    // The brackets are part of the link too
+    let text = markup::links(content)
+        .find(|link| link.start - 2 <= offset && offset < link.end + 2)
+        .map(|link| content[link].to_string())
+        .ok_or(EditorError::NoLinkUnderCursor)?;
+    let target = p.tree.link_target(&text).ok_or(EditorError::BrokenLink(text))?;
+    p.tree.activate(target)?;
+    Ok(render_and_make_command_output(p.tree, p.win, p.render_options, 0, None)?.set_jump())
+}
+
+/// Lists the bullets that link to the one under the cursor.
+pub fn command_gb(_: HandlerInput) -> Result<HandlerOutput, Error> {
+    Ok(HandlerOutput::new().set_ex_command(ExCommand::Backlinks))
+}
+
+/// Sorts the children of the bullet under the cursor naturally, see [SortKey::Natural].
+pub fn command_gs(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    sort_children(p, Sort::new(SortKey::Natural))
+}
+
+/// Reads the words after `:sort`, which are a [SortKey] and `recursive`, in any order. The sort
+/// is natural unless a key is given.
+fn parse_sort(reverse: bool, words: &str) -> Result<Sort, EditorError> {
+    let mut sort = Sort {
+        reverse,
+        ..Sort::new(SortKey::Natural)
+    };
+    for word in words.split_whitespace() {
+        match word {
+            "recursive" => sort.recursive = true,
+            key => sort.key = key.parse().map_err(|_| EditorError::UnknownSort(key.to_string()))?,
+        }
+    }
+    Ok(sort)
+}
+
+/// Sorts the children of the bullet under the cursor, which stays on the same character.
+fn sort_children(p: HandlerInput, sort: Sort) -> Result<HandlerOutput, Error> {
+    let cursor = p.cursor;
+    let (id, offset) = text_under_cursor(&p)?;
+    p.tree.activate(id)?;
+    if p.tree.active_iter().children_iter().next().is_none() {
+        return Err(EditorError::NoChildren.into());
+    }
+    let orders = p.tree.sort(sort);
+    let col = cursor.command_state().col;
+    Ok(
+        render_and_make_command_output(p.tree, p.win, p.render_options, offset, Some(col))?
+            .set_history_item(HistoryItem::Order { orders, cursor }),
+    )
+}
+
+/// Lists the bullets with each tag of the outline under the tag and how many bullets have it.
+fn list_tags(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    match p.tree.tag_index().counts().next() {
+        None => Err(EditorError::NoTags.into()),
+        Some(_) => Ok(HandlerOutput::new().set_ex_command(ExCommand::Tags)),
+    }
+}
+
+/// Saves `query` as the search `name` and opens it.
+fn save_search(p: HandlerInput, name: &str, query: &str) -> Result<HandlerOutput, Error> {
+    Query::parse(query)?;
+    p.tree.save_search(name, query);
+    Ok(HandlerOutput::new().set_ex_command(ExCommand::OpenSearch(name.to_string())))
+}
+
+fn delete_search(p: HandlerInput, name: &str) -> Result<HandlerOutput, Error> {
+    match p.tree.delete_search(name) {
+        Some(_) => Ok(HandlerOutput::new().set_message(format!("deleted search {}", name))),
+        None => Err(EditorError::UnknownSearch(name.to_string()).into()),
+    }
+}
+
+/// Lists the matches of each saved search under its name and query.
+fn list_searches(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    match p.tree.saved_searches().is_empty() {
+        true => Err(EditorError::NoSearches.into()),
+        false => Ok(HandlerOutput::new().set_ex_command(ExCommand::Searches)),
+    }
+}
+
+/// Goes to the next bullet with `tag` after the active one, starting over from the top after
+/// the last. Tags without a sigil are taken as `#tags`.
+fn jump_to_tag(p: HandlerInput, tag: &str) -> Result<HandlerOutput, Error> {
+    let tag = match tag.starts_with(&['#', '@'][..]) {
+        true => tag.to_string(),
+        false => format!("#{}", tag),
+    };
+    let tagged: HashSet<Id> = p.tree.tag_index().nodes(&tag).collect();
+    let order: Vec<Id> = p
+        .tree
+        .root_iter()
+        .traverse(TraversalType::PreOrder)
+        .map(|n| n.id())
+        .collect();
+    let at = order.iter().position(|&id| id == p.tree.get_active_id()).unwrap_or(0);
+    let id = *order[at + 1..]
+        .iter()
+        .chain(&order[..=at])
+        .find(|id| tagged.contains(id))
+        .ok_or(EditorError::UnknownTag(tag.clone()))?;
+    let number = order.iter().filter(|i| tagged.contains(i)).position(|&i| i == id).unwrap() + 1;
+    p.tree.activate(id)?;
+    let message = format!("{} {}/{}", tag, number, tagged.len());
+    Ok(render_and_make_command_output(p.tree, p.win, p.render_options, 0, None)?
+        .set_jump()
+        .set_message(message))
+}
+
+fn go_to_jump(p: HandlerInput, mark: Mark) -> Result<HandlerOutput, Error> {
+    if !p.tree.contains(mark.id) {
+        return Err(EditorError::JumpTargetDeleted.into());
+    }
+    go_to_mark(p, mark)
+}
+
+/// Activates the marked bullet and puts a command cursor on the marked offset, or on the last
+/// character if the content got shorter since.
+fn go_to_mark(p: HandlerInput, mark: Mark) -> Result<HandlerOutput, Error> {
+    p.tree.activate(mark.id)?;
+    let len = p.tree.get_active_content().len();
+    let offset = mark.offset.min(len.saturating_sub(1));
+    render_and_make_command_output(p.tree, p.win, p.render_options, offset, None)
+}
+
+fn mark_name(key: &str) -> Result<char, EditorError> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_lowercase() => Ok(c),
+        _ => Err(EditorError::InvalidMark(key.to_string())),
+    }
+}
+
+fn repeat_keys(keys: &[String], count: usize) -> Vec<String> {
+    keys.iter().cloned().cycle().take(keys.len() * count).collect()
+}
+
+fn register(key: &str) -> Result<char, EditorError> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Ok(c),
+        _ => Err(EditorError::InvalidRegister(key.to_string())),
+    }
+}
+
+/// Undoes the latest sort, even if other changes were made after it, by putting the children
+/// back in their order from before. Only sorting can be undone so far.
+pub fn command_u(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let latest = p
+        .history
+        .iter()
+        .rposition(|item| matches!(item, HistoryItem::Order { .. }))
+        .ok_or(EditorError::NothingToUndo)?;
+    let is_last = latest + 1 == p.history.len();
+    let (orders, cursor) = match p.history.remove(latest) {
+        Some(HistoryItem::Order { orders, cursor }) => (orders, cursor),
+        _ => unreachable!(),
+    };
+    for (parent, children) in orders.iter().rev() {
+        p.tree.reorder(*parent, children)?;
+    }
+    // The cursor from before the sort is only still on the same bullet if nothing came after
+    if !is_last {
+        return render_and_make_command_output(p.tree, p.win, p.render_options, 0, None);
+    }
+    let active = p.tree.get_active_id();
+    let (raster, _) = render::tree_render(p.win, p.tree.root_iter(), active, 0, p.render_options);
+    Ok(HandlerOutput::new().set_raster(raster).set_cursor(cursor))
+}
+
+fn find_left_text(b: Browser, col: u32) -> Result<Point, RasterError> {
     if b.state().is_browsable() {
         Ok(b.pos())
     } else {
@@ -288,7 +958,7 @@ fn find_left_text(b: Browser, col: u32) -> Result<Point, String> {
                 if b.state().is_browsable() {
                     Ok(b.pos())
                 } else {
-                    Err(String::from("no text on target line"))
+                    Err(RasterError::NoText)
                 }
             })
     }
@@ -317,125 +987,179 @@ fn find_separator(string: &str, mut index: usize, reverse: bool, sep: &[char]) -
     None
 }
 
-fn jump_to_next_separator<'a>(
+/// The offset in `string` that a word motion from `index` lands on
+fn word_motion_target(
     string: &str,
-    index: usize,
+    mut index: usize,
     dir: Direction,
     final_offset: i32,
     sep: &[char],
-    browser: Browser<'a>,
-) -> Result<Browser<'a>, String> {
+) -> Result<usize, RasterError> {
     let reverse = match dir {
         Direction::Left => true,
         Direction::Right => false,
-        _ => panic!(),
+        _ => return Err(RasterError::NotLinear),
     };
-    let final_index = match find_separator(string, index, reverse, sep) {
-        // Ignore separator if it is right next to current index
-        Some(i) if (i as i32 - index as i32).abs() == 1 => {
-            return jump_to_next_separator(
-                string,
-                i,
-                dir,
-                final_offset,
-                sep,
-                browser.go_wrap(dir, 1)?,
-            );
-        }
-        Some(i) => i as i32 + final_offset,
-        None => {
+    let final_index = loop {
+        match find_separator(string, index, reverse, sep) {
+            // Ignore separator if it is right next to current index
+            Some(i) if (i as i32 - index as i32).abs() == 1 => index = i,
+            Some(i) => break i as i32 + final_offset,
             // Go to extremities if no sep
-            match dir {
-                Direction::Left => 0,
-                Direction::Right => string.len() as i32 - 1,
-                _ => panic!(),
-            }
+            None if reverse => break 0,
+            None => break string.len() as i32 - 1,
         }
     };
-    let final_index = match final_index {
+    Ok(match final_index {
         x if x < 0 => 0,
-        x if x >= string.len() as i32 => string.len().saturating_sub(1) as i32,
-        _ => final_index,
-    };
-    Ok(browser.go_wrap(dir, (final_index - index as i32).abs() as u32)?)
+        x if x >= string.len() as i32 => string.len().saturating_sub(1),
+        x => x as usize,
+    })
 }
 
-pub fn insert_tab(p: HandlerInput) -> Result<HandlerOutput, String> {
+pub fn insert_tab(p: HandlerInput) -> Result<HandlerOutput, Error> {
     p.tree.indent(false)?;
-    render_and_make_insert_output(p.tree, p.win, 0)
+    render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
 }
 
-pub fn insert_shift_tab(p: HandlerInput) -> Result<HandlerOutput, String> {
+pub fn insert_shift_tab(p: HandlerInput) -> Result<HandlerOutput, Error> {
     p.tree.unindent()?;
-    render_and_make_insert_output(p.tree, p.win, 0)
+    render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
 }
 
-pub fn insert_enter(p: HandlerInput) -> Result<HandlerOutput, String> {
+pub fn insert_enter(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    p.tree.link_active_by_id();
+    p.tree.date_active();
     p.tree.create_sibling();
-    render_and_make_insert_output(p.tree, p.win, 0)
+    render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
 }
 
-pub fn insert_backspace(p: HandlerInput) -> Result<HandlerOutput, String> {
+pub fn insert_backspace(p: HandlerInput) -> Result<HandlerOutput, Error> {
     let cursor = p.cursor.insert_state();
     let content_len = p.tree.get_active_content().len();
-    if let Some(remove_index) = content_len
-        .checked_sub(cursor.offset)
-        .expect("offset should not be larger than length of content")
-        .checked_sub(1)
-    {
+    if let Some(remove_index) = content_len.saturating_sub(cursor.offset).checked_sub(1) {
         p.tree.get_mut_active_content().remove(remove_index);
-        render_and_make_insert_output(p.tree, p.win, 0)
+        render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
     } else {
         let mut itr = p.tree.active_iter();
         let new_active = match itr.next_sibling(Above) {
-            Some(id) => id,
+            Some(itr) => itr.id(),
             None => match itr.next_parent() {
-                Some(id) => id,
-                None => return Err(String::from("cannot backspace over first bullet")),
+                Some(itr) => itr.id(),
+                None => return Err(EditorError::BackspaceFirstBullet.into()),
             },
         };
         p.tree.delete()?;
-        p.tree.activate(new_active.id())?;
-        render_and_make_insert_output(p.tree, p.win, 0)
+        p.tree.activate(new_active)?;
+        render_and_make_insert_output(p.tree, p.win, p.render_options, 0)
     }
 }
 
-pub fn insert_control_c(p: HandlerInput) -> Result<HandlerOutput, String> {
+/// Completes the tag before the cursor as far as the tags of the outline that start with it
+/// agree, and lists them if that is not any further.
+pub fn insert_complete_tag(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    let offset = p.cursor.insert_state().offset;
+    let content = p.tree.get_active_content();
+    let end = content.len() - offset;
+    let start = markup::tag_before(content, end).ok_or(EditorError::NoTagBeforeCursor)?;
+    let typed = content[start..end].to_string();
+    // The tag being typed is in the index too
+    let tags: Vec<String> = p
+        .tree
+        .tag_index()
+        .completions(&typed)
+        .filter(|tag| *tag != typed)
+        .map(String::from)
+        .collect();
+    let common = match tags.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.as_str(), |common, tag| {
+            let len: usize = common
+                .chars()
+                .zip(tag.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum();
+            &common[..len]
+        }),
+        None => return Err(EditorError::NoTagCompletion(typed).into()),
+    };
+    if common.len() > typed.len() {
+        let rest = common[typed.len()..].to_string();
+        p.tree.get_mut_active_content().insert_str(end, &rest);
+        return render_and_make_insert_output(p.tree, p.win, p.render_options, offset);
+    }
+    Ok(HandlerOutput::new().set_message(tags.join("  ")))
+}
+
+pub fn insert_control_c(p: HandlerInput) -> Result<HandlerOutput, Error> {
+    // Links typed by content are kept by id from here on, and relative dates as the days they
+    // are today, which changes how the bullet is drawn
+    if p.tree.link_active_by_id() | p.tree.date_active() {
+        let len = p.tree.get_active_content().len();
+        let offset = len.saturating_sub(p.cursor.insert_state().offset);
+        let offset = offset.min(len.saturating_sub(1));
+        return render_and_make_command_output(p.tree, p.win, p.render_options, offset, None);
+    }
     let pos = p.cursor.pos();
-    Ok(
-        HandlerOutput::new().set_cursor(Command(match p.raster.get(pos).unwrap() {
-            state if state.is_browsable() => CommandState { pos, col: pos.1 },
-            Empty => {
-                // We are inserting at end so cursor is one past text
-                let pos = p
-                    .raster
-                    .browser(pos)
-                    .unwrap()
-                    .go_wrap(Direction::Left, 1)?
-                    .map(|b| match b.state() {
-                        Text { .. } => b.pos(),
-                        err => panic!("insert cursor was out of bounds on ctrl-c: {:?}", err),
-                    });
-                CommandState { pos, col: pos.1 }
+    let pos = match p.raster.get(pos) {
+        Some(state) if state.is_browsable() => pos,
+        Some(Empty) => {
+            // We are inserting at end so cursor is one past text
+            let browser = p.raster.browser(pos)?.go_wrap(Direction::Left, 1)?;
+            match browser.state() {
+                Text { .. } => browser.pos(),
+                state => return Err(RasterError::InvalidCursor(Some(state)).into()),
             }
-            _ => panic!("insert cursor was out of bounds on ctrl-c"),
-        })),
-    )
+        }
+        state => return Err(RasterError::InvalidCursor(state).into()),
+    };
+    Ok(HandlerOutput::new().set_cursor(Command(CommandState { pos, col: pos.1 })))
 }
 
-fn insert_arrow_keys(p: HandlerInput) -> Result<HandlerOutput, String> {
+fn insert_arrow_keys(p: HandlerInput) -> Result<HandlerOutput, Error> {
     p.tree
         .get_mut_active_content()
         .push_str(" USE VIM KEYBINDINGS YOU PLEB ");
-    render_and_make_insert_output(p.tree, p.win, p.cursor.insert_state().offset)
+    render_and_make_insert_output(p.tree, p.win, p.render_options, p.cursor.insert_state().offset)
+}
+
+/// Returns the id and content offset of the bullet under a command cursor.
+fn text_under_cursor(p: &HandlerInput) -> Result<(Id, usize), RasterError> {
+    match p.raster.get(p.cursor.pos()) {
+        Some(Text { id, offset }) => Ok((id, offset)),
+        Some(Placeholder(id)) => Ok((id, 0)),
+        state => Err(RasterError::InvalidCursor(state)),
+    }
+}
+
+/// Renders the tree and places a command cursor at `offset` in the content of the active node.
+/// `col` is the column to keep for vertical motions, the cursor's own column if None.
+fn render_and_make_command_output(
+    tree: &mut Tree,
+    win: &mut dyn Window,
+    options: &RenderOptions,
+    offset: usize,
+    col: Option<i32>,
+) -> Result<HandlerOutput, Error> {
+    let insert_offset = tree.get_active_content().len().saturating_sub(offset);
+    let (raster, pos) =
+        render::tree_render(win, tree.root_iter(), tree.get_active_id(), insert_offset, options);
+    Ok(HandlerOutput::new()
+        .set_cursor(Command(CommandState {
+            pos,
+            col: col.unwrap_or(pos.1),
+        }))
+        .set_raster(raster))
 }
 
 fn render_and_make_insert_output(
     tree: &mut Tree,
     win: &mut dyn Window,
+    options: &RenderOptions,
     offset: usize,
-) -> Result<HandlerOutput, String> {
-    let (raster, pos) = render::tree_render(win, tree.root_iter(), tree.get_active_id(), 0);
+) -> Result<HandlerOutput, Error> {
+    let (raster, pos) =
+        render::tree_render(win, tree.root_iter(), tree.get_active_id(), offset, options);
     Ok(HandlerOutput::new()
         .set_cursor(Insert(InsertState { offset, pos }))
         .set_raster(raster))
@@ -0,0 +1,582 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::error::JournalError;
+use crate::tree::check::Violation;
+use crate::tree::id::Id;
+use crate::tree::op::{FlatNode, Op};
+use crate::tree::searches::SavedSearch;
+use crate::tree::sort::Sort;
+use crate::time::Timestamp;
+use crate::tree::{Dir, IdGenerator, Tree};
+
+const SNAPSHOT_FILE: &str = "snapshot";
+const JOURNAL_FILE: &str = "journal";
+/// How many edits are journaled before the tree is written to a new snapshot
+const SNAPSHOT_INTERVAL: usize = 500;
+/// How many tab separated fields a node has in a snapshot or pasted subtree
+const NODE_FIELDS: usize = 7;
+
+/// Keeps the outline safe from crashes. Every edit is appended to a journal file as it happens
+/// and every so often the whole tree is written to a snapshot, which starts a new journal. Both
+/// files are removed on a clean exit, so finding them on start means the last session ended
+/// unexpectedly and its tree can be [recovered](recover).
+///
+/// Snapshots are numbered and a journal names the snapshot it continues, so a crash between
+/// writing a snapshot and clearing the journal does not apply the same edits twice.
+pub struct Journal {
+    dir: PathBuf,
+    file: File,
+    generation: u64,
+    since_snapshot: usize,
+}
+
+/// The tree of a session that did not exit cleanly
+pub struct Recovery {
+    pub tree: Tree,
+    /// How many journaled edits were applied on top of the snapshot
+    pub replayed: usize,
+    /// Edits at the end of the journal that could not be read or applied, like one that was
+    /// only partly written
+    pub lost: usize,
+    /// Problems with the snapshot that were fixed while loading it
+    pub repaired: Vec<Violation>,
+}
+
+impl Journal {
+    /// `$XDG_STATE_HOME/termflowy`, or `~/.local/state/termflowy`
+    pub fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+            .map(|dir| dir.join("termflowy"))
+    }
+
+    /// Starts journaling the edits of `tree`, replacing whatever journal was in `dir`.
+    pub fn start(dir: &Path, tree: &mut Tree) -> Result<Journal, JournalError> {
+        fs::create_dir_all(dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(JOURNAL_FILE))?;
+        let mut journal = Journal {
+            dir: dir.to_path_buf(),
+            file,
+            generation: 0,
+            since_snapshot: 0,
+        };
+        tree.record_ops();
+        journal.snapshot(tree)?;
+        Ok(journal)
+    }
+
+    /// Appends the edits made to `tree` since the last call.
+    pub fn record(&mut self, tree: &mut Tree) -> Result<(), JournalError> {
+        let ops = tree.take_ops();
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for op in &ops {
+            lines.push_str(&encode_op(op));
+            lines.push('\n');
+        }
+        self.file.write_all(lines.as_bytes())?;
+        self.since_snapshot += ops.len();
+        if self.since_snapshot >= SNAPSHOT_INTERVAL {
+            self.snapshot(tree)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the whole tree and starts an empty journal after it.
+    pub fn snapshot(&mut self, tree: &mut Tree) -> Result<(), JournalError> {
+        tree.take_ops();
+        let generation = self.generation + 1;
+        let mut text = format!("snapshot {}\nactive {}\n", generation, tree.get_active_id());
+        for search in tree.saved_searches() {
+            text.push_str(&encode_search(search));
+            text.push('\n');
+        }
+        for node in tree.flatten() {
+            text.push_str(&encode_node(&node));
+            text.push('\n');
+        }
+        let tmp = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        let mut file = File::create(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE))?;
+
+        self.file.set_len(0)?;
+        self.file
+            .write_all(format!("journal {}\n", generation).as_bytes())?;
+        self.file.sync_data()?;
+        self.generation = generation;
+        self.since_snapshot = 0;
+        Ok(())
+    }
+
+    /// Removes the journal, marking the session as cleanly ended.
+    pub fn close(self) -> Result<(), JournalError> {
+        drop(self.file);
+        fs::remove_file(self.dir.join(JOURNAL_FILE))?;
+        fs::remove_file(self.dir.join(SNAPSHOT_FILE))?;
+        Ok(())
+    }
+}
+
+/// Whether a session journaled to `dir` without closing its journal
+pub fn needs_recovery(dir: &Path) -> bool {
+    dir.join(SNAPSHOT_FILE).exists()
+}
+
+/// Rebuilds the tree of the session that journaled to `dir` by loading its last snapshot and
+/// applying the edits journaled after it. New ids come from `generator`.
+pub fn recover(dir: &Path, generator: Box<dyn IdGenerator>) -> Result<Recovery, JournalError> {
+    let snapshot = String::from_utf8_lossy(&fs::read(dir.join(SNAPSHOT_FILE))?).into_owned();
+    let (generation, active, nodes, searches) = parse_snapshot(&snapshot)?;
+    let (mut tree, repaired) = Tree::from_flat(generator, &nodes, active);
+    for search in searches {
+        tree.save_search(&search.name, &search.query);
+    }
+
+    // The journal may end in a partly written line, so it is not required to be valid UTF-8
+    let journal = fs::read(dir.join(JOURNAL_FILE)).unwrap_or_default();
+    let journal = String::from_utf8_lossy(&journal);
+    let mut lines = journal.lines();
+    let mut replayed = 0;
+    let mut lost = 0;
+    if lines.next() == Some(&format!("journal {}", generation)) {
+        let lines: Vec<&str> = lines.collect();
+        for (i, line) in lines.iter().enumerate() {
+            let applied = decode_op(line).map(|op| tree.apply(&op));
+            if applied != Some(Ok(())) {
+                lost = lines.len() - i;
+                break;
+            }
+            replayed += 1;
+        }
+    }
+    Ok(Recovery {
+        tree,
+        replayed,
+        lost,
+        repaired,
+    })
+}
+
+/// The generation, active node, nodes and saved searches of a snapshot
+type Snapshot = (u64, Id, Vec<FlatNode>, Vec<SavedSearch>);
+
+fn parse_snapshot(text: &str) -> Result<Snapshot, JournalError> {
+    let corrupt = |line| JournalError::Corrupt {
+        file: SNAPSHOT_FILE,
+        line,
+    };
+    let mut lines = text.lines();
+    let generation = lines
+        .next()
+        .and_then(|l| l.strip_prefix("snapshot "))
+        .and_then(|g| g.parse().ok())
+        .ok_or_else(|| corrupt(1))?;
+    let active = lines
+        .next()
+        .and_then(|l| l.strip_prefix("active "))
+        .and_then(|a| a.parse().ok())
+        .ok_or_else(|| corrupt(2))?;
+    let mut nodes = vec![];
+    let mut searches = vec![];
+    for (i, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let ["search", name, query] = fields.as_slice() {
+            let search = parse_search(name, query).ok_or_else(|| corrupt(i + 3))?;
+            searches.push(search);
+            continue;
+        }
+        let node = parse_node(&fields);
+        nodes.push(node.ok_or_else(|| corrupt(i + 3))?);
+    }
+    Ok((generation, active, nodes, searches))
+}
+
+/// Reads the fields written by [encode_node]
+fn parse_node(fields: &[&str]) -> Option<FlatNode> {
+    let (depth, id, state, created, modified, mirror, content) = match fields {
+        [depth, id, state, created, modified, mirror, content] => {
+            (depth, id, state, created, modified, mirror, content)
+        }
+        _ => return None,
+    };
+    Some(FlatNode {
+        depth: depth.parse().ok()?,
+        id: id.parse().ok()?,
+        content: unescape(content)?,
+        completed: parse_completed(state)?,
+        created: parse_time(created)?,
+        modified: parse_time(modified)?,
+        mirror: match *mirror {
+            "-" => None,
+            mirror => Some(mirror.parse().ok()?),
+        },
+    })
+}
+
+/// The fields of a node in a snapshot or pasted subtree
+fn encode_node(node: &FlatNode) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        node.depth,
+        node.id,
+        encode_completed(node.completed),
+        encode_time(node.created),
+        encode_time(node.modified),
+        node.mirror.map_or_else(|| "-".to_string(), |id| id.to_string()),
+        escape(&node.content)
+    )
+}
+
+fn encode_time(time: Option<Timestamp>) -> String {
+    time.map_or_else(|| "-".to_string(), |t| t.to_string())
+}
+
+/// Some(None) for a time that was not known
+fn parse_time(time: &str) -> Option<Option<Timestamp>> {
+    match time {
+        "-" => Some(None),
+        time => time.parse().ok().map(Some),
+    }
+}
+
+/// A saved search in a snapshot, or the edit that saved it
+fn encode_search(search: &SavedSearch) -> String {
+    format!("search\t{}\t{}", escape(&search.name), escape(&search.query))
+}
+
+fn parse_search(name: &str, query: &str) -> Option<SavedSearch> {
+    Some(SavedSearch {
+        name: unescape(name)?,
+        query: unescape(query)?,
+    })
+}
+
+fn encode_completed(completed: bool) -> &'static str {
+    match completed {
+        true => "x",
+        false => "-",
+    }
+}
+
+fn parse_completed(state: &str) -> Option<bool> {
+    match state {
+        "x" => Some(true),
+        "-" => Some(false),
+        _ => None,
+    }
+}
+
+/// One line of the journal, with fields separated by tabs
+fn encode_op(op: &Op) -> String {
+    let dir = |dir: &Dir| match dir {
+        Dir::Above => "above",
+        Dir::Below => "below",
+    };
+    match op {
+        Op::CreateSibling {
+            at,
+            dir: d,
+            id,
+            time,
+        } => format!("create\t{}\t{}\t{}\t{}", at, dir(d), id, time),
+        Op::CreateMirror {
+            at,
+            dir: d,
+            id,
+            of,
+            time,
+        } => format!("mirror\t{}\t{}\t{}\t{}\t{}", at, dir(d), id, of, time),
+        Op::InsertSubtree { at, dir: d, nodes } => {
+            let mut line = format!("paste\t{}\t{}", at, dir(d));
+            for node in nodes {
+                line.push('\t');
+                line.push_str(&encode_node(node));
+            }
+            line
+        }
+        Op::Indent { at, first, time } => {
+            let place = if *first { "first" } else { "last" };
+            format!("indent\t{}\t{}\t{}", at, place, time)
+        }
+        Op::Unindent { at, time } => format!("unindent\t{}\t{}", at, time),
+        Op::Delete { at } => format!("delete\t{}", at),
+        Op::Sort { at, sort } => format!(
+            "sort\t{}\t{}\t{}\t{}",
+            at,
+            sort.key,
+            if sort.reverse { "reverse" } else { "forward" },
+            if sort.recursive { "recursive" } else { "children" }
+        ),
+        Op::Reorder { parent, children } => {
+            let mut line = format!("reorder\t{}", parent);
+            for child in children {
+                line.push_str(&format!("\t{}", child));
+            }
+            line
+        }
+        Op::SetContent { id, content, time } => {
+            format!("content\t{}\t{}\t{}", id, time, escape(content))
+        }
+        Op::SetCompleted {
+            id,
+            completed,
+            time,
+        } => format!(
+            "completed\t{}\t{}\t{}",
+            id,
+            encode_completed(*completed),
+            time
+        ),
+        Op::SaveSearch { name, query } => encode_search(&SavedSearch {
+            name: name.clone(),
+            query: query.clone(),
+        }),
+        Op::DeleteSearch { name } => format!("unsearch\t{}", escape(name)),
+    }
+}
+
+fn decode_op(line: &str) -> Option<Op> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let dir = |s: &str| match s {
+        "above" => Some(Dir::Above),
+        "below" => Some(Dir::Below),
+        _ => None,
+    };
+    let op = match fields.as_slice() {
+        ["create", at, d, id, time] => Op::CreateSibling {
+            at: at.parse().ok()?,
+            dir: dir(d)?,
+            id: id.parse().ok()?,
+            time: time.parse().ok()?,
+        },
+        ["mirror", at, d, id, of, time] => Op::CreateMirror {
+            at: at.parse().ok()?,
+            dir: dir(d)?,
+            id: id.parse().ok()?,
+            of: of.parse().ok()?,
+            time: time.parse().ok()?,
+        },
+        ["paste", at, d, nodes @ ..] if nodes.len() % NODE_FIELDS == 0 => Op::InsertSubtree {
+            at: at.parse().ok()?,
+            dir: dir(d)?,
+            nodes: nodes
+                .chunks(NODE_FIELDS)
+                .map(parse_node)
+                .collect::<Option<_>>()?,
+        },
+        ["indent", at, place, time] => Op::Indent {
+            at: at.parse().ok()?,
+            first: match *place {
+                "first" => true,
+                "last" => false,
+                _ => return None,
+            },
+            time: time.parse().ok()?,
+        },
+        ["unindent", at, time] => Op::Unindent {
+            at: at.parse().ok()?,
+            time: time.parse().ok()?,
+        },
+        ["delete", at] => Op::Delete {
+            at: at.parse().ok()?,
+        },
+        ["sort", at, key, order, depth] => Op::Sort {
+            at: at.parse().ok()?,
+            sort: Sort {
+                key: key.parse().ok()?,
+                reverse: match *order {
+                    "reverse" => true,
+                    "forward" => false,
+                    _ => return None,
+                },
+                recursive: match *depth {
+                    "recursive" => true,
+                    "children" => false,
+                    _ => return None,
+                },
+            },
+        },
+        ["reorder", parent, children @ ..] => Op::Reorder {
+            parent: parent.parse().ok()?,
+            children: children
+                .iter()
+                .map(|child| child.parse().ok())
+                .collect::<Option<_>>()?,
+        },
+        ["content", id, time, content] => Op::SetContent {
+            id: id.parse().ok()?,
+            content: unescape(content)?,
+            time: time.parse().ok()?,
+        },
+        ["completed", id, state, time] => Op::SetCompleted {
+            id: id.parse().ok()?,
+            completed: parse_completed(state)?,
+            time: time.parse().ok()?,
+        },
+        ["search", name, query] => {
+            let search = parse_search(name, query)?;
+            Op::SaveSearch {
+                name: search.name,
+                query: search.query,
+            }
+        }
+        ["unsearch", name] => Op::DeleteSearch {
+            name: unescape(name)?,
+        },
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Makes content safe to put in a tab separated line
+fn escape(content: &str) -> String {
+    let mut escaped = String::with_capacity(content.len());
+    for c in content.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(escaped: &str) -> Option<String> {
+    let mut content = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            content.push(c);
+            continue;
+        }
+        content.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::id::CounterGen;
+    use crate::tree::sort::SortKey;
+
+    fn new_gen() -> Box<dyn IdGenerator> {
+        Box::new(CounterGen::new())
+    }
+
+    /// An empty directory for one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("termflowy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn edit(tree: &mut Tree) {
+        tree.get_mut_active_content().push_str("tab\there \\ back");
+        tree.create_sibling();
+        tree.indent(false).unwrap();
+        tree.activate(Id::from(1)).unwrap();
+        let subtree = tree.get_subtree();
+        tree.insert_subtree(subtree, Dir::Above);
+        tree.get_mut_active_content().push_str("pasted");
+        tree.set_completed(true);
+        tree.save_search("todo", "NOT is:completed");
+        tree.create_mirror(Id::from(1), Dir::Below).unwrap();
+        tree.activate(Id::from(1)).unwrap();
+        tree.sort(Sort {
+            key: SortKey::Natural,
+            reverse: true,
+            recursive: true,
+        });
+        tree.reorder(Id::from(1), &[Id::from(2)]).unwrap();
+    }
+
+    #[test]
+    fn op_round_trip() {
+        let mut tree = Tree::new(new_gen());
+        tree.record_ops();
+        edit(&mut tree);
+        tree.activate(Id::from(2)).unwrap();
+        tree.unindent().unwrap();
+        tree.delete().unwrap();
+        tree.delete_search("todo");
+        for op in tree.take_ops() {
+            assert_eq!(decode_op(&encode_op(&op)), Some(op));
+        }
+        assert_eq!(unescape(&escape("a\\b\tc\nd")).unwrap(), "a\\b\tc\nd");
+        assert_eq!(decode_op("content\t1\tbad\\x"), None);
+    }
+
+    #[test]
+    fn recovers_snapshot_and_journal() {
+        let dir = test_dir("recover");
+        let mut tree = Tree::new(new_gen());
+        let mut journal = Journal::start(&dir, &mut tree).unwrap();
+        tree.get_mut_active_content().push_str("before snapshot");
+        tree.create_sibling();
+        tree.save_search("top", "depth:1");
+        journal.record(&mut tree).unwrap();
+        journal.snapshot(&mut tree).unwrap();
+        edit(&mut tree);
+        journal.record(&mut tree).unwrap();
+        assert!(needs_recovery(&dir));
+
+        let recovery = recover(&dir, new_gen()).unwrap();
+        assert_eq!(recovery.tree.to_string(), tree.to_string());
+        assert_eq!(recovery.tree.saved_searches(), tree.saved_searches());
+        assert_eq!(recovery.replayed, 10);
+        assert_eq!(recovery.lost, 0);
+        assert_eq!(recovery.repaired, []);
+
+        journal.close().unwrap();
+        assert!(!needs_recovery(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_torn_and_stale_edits() {
+        let dir = test_dir("torn");
+        let mut tree = Tree::new(new_gen());
+        let mut journal = Journal::start(&dir, &mut tree).unwrap();
+        tree.create_sibling();
+        journal.record(&mut tree).unwrap();
+        let expected = tree.to_string();
+        tree.create_sibling();
+        journal.record(&mut tree).unwrap();
+
+        // The last edit was only partly written
+        let path = dir.join(JOURNAL_FILE);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &text[..text.len() - 3]).unwrap();
+        let recovery = recover(&dir, new_gen()).unwrap();
+        assert_eq!(recovery.tree.to_string(), expected);
+        assert_eq!((recovery.replayed, recovery.lost), (1, 1));
+
+        // A journal left over from before the snapshot is not applied again
+        fs::write(&path, "journal 0\ncreate\t1\tbelow\t9\n").unwrap();
+        let recovery = recover(&dir, new_gen()).unwrap();
+        assert_eq!(recovery.replayed, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
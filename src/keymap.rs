@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+
+use crate::editor::Handler;
+
+/// A handler exposed under a stable name so that it can be bound from the config file.
+pub struct Action {
+    pub name: &'static str,
+    pub handler: Handler,
+    /// Default bindings, written in key notation (see [parse_keys])
+    pub keys: &'static [&'static str],
+    /// Whether the action modifies the tree and can be repeated with `.`
+    pub is_change: bool,
+    /// Whether the handler receives the count prefix instead of being run count times
+    pub takes_count: bool,
+}
+
+impl Action {
+    pub const fn new(name: &'static str, handler: Handler, keys: &'static [&'static str]) -> Action {
+        Action {
+            name,
+            handler,
+            keys,
+            is_change: false,
+            takes_count: false,
+        }
+    }
+
+    pub const fn change(mut self) -> Action {
+        self.is_change = true;
+        self
+    }
+
+    pub const fn takes_count(mut self) -> Action {
+        self.takes_count = true;
+        self
+    }
+}
+
+pub const DEFAULT_LEADER: &str = "\\";
+
+pub enum Lookup {
+    Action(&'static Action),
+    /// The keys are bound but are also the beginning of a longer binding. Which one was meant is
+    /// resolved by the next key or by a timeout.
+    Ambiguous(&'static Action),
+    /// The keys are the beginning of at least one longer binding
+    Prefix,
+    None,
+}
+
+/// Maps sequences of ncurses key names to handlers. Each key descends one level into a trie, so
+/// a sequence like `dd` is declared once instead of being assembled by its handler.
+pub struct KeyMap {
+    root: TrieNode,
+    leader: String,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    action: Option<&'static Action>,
+    children: HashMap<String, TrieNode>,
+}
+
+impl TrieNode {
+    fn insert(&mut self, keys: &[String], action: &'static Action) {
+        match keys.split_first() {
+            Some((key, rest)) => self.children.entry(key.clone()).or_default().insert(rest, action),
+            None => self.action = Some(action),
+        }
+    }
+
+    fn get(&self, keys: &[String]) -> Option<&TrieNode> {
+        match keys.split_first() {
+            Some((key, rest)) => self.children.get(key)?.get(rest),
+            None => Some(self),
+        }
+    }
+
+    /// Removes every binding of the action named `name` and prunes the branches left empty.
+    fn remove_action(&mut self, name: &str) {
+        if matches!(self.action, Some(a) if a.name == name) {
+            self.action = None;
+        }
+        for child in self.children.values_mut() {
+            child.remove_action(name);
+        }
+        self.children
+            .retain(|_, c| c.action.is_some() || !c.children.is_empty());
+    }
+
+    fn collect_keys<'a>(&'a self, name: &str, path: &mut Vec<&'a str>, out: &mut Vec<Vec<String>>) {
+        if matches!(self.action, Some(a) if a.name == name) {
+            out.push(path.iter().map(|k| k.to_string()).collect());
+        }
+        for (key, child) in &self.children {
+            path.push(key);
+            child.collect_keys(name, path, out);
+            path.pop();
+        }
+    }
+}
+
+impl KeyMap {
+    /// Creates a map with the default bindings of every action.
+    pub fn new(actions: &'static [Action], leader: &str) -> KeyMap {
+        let mut map = KeyMap {
+            root: TrieNode::default(),
+            leader: leader.to_string(),
+        };
+        for action in actions {
+            for notation in action.keys {
+                let keys = map.parse(notation).expect("default bindings should be valid");
+                map.root.insert(&keys, action);
+            }
+        }
+        map
+    }
+
+    pub fn lookup(&self, keys: &[String]) -> Lookup {
+        match self.root.get(keys) {
+            Some(TrieNode {
+                action: Some(action),
+                children,
+            }) => match children.is_empty() {
+                true => Lookup::Action(action),
+                false => Lookup::Ambiguous(action),
+            },
+            Some(_) if !keys.is_empty() => Lookup::Prefix,
+            _ => Lookup::None,
+        }
+    }
+
+    /// Finds the longest sequence at the start of `keys` that is bound. Returns the action and
+    /// how many keys it consumes.
+    pub fn longest_match(&self, keys: &[String]) -> Option<(&'static Action, usize)> {
+        let mut node = &self.root;
+        let mut found = None;
+        for (i, key) in keys.iter().enumerate() {
+            node = match node.children.get(key) {
+                Some(node) => node,
+                None => break,
+            };
+            if let Some(action) = node.action {
+                found = Some((action, i + 1));
+            }
+        }
+        found
+    }
+
+    /// Replaces the bindings of every action named in `table`. Values are either a single key
+    /// sequence or a list of them, and an empty string or list unbinds the action. Returns a
+    /// message for every entry that could not be applied.
+    pub fn apply_overrides(&mut self, actions: &'static [Action], table: &toml::Table) -> Vec<String> {
+        let mut errors = vec![];
+        for (name, value) in table {
+            let action = match actions.iter().find(|a| a.name == name) {
+                Some(action) => action,
+                None => {
+                    errors.push(format!("unknown action: {}", name));
+                    continue;
+                }
+            };
+            let notations = match value {
+                toml::Value::String(s) if s.is_empty() => vec![],
+                toml::Value::String(s) => vec![s.as_str()],
+                toml::Value::Array(values) => match values.iter().map(|v| v.as_str()).collect() {
+                    Some(notations) => notations,
+                    None => {
+                        errors.push(format!("keys for {} should be strings", name));
+                        continue;
+                    }
+                },
+                _ => {
+                    errors.push(format!("keys for {} should be a string or a list", name));
+                    continue;
+                }
+            };
+            let sequences: Result<Vec<Vec<String>>, String> =
+                notations.into_iter().map(|n| self.parse(n)).collect();
+            match sequences {
+                Ok(sequences) => {
+                    self.root.remove_action(action.name);
+                    for keys in sequences {
+                        self.root.insert(&keys, action);
+                    }
+                }
+                Err(msg) => errors.push(format!("{}: {}", name, msg)),
+            }
+        }
+        errors
+    }
+
+    pub fn action_keys(&self, name: &str) -> Vec<Vec<String>> {
+        let mut keys = vec![];
+        self.root.collect_keys(name, &mut vec![], &mut keys);
+        keys
+    }
+
+    fn parse(&self, notation: &str) -> Result<Vec<String>, String> {
+        parse_keys(notation, &self.leader)
+    }
+}
+
+/// Parses vim style key notation into a sequence of ncurses key names. Plain characters stand for
+/// themselves and special keys are written in angle brackets, e.g. `dd`, `<C-c>` or `<S-Tab>`.
+/// `<leader>` is replaced by the keys of `leader`.
+pub fn parse_keys(notation: &str, leader: &str) -> Result<Vec<String>, String> {
+    let mut keys = vec![];
+    let mut chars = notation.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(c.to_string());
+            continue;
+        }
+        let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+        if name.eq_ignore_ascii_case("leader") {
+            keys.extend(parse_keys(leader, "")?);
+        } else {
+            keys.push(parse_special_key(&name)?);
+        }
+    }
+    if keys.is_empty() {
+        return Err(String::from("empty key sequence"));
+    }
+    Ok(keys)
+}
+
+fn parse_special_key(name: &str) -> Result<String, String> {
+    let lower = name.to_lowercase();
+    if let Some(c) = lower.strip_prefix("c-") {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(format!("^{}", c.to_ascii_uppercase())),
+            _ => Err(format!("invalid control key: <{}>", name)),
+        };
+    }
+    let key = match lower.as_str() {
+        "tab" => "^I",
+        "s-tab" => "KEY_BTAB",
+        "cr" | "enter" => "^J",
+        "bs" => "KEY_BACKSPACE",
+        "esc" => "^[",
+        "space" => " ",
+        "lt" => "<",
+        "del" => "KEY_DC",
+        "left" => "KEY_LEFT",
+        "right" => "KEY_RIGHT",
+        "up" => "KEY_UP",
+        "down" => "KEY_DOWN",
+        _ => return Err(format!("unknown key: <{}>", name)),
+    };
+    Ok(String::from(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{HandlerInput, HandlerOutput};
+    use crate::error::Error;
+
+    fn noop(_p: HandlerInput) -> Result<HandlerOutput, Error> {
+        Ok(HandlerOutput::new())
+    }
+
+    static ACTIONS: [Action; 4] = [
+        Action::new("first", noop, &["a"]),
+        Action::new("second", noop, &["dd", "<C-x>"]),
+        Action::new("third", noop, &[]),
+        Action::new("fourth", noop, &["g", "<leader>fs"]),
+    ];
+
+    fn keys(notation: &str) -> Vec<String> {
+        parse_keys(notation, " ").unwrap()
+    }
+
+    fn new_map() -> KeyMap {
+        KeyMap::new(&ACTIONS, " ")
+    }
+
+    fn table(s: &str) -> toml::Table {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_keys_test() {
+        assert_eq!(keys("dd"), ["d", "d"]);
+        assert_eq!(keys("<C-c>"), ["^C"]);
+        assert_eq!(keys("<S-Tab>x"), ["KEY_BTAB", "x"]);
+        assert_eq!(keys("<lt><lt>"), ["<", "<"]);
+        assert_eq!(keys("<Leader>x"), [" ", "x"]);
+        assert_eq!(parse_keys("<leader>", "<C-a>,").unwrap(), ["^A", ","]);
+        assert!(parse_keys("", " ").is_err());
+        assert!(parse_keys("<nope>", " ").is_err());
+        assert!(parse_keys("<C-ab>", " ").is_err());
+    }
+
+    #[test]
+    fn lookup_test() {
+        let map = new_map();
+        assert!(matches!(map.lookup(&keys("a")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&keys("d")), Lookup::Prefix));
+        assert!(matches!(map.lookup(&keys("dd")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&keys("x")), Lookup::None));
+        assert!(matches!(map.lookup(&keys("<leader>f")), Lookup::Prefix));
+        assert!(matches!(map.lookup(&keys("<leader>fs")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&[]), Lookup::None));
+    }
+
+    #[test]
+    fn ambiguous_prefix() {
+        let mut map = new_map();
+        map.apply_overrides(&ACTIONS, &table("third = 'gg'"));
+        assert!(matches!(map.lookup(&keys("g")), Lookup::Ambiguous(_)));
+        assert!(matches!(map.lookup(&keys("gg")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&keys("gx")), Lookup::None));
+        assert_eq!(map.longest_match(&keys("gx")).map(|(_, n)| n), Some(1));
+        assert_eq!(map.longest_match(&keys("ggg")).map(|(_, n)| n), Some(2));
+        assert!(map.longest_match(&keys("x")).is_none());
+    }
+
+    #[test]
+    fn overrides_remap_and_unbind() {
+        let mut map = new_map();
+        let errors = map.apply_overrides(
+            &ACTIONS,
+            &table("first = ''\nthird = ['gg', 'x']\nfourth = []"),
+        );
+        assert!(errors.is_empty());
+        assert!(matches!(map.lookup(&keys("a")), Lookup::None));
+        assert!(matches!(map.lookup(&keys("g")), Lookup::Prefix));
+        assert!(matches!(map.lookup(&keys("<leader>")), Lookup::None));
+        assert!(matches!(map.lookup(&keys("gg")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&keys("x")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&keys("dd")), Lookup::Action(_)));
+        assert_eq!(map.action_keys("second").len(), 2);
+    }
+
+    #[test]
+    fn overrides_report_errors() {
+        let mut map = new_map();
+        let errors = map.apply_overrides(&ACTIONS, &table("nope = 'a'\nfirst = 3\nsecond = '<bad>'"));
+        assert_eq!(errors.len(), 3);
+        // Bad entries leave the defaults in place
+        assert!(matches!(map.lookup(&keys("a")), Lookup::Action(_)));
+        assert!(matches!(map.lookup(&keys("dd")), Lookup::Action(_)));
+    }
+}
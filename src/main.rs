@@ -1,17 +1,38 @@
-#![feature(iter_advance_by)]
 #![allow(dead_code)]
-#![allow(clippy::eval_order_dependence)]
+#![allow(clippy::mixed_read_write_in_expression)]
 
-use crate::{render::NCurses, status::render_status};
+use crate::{config::Config, render::NCurses, status::render_status};
 use editor::Editor;
+use journal::{Journal, Recovery};
 use ncurses as n;
-use std::{panic, time::{Duration, Instant}};
+use tree::{check::Violation, id::UlidGen, Tree};
+use std::{
+    env,
+    io::{self, Read, Write},
+    panic,
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
 
+mod cli;
+mod config;
+mod due;
 mod editor;
+mod error;
+mod filter;
 mod handlers;
+mod journal;
+mod keymap;
+mod markup;
+mod outline;
+mod query;
 mod raster;
 mod render;
 mod status;
+mod textobject;
+mod theme;
+mod time;
 mod tree;
 
 struct RenderStats {
@@ -28,35 +49,120 @@ fn average(times: &[Duration]) -> f32 {
     times.iter().map(|d| d.as_millis()).sum::<u128>() as f32 / times.len() as f32
 }
 
-fn main_loop(wins: &mut render::WindowStore, e: &mut Editor) -> RenderStats {
+fn main_loop(wins: &mut render::WindowStore, e: &mut Editor, msg: &str) -> RenderStats {
     let mut stats = RenderStats {
         key_render_times: vec![],
         loop_times: vec![],
     };
-    render_status(wins.status.as_mut(), e.cursor(), "");
+    render_status(wins.status.as_mut(), e.cursor(), e.theme(), &e.bullet_info(), msg);
     loop {
+        wins.editor.set_timeout(e.pending_timeout());
         let key = wins.editor.getch();
         let loop_now = Instant::now();
-        if key == "^[" {
+        if key.as_deref() == Some("^[") {
             break;
         }
 
         let now = Instant::now();
-        let e_update = e.update(&key, wins.editor.as_mut());
+        let e_update = match key {
+            Some(key) => e.update(&key, wins.editor.as_mut()),
+            None => e.timeout(wins.editor.as_mut()),
+        };
         stats.key_render_times.push(now.elapsed());
         if e_update.should_quit {
             break;
         }
         let cursor = e.cursor();
 
-        render_status(wins.status.as_mut(), cursor, &e_update.status_msg);
+        let info = e.bullet_info();
+        render_status(wins.status.as_mut(), cursor, e.theme(), &info, &e_update.status_msg);
         stats.loop_times.push(loop_now.elapsed());
     }
     stats
 }
 
+/// Asks on the terminal whether to recover the tree of a session that did not exit cleanly.
+/// Must be called before ncurses is set up.
+fn offer_recovery(dir: &Path) -> Option<Recovery> {
+    print!("termflowy did not exit cleanly last time. Recover the unsaved outline? [Y/n] ");
+    io::stdout().flush().ok()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).ok()?;
+    if answer.trim().to_lowercase().starts_with('n') {
+        return None;
+    }
+    match journal::recover(dir, Box::new(UlidGen::new())) {
+        Ok(recovery) => Some(recovery),
+        Err(e) => {
+            eprintln!("could not recover: {}", e);
+            None
+        }
+    }
+}
+
+/// The outline piped in for `termflowy -`
+struct Piped {
+    tree: Tree,
+    /// What was read
+    text: String,
+    repairs: Vec<Violation>,
+}
+
+fn read_piped_outline() -> Result<Piped, error::FileError> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    let (tree, repairs) = outline::parse(&text, Box::new(UlidGen::new()))?;
+    Ok(Piped {
+        tree,
+        text,
+        repairs,
+    })
+}
+
+/// Tells about the repairs made to an outline that was read, in few enough words for the status
+/// bar
+fn repairs_message(repairs: &[Violation]) -> String {
+    match repairs {
+        [] => String::new(),
+        [repair] => format!("repaired: {}", repair),
+        [repair, rest @ ..] => format!("repaired: {}, and {} more", repair, rest.len()),
+    }
+}
+
 fn main() {
-    render::setup_ncurses();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        process::exit(code);
+    }
+    let piped = match args.as_slice() {
+        [dash] if dash == "-" => match read_piped_outline() {
+            Ok(tree) => Some(tree),
+            Err(e) => {
+                eprintln!("termflowy: {}", e);
+                process::exit(cli::EXIT_FILE);
+            }
+        },
+        _ => None,
+    };
+
+    // A piped session is not journaled, so that it does not take over the recovery of the last
+    // interactive one
+    let journal_dir = Journal::default_dir().filter(|_| piped.is_none());
+    let recovery = journal_dir
+        .as_deref()
+        .filter(|dir| journal::needs_recovery(dir))
+        .and_then(offer_recovery);
+
+    let is_piped = piped.is_some();
+    if is_piped {
+        // stdin and stdout are taken by the pipe
+        if let Err(e) = render::setup_ncurses_on("/dev/tty") {
+            eprintln!("termflowy: cannot open /dev/tty: {}", e);
+            process::exit(1);
+        }
+    } else {
+        render::setup_ncurses();
+    }
     let default_hook = panic::take_hook(); 
     panic::set_hook(Box::new(move |info| {
         n::endwin();
@@ -70,11 +176,48 @@ fn main() {
         editor: Box::new(NCurses::new(render::create_window(bounds.0 - 2, bounds.1, 0, 0))),
         status: Box::new(NCurses::new(render::create_window(1, bounds.1, bounds.0 - 1, 0))),
     };
-    let mut editor = Editor::new(window_store.editor.as_mut());
-    let stats = main_loop(&mut window_store, &mut editor);
+    let config = Config::load();
+    render::init_colors(&config.render.theme);
+    let mut status_msg = config.status_msg();
+    let mut editor = match (piped, recovery) {
+        (Some(piped), _) => {
+            if status_msg.is_empty() {
+                status_msg = repairs_message(&piped.repairs);
+            }
+            let mut editor = Editor::with_tree(window_store.editor.as_mut(), config, piped.tree);
+            editor.pipe_output(&piped.text, outline::detect_format(&piped.text));
+            editor
+        }
+        (None, Some(recovery)) => {
+            if status_msg.is_empty() {
+                status_msg = format!(
+                    "recovered {} edits, {} lost, {} repairs",
+                    recovery.replayed,
+                    recovery.lost,
+                    recovery.repaired.len()
+                );
+            }
+            Editor::with_tree(window_store.editor.as_mut(), config, recovery.tree)
+        }
+        (None, None) => Editor::new(window_store.editor.as_mut(), config),
+    };
+    if let Some(dir) = &journal_dir {
+        if let Err(e) = editor.start_journal(dir) {
+            status_msg = e.to_string();
+        }
+    }
+    let stats = main_loop(&mut window_store, &mut editor, &status_msg);
+    let closed = editor.close_journal();
     n::endwin();
     n::delscreen(n::stdscr());
 
+    if is_piped {
+        if let Some(outline) = editor.piped_outline() {
+            print!("{}", outline);
+        }
+        return;
+    }
+
     // 5 ms
     println!(
         "average editor latency: {:.2}",
@@ -82,4 +225,7 @@ fn main() {
     );
     println!("average loop latency: {:.2}", average(&stats.loop_times));
     println!("{}", editor.get_tree());
+    if let Err(e) = closed {
+        eprintln!("{}", e);
+    }
 }
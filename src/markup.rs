@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Delimiters in the order they are tried, so that `**` wins over `*`
+const DELIMITERS: [(&str, Span); 6] = [
+    ("**", Span::Bold),
+    ("__", Span::Bold),
+    ("~~", Span::Strike),
+    ("`", Span::Code),
+    ("*", Span::Italic),
+    ("_", Span::Italic),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Span {
+    Bold,
+    Italic,
+    Code,
+    Strike,
+}
+
+/// The formatting of a piece of content. Spans can be nested, except in code.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub strike: bool,
+    /// A `#tag` or `@person`
+    pub tag: bool,
+    /// A `[[link]]` to another bullet
+    pub link: bool,
+}
+
+impl Style {
+    fn with(mut self, span: Span) -> Style {
+        match span {
+            Span::Bold => self.bold = true,
+            Span::Italic => self.italic = true,
+            Span::Code => self.code = true,
+            Span::Strike => self.strike = true,
+        }
+        self
+    }
+}
+
+/// A run of content with one style
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Byte range in the content
+    pub range: Range<usize>,
+    pub style: Style,
+    /// Whether this is a delimiter like `**` rather than text
+    pub is_marker: bool,
+}
+
+/// Splits content into segments that cover it in order. Recognizes `**bold**`, `__bold__`,
+/// `*italic*`, `_italic_`, `` `code` `` and `~~strike~~`. Delimiters that are not closed are
+/// text, and so are `_` inside words like `snake_case`. Tags are segments of their own, and so
+/// is the text of a `[[link]]`, which like code has no markup inside.
+pub fn parse(content: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    parse_range(content, 0..content.len(), Style::default(), &mut segments);
+    segments
+}
+
+fn parse_range(content: &str, range: Range<usize>, style: Style, out: &mut Vec<Segment>) {
+    let mut found = HashMap::new();
+    let mut text_start = range.start;
+    let mut i = range.start;
+    while i < range.end {
+        if let Some(close) = find_link(content, i, range.end) {
+            push(out, text_start..i, style, false);
+            let link = Style { link: true, ..style };
+            push(out, i..i + 2, link, true);
+            push(out, i + 2..close, link, false);
+            push(out, close..close + 2, link, true);
+            i = close + 2;
+            text_start = i;
+            continue;
+        }
+        if let Some((delimiter, span, close)) = find_span(content, i, range.end, &mut found) {
+            let len = delimiter.len();
+            push(out, text_start..i, style, false);
+            let inner = style.with(span);
+            push(out, i..i + len, inner, true);
+            match span {
+                Span::Code => push(out, i + len..close, inner, false),
+                _ => parse_range(content, i + len..close, inner, out),
+            }
+            push(out, close..close + len, inner, true);
+            i = close + len;
+            text_start = i;
+            continue;
+        }
+        if let Some(len) = tag_len(content, i, range.end) {
+            push(out, text_start..i, style, false);
+            let tag = Style { tag: true, ..style };
+            push(out, i..i + len, tag, false);
+            i += len;
+            text_start = i;
+            continue;
+        }
+        i += content[i..].chars().next().map_or(1, char::len_utf8);
+    }
+    push(out, text_start..range.end, style, false);
+}
+
+/// The `#tags` and `@people` in content, with their sigil, in the order they appear. Tags in
+/// code spans do not count.
+pub fn tags(content: &str) -> impl Iterator<Item = &str> {
+    parse(content)
+        .into_iter()
+        .filter(|s| s.style.tag)
+        .map(move |s| &content[s.range])
+}
+
+/// The byte ranges of the text of the `[[links]]` in content, in the order they appear
+pub fn links(content: &str) -> impl Iterator<Item = Range<usize>> {
+    parse(content)
+        .into_iter()
+        .filter(|s| s.style.link && !s.is_marker)
+        .map(|s| s.range)
+}
+
+/// Start of the tag being typed right before `end`, which is only a sigil if nothing of the
+/// name was typed yet.
+pub fn tag_before(content: &str, end: usize) -> Option<usize> {
+    let name: usize = content[..end]
+        .chars()
+        .rev()
+        .take_while(|&c| is_tag_char(c))
+        .map(char::len_utf8)
+        .sum();
+    let start = (end - name).checked_sub(1)?;
+    let is_sigil = content[start..].starts_with(&['#', '@'][..]);
+    match is_sigil && !is_word_char_before(content, start) {
+        true => Some(start),
+        false => None,
+    }
+}
+
+fn push(out: &mut Vec<Segment>, range: Range<usize>, style: Style, is_marker: bool) {
+    if !range.is_empty() {
+        out.push(Segment {
+            range,
+            style,
+            is_marker,
+        });
+    }
+}
+
+/// Index of the `]]` closing a link that opens at `start`, if there is one before `end`. Links
+/// are not empty and do not span lines.
+fn find_link(content: &str, start: usize, end: usize) -> Option<usize> {
+    if !content[start..end].starts_with("[[") {
+        return None;
+    }
+    let inner = &content[start + 2..end];
+    let close = inner.find("]]")?;
+    match close > 0 && !inner[..close].contains(&['[', '\n'][..]) {
+        true => Some(start + 2 + close),
+        false => None,
+    }
+}
+
+/// A span with its delimiter and the index of its closing delimiter
+type FoundSpan = Option<(&'static str, Span, usize)>;
+
+/// Finds a span opening at `start` and the index of its closing delimiter before `end`. What
+/// was found at each start before the same `end` is kept in `found`, as nested spans are looked
+/// for again from every span that contains them.
+fn find_span(
+    content: &str,
+    start: usize,
+    end: usize,
+    found: &mut HashMap<usize, FoundSpan>,
+) -> FoundSpan {
+    if let Some(&span) = found.get(&start) {
+        return span;
+    }
+    let span = find_span_uncached(content, start, end, found);
+    found.insert(start, span);
+    span
+}
+
+fn find_span_uncached(
+    content: &str,
+    start: usize,
+    end: usize,
+    found: &mut HashMap<usize, FoundSpan>,
+) -> FoundSpan {
+    let (delimiter, span) = DELIMITERS
+        .iter()
+        .find(|(d, _)| content[start..end].starts_with(d))?;
+    let after = start + delimiter.len();
+    let opens = content[after..end]
+        .chars()
+        .next()
+        .is_some_and(|c| !c.is_whitespace())
+        && !(delimiter.starts_with('_') && is_word_char_before(content, start));
+    if !opens {
+        return None;
+    }
+    // Spans are never empty, so the search starts after the first character
+    let mut i = after + content[after..end].chars().next()?.len_utf8();
+    while i < end {
+        let rest = &content[i..end];
+        if *span != Span::Code && rest.starts_with(delimiter) && closes(content, i, end, delimiter)
+        {
+            return Some((delimiter, *span, i));
+        }
+        if *span == Span::Code && rest.starts_with('`') {
+            return Some((delimiter, *span, i));
+        }
+        // Nested spans are skipped whole, so that the `**` of `*a **b** c*` does not close it
+        i = match find_span(content, i, end, found) {
+            Some((nested, _, close)) if *span != Span::Code => close + nested.len(),
+            _ => i + rest.chars().next().map_or(1, char::len_utf8),
+        };
+    }
+    None
+}
+
+/// Whether the delimiter at `i` can close a span: it follows text and, for `_`, is not
+/// followed by more of the same word.
+fn closes(content: &str, i: usize, end: usize, delimiter: &str) -> bool {
+    let after = i + delimiter.len();
+    let follows_text = content[..i]
+        .chars()
+        .next_back()
+        .is_some_and(|c| !c.is_whitespace());
+    let ends_word = !delimiter.starts_with('_')
+        || !content[after..end]
+            .chars()
+            .next()
+            .is_some_and(char::is_alphanumeric);
+    follows_text && ends_word
+}
+
+/// Length of the `#tag` or `@person` starting at `start`, if there is one. Like with `_`, a
+/// sigil inside a word like in `a@b.com` does not start a tag.
+fn tag_len(content: &str, start: usize, end: usize) -> Option<usize> {
+    let rest = &content[start..end];
+    if !rest.starts_with(&['#', '@'][..]) || is_word_char_before(content, start) {
+        return None;
+    }
+    let name: usize = rest[1..]
+        .chars()
+        .take_while(|&c| is_tag_char(c))
+        .map(char::len_utf8)
+        .sum();
+    match name {
+        0 => None,
+        name => Some(1 + name),
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_word_char_before(content: &str, i: usize) -> bool {
+    content[..i]
+        .chars()
+        .next_back()
+        .is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each segment as `text/styles` with a letter per style, and a leading `!` for markers
+    fn describe(content: &str) -> Vec<String> {
+        parse(content)
+            .into_iter()
+            .map(|s| {
+                let flags = [
+                    (s.style.bold, 'b'),
+                    (s.style.italic, 'i'),
+                    (s.style.code, 'c'),
+                    (s.style.strike, 's'),
+                    (s.style.tag, 't'),
+                    (s.style.link, 'l'),
+                ];
+                let flags: String = flags.iter().filter(|f| f.0).map(|f| f.1).collect();
+                let marker = if s.is_marker { "!" } else { "" };
+                format!("{}{}/{}", marker, &content[s.range], flags)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_spans() {
+        assert_eq!(
+            describe("a **b** `c*d*` ~~e~~"),
+            [
+                "a /", "!**/b", "b/b", "!**/b", " /", "!`/c", "c*d*/c", "!`/c", " /", "!~~/s",
+                "e/s", "!~~/s",
+            ]
+        );
+    }
+
+    #[test]
+    fn nests_spans() {
+        assert_eq!(
+            describe("*a **b** c*"),
+            ["!*/i", "a /i", "!**/bi", "b/bi", "!**/bi", " c/i", "!*/i"]
+        );
+    }
+
+    #[test]
+    fn unclosed_and_intraword_delimiters_are_text() {
+        for content in [
+            "snake_case_name",
+            "a ** b",
+            "**open",
+            "a * b *",
+            "``",
+            "_a_b",
+        ] {
+            assert_eq!(describe(content), [format!("{}/", content)]);
+        }
+        assert_eq!(describe("_it_,")[1], "it/i");
+        assert_eq!(describe("é*ü*")[2], "ü/i");
+    }
+
+    #[test]
+    fn unclosed_delimiters_parse_quickly() {
+        for delimiter in ["*", "_", "~~"] {
+            let content = format!("{}a ", delimiter).repeat(200);
+            let start = std::time::Instant::now();
+            assert_eq!(describe(&content), [format!("{}/", content)]);
+            assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn finds_tag_being_typed() {
+        let content = "see #pro and a@b";
+        assert_eq!(tag_before(content, 8), Some(4));
+        assert_eq!(tag_before(content, 5), Some(4));
+        assert_eq!(tag_before(content, 3), None);
+        assert_eq!(tag_before(content, content.len()), None);
+        let tags: Vec<&str> = tags("#a `#b` @c #a").collect();
+        assert_eq!(tags, ["#a", "@c", "#a"]);
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!(
+            describe("#todo for @ann-marie, *#x* `#no` a@b.c # #"),
+            [
+                "#todo/t",
+                " for /",
+                "@ann-marie/t",
+                ", /",
+                "!*/i",
+                "#x/it",
+                "!*/i",
+                " /",
+                "!`/c",
+                "#no/c",
+                "!`/c",
+                " a@b.c # #/",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_links() {
+        assert_eq!(
+            describe("see [[a *b* #c]] and **[[d]]** [[]] [[e"),
+            [
+                "see /", "![[/l", "a *b* #c/l", "!]]/l", " and /", "!**/b", "![[/bl", "d/bl",
+                "!]]/bl", "!**/b", " [[]] [[e/",
+            ]
+        );
+        let content = "[[x]] `[[y]]` [[z]]";
+        let links: Vec<&str> = links(content).map(|r| &content[r]).collect();
+        assert_eq!(links, ["x", "z"]);
+    }
+}
@@ -0,0 +1,469 @@
+use std::{fs, path::Path};
+
+use crate::error::FileError;
+use crate::tree::check::Violation;
+use crate::tree::id::Id;
+use crate::tree::op::FlatNode;
+use crate::tree::{IdGenerator, NodeIterator, Tree};
+
+/// Indentation of one level when writing
+const INDENT: &str = "  ";
+const MARKERS: [char; 3] = ['-', '*', '+'];
+/// Starts the lines that save a [search](crate::tree::searches::SavedSearch)
+const SEARCH: &str = "%search ";
+/// The keys of the metadata in braces at the end of a line
+const METADATA_KEYS: [&str; 4] = ["id", "created", "modified", "mirror"];
+
+/// The ways an outline can be written out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The file format, see [parse]
+    Termflowy,
+    /// Content indented with tabs, like `Tree`'s Display without the ids
+    Text,
+    Markdown,
+    Opml,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "termflowy" => Some(Format::Termflowy),
+            "text" => Some(Format::Text),
+            "markdown" | "md" => Some(Format::Markdown),
+            "opml" => Some(Format::Opml),
+            _ => None,
+        }
+    }
+}
+
+/// Reads an outline, one bullet per line:
+///
+/// ```text
+/// - Groceries {id=01JAF3H7M2X1}
+///   - [x] Milk {id=01JAF3H9QDZ0}
+/// ```
+///
+/// Children are indented deeper than their parent. The `-` (or `*`, `+`) before the content,
+/// the `[x]` of completed bullets and the metadata in braces at the end are all optional, so
+/// plain indented text reads as an outline too. Bullets without an id get one from `generator`.
+/// The metadata can also have the `created` and `modified` times, like
+/// `{id=01JAF3H7M2X1 created=2026-10-18T09:30:00Z}`. A bullet with `mirror=01JAF3H7M2X1` in
+/// its metadata is a [mirror](Tree::create_mirror) of the bullet with that id, and its own
+/// content is not used.
+///
+/// Unindented lines like `%search urgent tag:urgent NOT is:completed` save a search by the name
+/// after `%search` instead of being a bullet.
+///
+/// Whatever in the text breaks the invariants of a tree, like an id used twice, is
+/// [repaired](Tree::repair) and returned with the tree.
+pub fn parse(
+    text: &str,
+    generator: Box<dyn IdGenerator>,
+) -> Result<(Tree, Vec<Violation>), FileError> {
+    let mut nodes = vec![];
+    let mut ids = vec![];
+    let mut searches = vec![];
+    // Indentation of the bullets that later lines can be children of
+    let mut levels: Vec<usize> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some((name, query)) = line.strip_prefix(SEARCH).and_then(split_search) {
+            searches.push((name, query));
+            continue;
+        }
+        let width = line.len() - line.trim_start().len();
+        while levels.last().is_some_and(|&w| w >= width) {
+            levels.pop();
+        }
+        let (node, id) = parse_line(trimmed, levels.len(), i + 1)?;
+        levels.push(width);
+        if let Some(id) = id {
+            generator.reserve(id);
+        }
+        nodes.push(node);
+        ids.push(id);
+    }
+    for (node, id) in nodes.iter_mut().zip(ids) {
+        node.id = id.unwrap_or_else(|| generator.gen());
+    }
+    let first = nodes.first().map_or(Id::ROOT, |n| n.id);
+    let (mut tree, mut repairs) = Tree::from_flat(generator, &nodes, first);
+    if nodes.is_empty() {
+        // An empty outline gets a first bullet like a new one does, which is not a repair
+        repairs.clear();
+    }
+    for (name, query) in searches {
+        tree.save_search(name, query);
+    }
+    Ok((tree, repairs))
+}
+
+/// Splits the rest of a `%search` line into the name and the query
+fn split_search(rest: &str) -> Option<(&str, &str)> {
+    let (name, query) = rest.trim().split_once(' ')?;
+    Some((name, query.trim()))
+}
+
+fn parse_line(
+    line: &str,
+    depth: usize,
+    number: usize,
+) -> Result<(FlatNode, Option<Id>), FileError> {
+    let mut rest = line;
+    if let Some(after) = rest.strip_prefix(&MARKERS[..]) {
+        if after.is_empty() || after.starts_with(' ') {
+            rest = after.trim_start();
+        }
+    }
+    let mut completed = false;
+    if let Some(after) = strip_checkbox(rest, &["[x]", "[X]"]) {
+        rest = after;
+        completed = true;
+    } else if let Some(after) = strip_checkbox(rest, &["[ ]"]) {
+        rest = after;
+    }
+    let (content, metadata) = split_metadata(rest);
+    let mut id = None;
+    let mut mirror = None;
+    let mut times = [None, None];
+    let parse_id = |value: &str| {
+        value.parse().map_err(|_| FileError::InvalidId {
+            line: number,
+            id: value.to_string(),
+        })
+    };
+    for (key, value) in metadata {
+        let time = match key {
+            "id" => {
+                id = Some(parse_id(value)?);
+                continue;
+            }
+            "mirror" => {
+                mirror = Some(parse_id(value)?);
+                continue;
+            }
+            "created" => &mut times[0],
+            "modified" => &mut times[1],
+            _ => continue,
+        };
+        *time = Some(value.parse().map_err(|_| FileError::InvalidTime {
+            line: number,
+            time: value.to_string(),
+        })?);
+    }
+    let node = FlatNode {
+        depth,
+        id: Id::ROOT,
+        content: content.to_string(),
+        completed,
+        created: times[0],
+        modified: times[1],
+        mirror,
+    };
+    Ok((node, id))
+}
+
+fn strip_checkbox<'a>(line: &'a str, boxes: &[&str]) -> Option<&'a str> {
+    boxes.iter().find_map(|b| {
+        let after = line.strip_prefix(b)?;
+        match after.is_empty() || after.starts_with(' ') {
+            true => Some(after.trim_start()),
+            false => None,
+        }
+    })
+}
+
+/// Splits `content {key=value ...}` into the content and the pairs. Lines that do not end in
+/// such braces, or whose braces have a key that is not [metadata](METADATA_KEYS), are all
+/// content.
+fn split_metadata(line: &str) -> (&str, Vec<(&str, &str)>) {
+    let no_metadata = (line, vec![]);
+    let open = match line.strip_suffix('}').and_then(|l| l.rfind('{')) {
+        Some(open) if open == 0 || line[..open].ends_with(' ') => open,
+        _ => return no_metadata,
+    };
+    let mut pairs = vec![];
+    for pair in line[open + 1..line.len() - 1].split_whitespace() {
+        match pair.split_once('=') {
+            Some((key, value))
+                if METADATA_KEYS.contains(&key) && !value.is_empty() && !value.contains('{') =>
+            {
+                pairs.push((key, value))
+            }
+            _ => return no_metadata,
+        }
+    }
+    match pairs.is_empty() {
+        true => no_metadata,
+        false => (line[..open].trim_end(), pairs),
+    }
+}
+
+/// The format to write an outline read from `text` back out in: the file format if it has
+/// anything that only the file format keeps, like ids, times or saved searches, and otherwise
+/// markdown if its bullets have markers and text if they do not.
+pub fn detect_format(text: &str) -> Format {
+    let mut format = Format::Text;
+    for line in text.lines() {
+        if line.strip_prefix(SEARCH).and_then(split_search).is_some() {
+            return Format::Termflowy;
+        }
+        let trimmed = line.trim();
+        if !split_metadata(trimmed).1.is_empty() {
+            return Format::Termflowy;
+        }
+        if let Some(after) = trimmed.strip_prefix(&MARKERS[..]) {
+            if after.is_empty() || after.starts_with(' ') {
+                format = Format::Markdown;
+            }
+        }
+    }
+    format
+}
+
+pub fn export(tree: &Tree, format: Format) -> String {
+    let mut out = String::new();
+    if format == Format::Opml {
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+        out.push_str("  <head></head>\n  <body>\n");
+        for child in tree.root_iter().children_iter() {
+            write_opml(child, 2, &mut out);
+        }
+        out.push_str("  </body>\n</opml>\n");
+        return out;
+    }
+    if format == Format::Termflowy {
+        for search in tree.saved_searches() {
+            out.push_str(&format!("{}{} {}\n", SEARCH, search.name, search.query));
+        }
+    }
+    for node in tree.flatten() {
+        // Mirrors are written with the content they show, except in the file format
+        let shown = tree.node_iter(node.id).expect("flattened node is in the tree");
+        let line = match format {
+            Format::Termflowy => {
+                let mut line = format!("{}- ", INDENT.repeat(node.depth));
+                if node.completed {
+                    line.push_str("[x] ");
+                } else if node.content.starts_with('[') {
+                    // So that content like `[x] not done` is not read back as completed
+                    line.push_str("[ ] ");
+                }
+                line.push_str(&node.content);
+                if !node.content.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(&format!("{{id={}", node.id));
+                if let Some(mirror) = node.mirror {
+                    line.push_str(&format!(" mirror={}", mirror));
+                }
+                if let Some(created) = node.created {
+                    line.push_str(&format!(" created={}", created));
+                }
+                if let Some(modified) = node.modified {
+                    line.push_str(&format!(" modified={}", modified));
+                }
+                line + "}"
+            }
+            Format::Text => format!(
+                "{}{}{}",
+                "\t".repeat(node.depth),
+                if shown.is_completed() { "[x] " } else { "" },
+                shown.content()
+            ),
+            Format::Markdown => format!(
+                "{}- {}{}",
+                INDENT.repeat(node.depth),
+                if shown.is_completed() { "[x] " } else { "" },
+                shown.content()
+            ),
+            Format::Opml => unreachable!(),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn write_opml(itr: NodeIterator, depth: usize, out: &mut String) {
+    let indent = INDENT.repeat(depth);
+    out.push_str(&format!(
+        "{}<outline text=\"{}\"",
+        indent,
+        escape_xml(itr.content())
+    ));
+    if itr.is_completed() {
+        out.push_str(" _complete=\"true\"");
+    }
+    if itr.children_iter().next().is_none() {
+        out.push_str("/>\n");
+        return;
+    }
+    out.push_str(">\n");
+    for child in itr.children_iter() {
+        write_opml(child, depth + 1, out);
+    }
+    out.push_str(&format!("{}</outline>\n", indent));
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn load(
+    path: &Path,
+    generator: Box<dyn IdGenerator>,
+) -> Result<(Tree, Vec<Violation>), FileError> {
+    parse(&fs::read_to_string(path)?, generator)
+}
+
+/// Writes the tree in the [file format](parse). The file is replaced in one step, so it is
+/// never left half written.
+pub fn save(tree: &Tree, path: &Path) -> Result<(), FileError> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, export(tree, Format::Termflowy))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::id::CounterGen;
+
+    fn parse_text(text: &str) -> Tree {
+        parse(text, Box::new(CounterGen::new())).unwrap().0
+    }
+
+    #[test]
+    fn parses_plain_indented_text() {
+        let tree = parse_text("a\n  b\n    c\n\n  d\ne\n\tf\n");
+        assert_eq!(export(&tree, Format::Text), "a\n\tb\n\t\tc\n\td\ne\n\tf\n");
+        assert_eq!(tree.get_active_id(), Id::from(1));
+        assert_eq!(tree.check_invariants(), []);
+    }
+
+    #[test]
+    fn file_format_round_trip() {
+        let text =
+            "- a {id=7}\n  - [x] b {id=3}\n  - [ ] [x] c {id=5}\n  - {id=4}\n- d {e=f} {id=6}\n";
+        let tree = parse_text(text);
+        assert_eq!(export(&tree, Format::Termflowy), text);
+        let c = tree.node_iter(Id::from(5)).unwrap();
+        assert_eq!((c.content().as_str(), c.is_completed()), ("[x] c", false));
+        assert!(tree.node_iter(Id::from(3)).unwrap().is_completed());
+    }
+
+    #[test]
+    fn times_round_trip() {
+        let text = "- a {id=1 created=2026-10-18T09:30:00Z modified=2026-10-19T10:00:00Z}\n";
+        let tree = parse_text(text);
+        assert_eq!(export(&tree, Format::Termflowy), text);
+        let a = tree.node_iter(Id::from(1)).unwrap();
+        assert_eq!(a.created().unwrap().to_string(), "2026-10-18T09:30:00Z");
+        let result = parse("- a {id=1 created=today}", Box::new(CounterGen::new()));
+        assert_eq!(
+            result.err(),
+            Some(FileError::InvalidTime {
+                line: 1,
+                time: "today".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn mirrors_round_trip() {
+        let text = "- {id=2 mirror=1}\n- a {id=1}\n  - b {id=3}\n";
+        let tree = parse_text(text);
+        assert_eq!(export(&tree, Format::Termflowy), text);
+        assert_eq!(export(&tree, Format::Text), "a\na\n\tb\n");
+        assert_eq!(tree.node_iter(Id::from(2)).unwrap().content(), "a");
+        assert_eq!(tree.check_invariants(), []);
+    }
+
+    #[test]
+    fn saved_searches_round_trip() {
+        let text = "%search todo NOT is:completed\n- a {id=1}\n%search urgent tag:urgent\n";
+        let tree = parse_text(text);
+        assert_eq!(
+            export(&tree, Format::Termflowy),
+            "%search todo NOT is:completed\n%search urgent tag:urgent\n- a {id=1}\n"
+        );
+        assert_eq!(export(&tree, Format::Text), "a\n");
+        // Only unindented lines with a name and a query are searches
+        let tree = parse_text("a\n  %search b c\n%search d\n");
+        assert_eq!(export(&tree, Format::Text), "a\n\t%search b c\n%search d\n");
+    }
+
+    #[test]
+    fn missing_ids_do_not_clash() {
+        let tree = parse_text("* new\n* old {id=1}\n+ [X] other");
+        let ids: Vec<Id> = tree.flatten().iter().map(|n| n.id).collect();
+        assert_eq!(ids, [Id::from(2), Id::from(1), Id::from(3)]);
+        assert!(tree.node_iter(Id::from(3)).unwrap().is_completed());
+        let result = parse("- a {id=!}", Box::new(CounterGen::new()));
+        assert_eq!(
+            result.err(),
+            Some(FileError::InvalidId {
+                line: 1,
+                id: "!".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn reports_repairs() {
+        let text = "- a {id=1}\n- b {id=1}\n";
+        let (tree, repairs) = parse(text, Box::new(CounterGen::new())).unwrap();
+        assert!(repairs.contains(&Violation::DuplicateId(Id::from(1))));
+        assert_eq!(tree.check_invariants(), []);
+        let (_, repairs) = parse("", Box::new(CounterGen::new())).unwrap();
+        assert_eq!(repairs, []);
+    }
+
+    #[test]
+    fn metadata_needs_pairs() {
+        assert_eq!(split_metadata("a {b}"), ("a {b}", vec![]));
+        assert_eq!(split_metadata("a{id=1}"), ("a{id=1}", vec![]));
+        assert_eq!(
+            split_metadata("a  {id=1 mirror=2}"),
+            ("a", vec![("id", "1"), ("mirror", "2")])
+        );
+        assert_eq!(split_metadata("a {id=1 x=y}"), ("a {id=1 x=y}", vec![]));
+    }
+
+    #[test]
+    fn unknown_metadata_is_content() {
+        let tree = parse_text("note {a=b}\n");
+        assert_eq!(tree.flatten()[0].content, "note {a=b}");
+        let text = export(&tree, Format::Termflowy);
+        assert_eq!(text, "- note {a=b} {id=1}\n");
+        assert_eq!(export(&parse_text(&text), Format::Termflowy), text);
+    }
+
+    #[test]
+    fn detects_format_to_write_back() {
+        assert_eq!(detect_format("a\n\tb\n"), Format::Text);
+        assert_eq!(detect_format("a\n  * b\n"), Format::Markdown);
+        assert_eq!(detect_format("- a\n  - b {id=1}\n"), Format::Termflowy);
+        assert_eq!(detect_format("a\n%search x tag:x\n"), Format::Termflowy);
+    }
+
+    #[test]
+    fn exports_markdown_and_opml() {
+        let tree = parse_text("a\n  [x] b & <c>\n");
+        assert_eq!(export(&tree, Format::Markdown), "- a\n  - [x] b & <c>\n");
+        let opml = export(&tree, Format::Opml);
+        assert!(opml.contains(
+            "    <outline text=\"a\">\n      <outline text=\"b &amp; &lt;c&gt;\" _complete=\"true\"/>\n    </outline>\n"
+        ));
+    }
+}
@@ -0,0 +1,424 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::QueryError;
+use crate::markup;
+use crate::time::{self, Date, Timestamp};
+use crate::tree::NodeIterator;
+
+/// A search over the bullets of an outline, like `tag:urgent AND NOT is:completed`.
+///
+/// Terms are `key:value` predicates or plain words, which search the text. Terms next to each
+/// other must all match, and `AND`, `OR`, `NOT` and parentheses combine them with NOT binding
+/// tightest and OR loosest. Values with spaces are quoted, like `under:"Project X"`, and so are
+/// words that should not be read as a predicate or operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// `text:` or a plain word, matching content that contains it regardless of case
+    Text(String),
+    /// `tag:urgent` matches `#urgent` and `@urgent`, `tag:#urgent` only the first
+    Tag(String),
+    /// `is:completed`
+    Completed,
+    /// `depth:<3`, where top level bullets are at depth 1
+    Depth(Cmp, usize),
+    /// `has:children`
+    HasChildren,
+    /// `under:X` matches bullets with an ancestor whose content contains X, regardless of case
+    Under(String),
+    /// `created:>=2026-10-01` compares the day the bullet was created, in local time
+    Created(Cmp, Date),
+    /// `modified:2026-10-18`, like `created:`
+    Modified(Cmp, Date),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+/// How a value compares to the one in a predicate, like the `<` of `depth:<3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Cmp {
+    /// Reads the comparison at the start of `value` and returns the rest
+    fn split(value: &str) -> (Cmp, &str) {
+        for (prefix, cmp) in [
+            ("<=", Cmp::Le),
+            (">=", Cmp::Ge),
+            ("<", Cmp::Lt),
+            (">", Cmp::Gt),
+            ("=", Cmp::Eq),
+        ] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (cmp, rest);
+            }
+        }
+        (Cmp::Eq, value)
+    }
+
+    fn holds<T: Ord>(self, value: T, bound: T) -> bool {
+        match self {
+            Cmp::Lt => value < bound,
+            Cmp::Le => value <= bound,
+            Cmp::Eq => value == bound,
+            Cmp::Ge => value >= bound,
+            Cmp::Gt => value > bound,
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err(QueryError::Empty);
+        }
+        let mut parser = Parser { tokens, next: 0 };
+        let query = parser.or()?;
+        match parser.tokens.get(parser.next) {
+            Some(token) => Err(QueryError::Unexpected(token.to_string())),
+            None => Ok(query),
+        }
+    }
+
+    pub fn matches(&self, node: &NodeIterator) -> bool {
+        match self {
+            Query::Text(text) => node.content().to_lowercase().contains(text),
+            Query::Tag(tag) => {
+                markup::tags(node.content()).any(|t| match tag.starts_with(SIGILS) {
+                    true => t == tag,
+                    false => &t[1..] == tag,
+                })
+            }
+            Query::Completed => node.is_completed(),
+            Query::Depth(cmp, depth) => cmp.holds(ancestors(*node).count(), *depth),
+            Query::HasChildren => node.children_iter().next().is_some(),
+            Query::Under(text) => ancestors(*node)
+                .skip(1)
+                .any(|a| a.content().to_lowercase().contains(text)),
+            Query::Created(cmp, date) => on_day(node.created(), *cmp, *date),
+            Query::Modified(cmp, date) => on_day(node.modified(), *cmp, *date),
+            Query::Not(query) => !query.matches(node),
+            Query::And(a, b) => a.matches(node) && b.matches(node),
+            Query::Or(a, b) => a.matches(node) || b.matches(node),
+        }
+    }
+}
+
+const SIGILS: &[char] = &['#', '@'];
+
+/// Whether `time` is known and its day in local time compares to `date`
+fn on_day(time: Option<Timestamp>, cmp: Cmp, date: Date) -> bool {
+    time.is_some_and(|t| cmp.holds(t.local_date(time::local_offset(t)), date))
+}
+
+/// `node` and its ancestors up to, but without, the root
+fn ancestors(node: NodeIterator) -> impl Iterator<Item = NodeIterator> {
+    std::iter::successors(Some(node), |n| n.clone().next_parent()).take_while(|n| !n.is_root())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// A term, which is `literal` if it started with a quote
+    Word {
+        text: String,
+        literal: bool,
+    },
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Word { text, .. } => write!(f, "{}", text),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            _ => tokens.push(word(&mut chars)?),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a word up to whitespace or a parenthesis outside of quotes, leaving out the quotes.
+fn word(chars: &mut Peekable<Chars>) -> Result<Token, QueryError> {
+    let literal = chars.peek() == Some(&'"');
+    let mut text = String::new();
+    let mut quoted = false;
+    while let Some(&c) = chars.peek() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => text.push(c),
+            '(' | ')' => break,
+            _ if c.is_whitespace() => break,
+            _ => text.push(c),
+        }
+        chars.next();
+    }
+    if quoted {
+        return Err(QueryError::UnclosedQuote);
+    }
+    Ok(match (text.as_str(), literal) {
+        ("AND", false) => Token::And,
+        ("OR", false) => Token::Or,
+        ("NOT", false) => Token::Not,
+        _ => Token::Word { text, literal },
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Result<Token, QueryError> {
+        let token = self.peek().cloned().ok_or(QueryError::UnexpectedEnd)?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    /// Terms joined by AND, which can be left out
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.not()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.next += 1,
+                Some(Token::Or) | Some(Token::Close) | None => return Ok(query),
+                Some(_) => {}
+            }
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Query, QueryError> {
+        match self.take()? {
+            Token::Not => Ok(Query::Not(Box::new(self.not()?))),
+            Token::Open => {
+                let query = self.or()?;
+                match self.take()? {
+                    Token::Close => Ok(query),
+                    token => Err(QueryError::Unexpected(token.to_string())),
+                }
+            }
+            Token::Word { text, literal } => term(&text, literal),
+            token => Err(QueryError::Unexpected(token.to_string())),
+        }
+    }
+}
+
+fn term(text: &str, literal: bool) -> Result<Query, QueryError> {
+    let (key, value) = match text.split_once(':') {
+        Some((key, value)) if !literal && key.chars().all(|c| c.is_ascii_alphabetic()) => {
+            (key, value)
+        }
+        _ => return Ok(Query::Text(text.to_lowercase())),
+    };
+    let invalid = || QueryError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    };
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    match key {
+        "text" => Ok(Query::Text(value.to_lowercase())),
+        "tag" => Ok(Query::Tag(value.to_string())),
+        "is" if value == "completed" => Ok(Query::Completed),
+        "has" if value == "children" => Ok(Query::HasChildren),
+        "depth" => {
+            let (cmp, depth) = Cmp::split(value);
+            depth
+                .parse()
+                .map(|d| Query::Depth(cmp, d))
+                .map_err(|_| invalid())
+        }
+        "under" => Ok(Query::Under(value.to_lowercase())),
+        "created" | "modified" => {
+            let (cmp, date) = Cmp::split(value);
+            let date = date.parse().map_err(|_| invalid())?;
+            Ok(match key {
+                "created" => Query::Created(cmp, date),
+                _ => Query::Modified(cmp, date),
+            })
+        }
+        "is" | "has" => Err(invalid()),
+        _ => Err(QueryError::UnknownKey(key.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::FixedClock;
+    use crate::tree::id::CounterGen;
+    use crate::tree::{TraversalType, Tree};
+
+    /// The contents of the bullets of a small outline that match `query`
+    fn search(query: &str) -> Vec<String> {
+        // Projects
+        //   Termflowy #work
+        //     release notes (completed)
+        //   Garden @ann
+        // Inbox "quoted"
+        let mut tree = Tree::new(Box::new(CounterGen::new()));
+        // Midday, so that the days are the same in most time zones
+        let clock = FixedClock::new("2026-10-18T12:00:00Z".parse().unwrap());
+        tree.set_clock(Box::new(clock.clone()));
+        let nodes = [
+            (0, "Projects"),
+            (1, "Termflowy #work"),
+            (2, "release notes"),
+            (1, "Garden @ann"),
+            (0, "Inbox \"quoted\""),
+        ];
+        for (i, (depth, content)) in nodes.iter().enumerate() {
+            if i > 0 {
+                tree.create_sibling();
+            }
+            let previous_depth = if i > 0 { nodes[i - 1].0 } else { 0 };
+            match depth.cmp(&previous_depth) {
+                std::cmp::Ordering::Greater => tree.indent(false).unwrap(),
+                std::cmp::Ordering::Less => tree.unindent().unwrap(),
+                std::cmp::Ordering::Equal => {}
+            }
+            tree.get_mut_active_content().push_str(content);
+        }
+        tree.activate(3.into()).unwrap();
+        clock.set("2026-10-19T12:00:00Z".parse().unwrap());
+        tree.set_completed(true);
+
+        let query = Query::parse(query).unwrap();
+        tree.root_iter()
+            .traverse(TraversalType::PreOrder)
+            .skip(1)
+            .filter(|n| query.matches(n))
+            .map(|n| n.content().clone())
+            .collect()
+    }
+
+    #[test]
+    fn predicates() {
+        assert_eq!(search("GARDEN"), ["Garden @ann"]);
+        assert_eq!(search("text:notes"), ["release notes"]);
+        assert_eq!(search("tag:work"), ["Termflowy #work"]);
+        assert_eq!(search("tag:@ann"), ["Garden @ann"]);
+        assert!(search("tag:#ann").is_empty());
+        assert_eq!(search("is:completed"), ["release notes"]);
+        assert_eq!(search("depth:1"), ["Projects", "Inbox \"quoted\""]);
+        assert_eq!(search("depth:>=3"), ["release notes"]);
+        assert_eq!(search("has:children"), ["Projects", "Termflowy #work"]);
+        assert_eq!(
+            search("under:\"projects\""),
+            ["Termflowy #work", "release notes", "Garden @ann"]
+        );
+        assert_eq!(search("\"\"\"quoted\"\"\""), ["Inbox \"quoted\""]);
+        assert_eq!(search("modified:2026-10-19"), ["release notes"]);
+        assert_eq!(
+            search("created:<=2026-10-18 under:projects"),
+            ["Termflowy #work", "release notes", "Garden @ann"]
+        );
+        assert!(search("created:<2026-10-18").is_empty());
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            search("under:Projects NOT has:children"),
+            ["release notes", "Garden @ann"]
+        );
+        assert_eq!(
+            search("depth:1 OR tag:work AND NOT is:completed"),
+            ["Projects", "Termflowy #work", "Inbox \"quoted\""]
+        );
+        assert_eq!(
+            search("(depth:1 OR under:termflowy) AND text:s"),
+            ["Projects", "release notes"]
+        );
+        assert_eq!(
+            search("NOT NOT e r"),
+            [
+                "Projects",
+                "Termflowy #work",
+                "release notes",
+                "Garden @ann"
+            ]
+        );
+        assert_eq!(search("\"OR\""), ["Termflowy #work"]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Query::parse("  "), Err(QueryError::Empty));
+        assert_eq!(Query::parse("a AND"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("(a"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("a)"), Err(QueryError::Unexpected(")".into())));
+        assert_eq!(
+            Query::parse("OR a"),
+            Err(QueryError::Unexpected("OR".into()))
+        );
+        assert_eq!(Query::parse("under:\"a"), Err(QueryError::UnclosedQuote));
+        assert_eq!(
+            Query::parse("color:red"),
+            Err(QueryError::UnknownKey("color".into()))
+        );
+        assert!(matches!(
+            Query::parse("depth:<x"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Query::parse("created:yesterday"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Query::parse("is:open"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert_eq!(Query::parse("\"a:b\""), Ok(Query::Text("a:b".into())));
+    }
+}
@@ -1,4 +1,6 @@
+use crate::error::RasterError;
 use crate::render::Point;
+use crate::tree::id::Id;
 use Direction::*;
 use PixelState::*;
 
@@ -18,9 +20,13 @@ impl Raster {
         }
     }
 
+    /// Adds a pixel after the last one. Pixels that do not fit are dropped since they would be
+    /// off screen.
     pub fn push(&mut self, state: PixelState) {
-        self.current = linear_move(self.current, self.max, 1).expect("cannot add to full raster");
-        self.map[self.current.0 as usize].push(state);
+        if let Some(current) = linear_move(self.current, self.max, 1) {
+            self.current = current;
+            self.map[self.current.0 as usize].push(state);
+        }
     }
 
     pub fn push_multiple(&mut self, state: PixelState, count: u32) {
@@ -32,22 +38,74 @@ impl Raster {
     // TODO remove option and add an Unknown state. Do not use safe gets with Vec
     pub fn get(&self, pos: Point) -> Option<PixelState> {
         match self.map.get(pos.0 as usize) {
-            Some(v) => match v.get(pos.1 as usize) {
-                Some(state) => Some(*state),
-                None => None,
-            },
+            Some(v) => v.get(pos.1 as usize).copied(),
             None => None,
         }
     }
 
-    pub fn browser(&self, pos: Point) -> Result<Browser, String> {
+    /// The drawn content of bullet `id` as offsets and their positions, in order
+    fn text_of(&self, id: Id) -> impl Iterator<Item = (usize, Point)> + '_ {
+        self.map.iter().enumerate().flat_map(move |(y, row)| {
+            row.iter().enumerate().filter_map(move |(x, state)| match *state {
+                Text { id: text_id, offset } if text_id == id => {
+                    Some((offset, (y as i32, x as i32)))
+                }
+                _ => None,
+            })
+        })
+    }
+
+    /// Position of the content at `offset` of bullet `id`. Offsets which are not drawn, like
+    /// those of concealed markup, resolve to the closest drawn offset after them, or before them
+    /// at the end of the content. A bullet drawn more than once, under mirrors, is found in the
+    /// copy closest to the row of `near`.
+    pub fn find_text(&self, id: Id, offset: usize, near: Point) -> Option<Point> {
+        let mut copies: Vec<Point> = vec![];
+        // Each copy starts at its bullet glyph
+        let mut new_copy = false;
+        let mut found = false;
+        for (y, row) in self.map.iter().enumerate() {
+            for (x, state) in row.iter().enumerate() {
+                let pos = (y as i32, x as i32);
+                match *state {
+                    Bullet(bullet_id) if bullet_id == id => new_copy = true,
+                    Text { id: text_id, offset: text_offset } if text_id == id => {
+                        if new_copy || copies.is_empty() {
+                            copies.push(pos);
+                            new_copy = false;
+                        } else if found {
+                            continue;
+                        } else if let Some(last) = copies.last_mut() {
+                            *last = pos;
+                        }
+                        found = text_offset >= offset;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        copies.into_iter().min_by_key(|pos| (pos.0 - near.0).abs())
+    }
+
+    /// The first and last drawn content offsets of bullet `id`
+    pub fn text_bounds(&self, id: Id) -> Option<(usize, usize)> {
+        let mut offsets = self.text_of(id).map(|(offset, _)| offset);
+        let first = offsets.next()?;
+        Some((first, offsets.last().unwrap_or(first)))
+    }
+
+    /// Whether anything on `row` can hold the cursor
+    pub fn row_is_browsable(&self, row: i32) -> bool {
+        self.map
+            .get(row as usize)
+            .is_some_and(|r| r.iter().any(|state| state.is_browsable()))
+    }
+
+    pub fn browser(&self, pos: Point) -> Result<Browser<'_>, RasterError> {
         if is_in_bounds(pos, self.max) {
             Ok(Browser { raster: self, pos })
         } else {
-            Err(format!(
-                "cannot get browser for pixel {:?} which is out of bounds {:?}",
-                pos, self.max
-            ))
+            Err(RasterError::OutOfBounds(pos))
         }
     }
 }
@@ -55,13 +113,13 @@ impl Raster {
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum PixelState {
     Empty,
-    Filler(i32),
+    Filler(Id),
     Text {
-        id: i32,
+        id: Id,
         offset: usize, // position in content
     },
-    Bullet(i32),
-    Placeholder(i32), // used for empty bullets
+    Bullet(Id),
+    Placeholder(Id), // used for empty bullets
 }
 
 impl PixelState {
@@ -69,7 +127,7 @@ impl PixelState {
         matches!(self, Text { .. })
     }
 
-    pub fn id(self) -> i32 {
+    pub fn id(self) -> Id {
         match self {
             Filler(id) | Text{ id, .. } | Bullet(id) | Placeholder(id) => id,
             _ => panic!("assumed pixel state has id but was wrong: {:?}", self),
@@ -77,10 +135,7 @@ impl PixelState {
     }
 
     pub fn is_browsable(self) -> bool {
-        match self {
-            Text { .. } | Placeholder(_) => true,
-            _ => false,
-        }
+        matches!(self, Text { .. } | Placeholder(_))
     }
 }
 
@@ -103,25 +158,22 @@ impl<'a> Browser<'a> {
         self.pos
     }
 
+    /// The state of the pixel under the browser. Pixels which have not been rendered are empty.
     pub fn state(&self) -> PixelState {
-        self.raster.get(self.pos).unwrap()
+        self.raster.get(self.pos).unwrap_or(Empty)
     }
 
     /// Moves the Browser in a given direction while the predicate returns true or bounds were hit,
     /// resulting in an error. Calling [pos](Browser::pos) will return the position of the pixel
     /// for which the predicate returned false.
-    pub fn go_while<F>(mut self, dir: Direction, mut predicate: F) -> Result<Browser<'a>, String>
+    pub fn go_while<F>(mut self, dir: Direction, mut predicate: F) -> Result<Browser<'a>, RasterError>
     where
         F: FnMut(PixelState) -> bool,
     {
         let offset = match dir {
             Left => -1,
             Right => 1,
-            _ => {
-                return Err(String::from(
-                    "go_while only defined for Left and Right directions",
-                ))
-            }
+            _ => return Err(RasterError::NotLinear),
         };
         loop {
             if let Some(pos) = linear_move(self.pos, self.raster.max, offset) {
@@ -132,7 +184,7 @@ impl<'a> Browser<'a> {
                     }
                 }
             } else {
-                return Err(String::from("could not browse past bounds"));
+                return Err(RasterError::HitBounds);
             }
         }
         Ok(self)
@@ -143,7 +195,7 @@ impl<'a> Browser<'a> {
         dir: Direction,
         mut count: u32,
         mut predicate: F,
-    ) -> Result<Browser<'a>, String>
+    ) -> Result<Browser<'a>, RasterError>
     where
         F: FnMut(PixelState) -> bool,
     {
@@ -162,7 +214,7 @@ impl<'a> Browser<'a> {
         dir: Direction,
         mut count: u32,
         mut predicate: F,
-    ) -> Result<Browser<'a>, String>
+    ) -> Result<Browser<'a>, RasterError>
     where
         F: FnMut(PixelState) -> bool,
     {
@@ -178,7 +230,7 @@ impl<'a> Browser<'a> {
         }
     }
 
-    pub fn go_no_wrap(mut self, dir: Direction, times: u32) -> Result<Browser<'a>, String> {
+    pub fn go_no_wrap(mut self, dir: Direction, times: u32) -> Result<Browser<'a>, RasterError> {
         let times = times as i32;
         let new_pos = add_points(
             self.pos,
@@ -193,11 +245,11 @@ impl<'a> Browser<'a> {
             self.pos = new_pos;
             Ok(self)
         } else {
-            Err(String::from("hit bounds"))
+            Err(RasterError::HitBounds)
         }
     }
 
-    pub fn go_wrap(self, dir: Direction, times: u32) -> Result<Browser<'a>, String> {
+    pub fn go_wrap(self, dir: Direction, times: u32) -> Result<Browser<'a>, RasterError> {
         self.go_until_count(dir, times, |_| true)
     }
 
@@ -259,15 +311,15 @@ mod tests {
     #[test]
     fn raster_test() {
         let raster = raster_from_vec(vec![
-            vec![Empty, Filler(2), Empty], //
-            vec![Empty, Bullet(2), Empty], //
+            vec![Empty, Filler(Id::from(2)), Empty], //
+            vec![Empty, Bullet(Id::from(2)), Empty], //
         ]);
 
         assert_eq!(raster.get((0, 0)).unwrap(), Empty);
-        assert_eq!(raster.get((0, 1)).unwrap(), Filler(2));
+        assert_eq!(raster.get((0, 1)).unwrap(), Filler(Id::from(2)));
         assert_eq!(raster.get((0, 2)).unwrap(), Empty);
         assert_eq!(raster.get((1, 0)).unwrap(), Empty);
-        assert_eq!(raster.get((1, 1)).unwrap(), Bullet(2));
+        assert_eq!(raster.get((1, 1)).unwrap(), Bullet(Id::from(2)));
         assert_eq!(raster.get((1, 2)).unwrap(), Empty);
     }
 
@@ -283,9 +335,9 @@ mod tests {
 
     #[test]
     fn browser_go_while_continuous() {
-        let sample_text = Text { id: 0, offset: 0 };
+        let sample_text = Text { id: Id::ROOT, offset: 0 };
         let raster = raster_from_vec(vec![
-            vec![Empty, Filler(2), Empty],         //
+            vec![Empty, Filler(Id::from(2)), Empty],         //
             vec![Empty, sample_text, sample_text], //
             vec![sample_text, sample_text, Empty], //
         ]);
@@ -313,10 +365,10 @@ mod tests {
 
     #[test]
     fn browser_go_while_interrupted() {
-        let sample_text = Text { id: 0, offset: 0 };
+        let sample_text = Text { id: Id::ROOT, offset: 0 };
         let raster = raster_from_vec(vec![
-            vec![Bullet(2), Filler(2), sample_text, sample_text, sample_text], //
-            vec![Empty, Bullet(3), Filler(3), sample_text, sample_text],       //
+            vec![Bullet(Id::from(2)), Filler(Id::from(2)), sample_text, sample_text, sample_text], //
+            vec![Empty, Bullet(Id::from(3)), Filler(Id::from(3)), sample_text, sample_text],       //
             vec![Empty, Empty, Empty, sample_text, sample_text],               //
         ]);
 
@@ -333,10 +385,10 @@ mod tests {
 
     #[test]
     fn browser_out_of_bounds() {
-        let sample_text = Text { id: 0, offset: 0 };
+        let sample_text = Text { id: Id::ROOT, offset: 0 };
         let raster = raster_from_vec(vec![
-            vec![Bullet(2), Filler(2), sample_text, sample_text], //
-            vec![Empty, Bullet(3), Filler(3), sample_text],       //
+            vec![Bullet(Id::from(2)), Filler(Id::from(2)), sample_text, sample_text], //
+            vec![Empty, Bullet(Id::from(3)), Filler(Id::from(3)), sample_text],       //
             vec![Empty, Empty, Empty, sample_text],               //
         ]);
 
@@ -354,7 +406,7 @@ mod tests {
 
     #[test]
     fn go_while_one_jump() {
-        let text = Text { id: 0, offset: 0 };
+        let text = Text { id: Id::ROOT, offset: 0 };
         let raster = raster_from_vec(vec![
             vec![text, text], //
             vec![text, text], //
@@ -395,7 +447,7 @@ mod tests {
     }
 
     #[test]
-    fn browser_go_no_wrap_test() -> Result<(), String> {
+    fn browser_go_no_wrap_test() -> Result<(), RasterError> {
         let raster = raster_from_vec(vec![
             vec![Empty, Empty, Empty], //
             vec![Empty, Empty, Empty], //
@@ -1,19 +1,44 @@
+use std::collections::HashSet;
+use std::ffi::CString;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::time::Duration;
 
 use ncurses as n;
 
+use crate::filter::{Filter, Section};
+use crate::markup::{self, Style};
 use crate::raster::PixelState;
 use crate::raster::Raster;
 use crate::raster::{is_in_bounds, linear_move};
+use crate::theme::{self, Role, Theme};
+use crate::time::Date;
 use crate::tree;
+use crate::tree::id::Id;
 
 const CHAR_BULLET: char = '•';
+/// The bullet of a [mirror](tree::Tree::create_mirror)
+const CHAR_MIRROR: char = '◈';
 const CHAR_TRIANGLE_DOWN: char = '▼';
 const CHAR_TRIANGLE_RIGHT: char = '▸';
 const INDENTATION: &str = "  ";
 
 pub type Point = (i32, i32);
+/// Text attributes, a combination of the `A_*` attributes of ncurses
+pub type Attr = n::attr_t;
+
+/// How the tree is drawn, from the `[render]` and `[theme]` sections of the config
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    /// Hides markup markers like `**` except on the active bullet
+    pub conceal_markup: bool,
+    pub theme: Theme,
+    /// Draws only the bullets the filter shows, and the active one
+    pub filter: Option<Filter>,
+    /// The day that bullets due before it are overdue, if they should be highlighted
+    pub today: Option<Date>,
+}
 
 pub struct WindowStore {
     pub editor: Box<dyn Window>,
@@ -27,8 +52,16 @@ pub trait Window {
     fn addstr(&mut self, s: &str);
     fn addch(&mut self, c: char);
     fn move_addstr(&mut self, pos: Point, s: &str);
+    /// Sets the attributes of the text added from now on
+    fn set_attr(&mut self, attr: Attr);
+    /// Sets the color pair of the text added from now on, see [init_colors]. Pair 0 is the
+    /// terminal's colors.
+    fn set_color_pair(&mut self, pair: i16);
     fn refresh(&self);
-    fn getch(&self) -> String;
+    /// Waits for a key press. Returns None if there was no key press before the timeout.
+    fn getch(&self) -> Option<String>;
+    /// Sets how long [getch](Window::getch) waits, forever if None.
+    fn set_timeout(&mut self, timeout: Option<Duration>);
 }
 
 pub struct NCurses(pub n::WINDOW);
@@ -71,12 +104,34 @@ impl Window for NCurses {
         n::mvwaddstr(self.0, pos.0, pos.1, s);
     }
 
+    fn set_attr(&mut self, mut attr: Attr) {
+        // Terminals without italics underline instead
+        if attr & n::A_ITALIC() != 0 && n::termattrs() as Attr & n::A_ITALIC() == 0 {
+            attr = attr & !n::A_ITALIC() | n::A_UNDERLINE();
+        }
+        // Keeps the color pair, which wattrset would reset
+        let (mut old, mut pair) = (0, 0);
+        n::wattr_get(self.0, &mut old, &mut pair);
+        n::wattr_set(self.0, attr, pair);
+    }
+
+    fn set_color_pair(&mut self, pair: i16) {
+        n::wcolor_set(self.0, pair);
+    }
+
     fn refresh(&self) {
         n::wrefresh(self.0);
     }
 
-    fn getch(&self) -> String {
-        n::keyname(n::wgetch(self.0)).expect("wgetch returned unexpected value for keyname")
+    fn getch(&self) -> Option<String> {
+        match n::wgetch(self.0) {
+            n::ERR => None,
+            ch => Some(n::keyname(ch).expect("wgetch returned unexpected value for keyname")),
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        n::wtimeout(self.0, timeout.map_or(-1, |t| t.as_millis() as i32));
     }
 }
 
@@ -84,6 +139,44 @@ pub fn setup_ncurses() {
     // Allows for wide characters
     n::setlocale(n::LcCategory::all, "");
     n::initscr();
+    setup_input();
+}
+
+/// Like [setup_ncurses] but on the terminal device at `path` instead of stdin and stdout, so
+/// that those can be pipes.
+pub fn setup_ncurses_on(path: &str) -> io::Result<()> {
+    let path = CString::new(path)?;
+    let mode = CString::new("r+")?;
+    let file = unsafe { libc::fopen(path.as_ptr(), mode.as_ptr()) };
+    if file.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    n::setlocale(n::LcCategory::all, "");
+    if n::newterm(None, file, file).is_null() {
+        return Err(io::Error::other("could not set up the terminal"));
+    }
+    setup_input();
+    Ok(())
+}
+
+/// Sets up the color pairs of `theme`. Colors the terminal does not have are left as the
+/// terminal's default.
+pub fn init_colors(theme: &Theme) {
+    if !n::has_colors() {
+        return;
+    }
+    n::start_color();
+    n::use_default_colors();
+    for (pair, color) in theme.pairs() {
+        let color = match (color as i32) < n::COLORS() {
+            true => color,
+            false => theme::DEFAULT,
+        };
+        n::init_pair(pair, color, theme::DEFAULT);
+    }
+}
+
+fn setup_input() {
     // Captures signal sequences and no buffer
     n::raw();
     // F keys and arrows
@@ -133,51 +226,175 @@ pub fn clear_remaining_line(win: &mut dyn Window) -> usize {
 
 pub fn addstr_right_aligned(win: &mut dyn Window, txt: &str) {
     let bounds = win.get_max_yx();
-    win.move_addstr((0, bounds.1 - txt.len() as i32), txt);
+    let col = bounds.1 - txt.chars().count() as i32;
+    win.move_addstr((0, col.max(0)), txt);
 }
 
 pub fn tree_render(
     win: &mut dyn Window,
     node: tree::NodeIterator,
-    active_id: i32,
+    active_id: Id,
     insert_offset: usize,
+    options: &RenderOptions,
 ) -> (Raster, (i32, i32)) {
+    // The active bullet is drawn more than once if it is mirrored, and the cursor goes to the
+    // copy closest to where it was
+    let before = win.get_yx();
     win.move_cursor((0, 0));
-    let mut cursor_pos: Option<(i32, i32)> = None;
+    let mut cursor_pos = vec![];
     let mut raster = Raster::new(win.get_max_yx());
-    for child in node.children_iter() {
-        let subtree_pos = subtree_render(win, child, 0, insert_offset, active_id, &mut raster);
-        cursor_pos = cursor_pos.or(subtree_pos);
+    let filter = options.filter.as_ref();
+    let frame = Frame {
+        active_id,
+        insert_offset,
+        options,
+        visible: filter.map(|f| f.visible(node, Some(active_id))),
+        zoomed: filter.is_some_and(|f| f.is_zoomed()),
+        paths: filter.is_some_and(|f| f.shows_paths()),
+    };
+    let sections = match filter {
+        Some(filter) if frame.zoomed => filter.sections(node, Some(active_id)),
+        _ => vec![Section {
+            heading: String::new(),
+            bullets: frame.children(node),
+        }],
+    };
+    for section in sections {
+        if frame.zoomed {
+            title_render(win, &section.heading, &mut raster);
+        }
+        for bullet in section.bullets {
+            cursor_pos.extend(subtree_render(win, bullet, 0, &frame, &mut raster));
+        }
     }
     raster.push_multiple(PixelState::Empty, clear_remaining(win) as u32);
-    (raster, cursor_pos.expect("could not find active node during tree_render"))
+    let cursor_pos = cursor_pos
+        .into_iter()
+        .min_by_key(|pos| (pos.0 - before.0).abs())
+        .expect("could not find active node during tree_render");
+    (raster, cursor_pos)
+}
+
+/// What stays the same while drawing the bullets of a tree
+struct Frame<'a> {
+    active_id: Id,
+    insert_offset: usize,
+    options: &'a RenderOptions,
+    /// The bullets to draw if the tree is filtered
+    visible: Option<HashSet<Id>>,
+    /// Whether the visible bullets are drawn as a flat list, see [Filter]
+    zoomed: bool,
+    /// Whether bullets are followed by the path of their ancestors
+    paths: bool,
+}
+
+impl Frame<'_> {
+    fn is_visible(&self, node: &tree::NodeIterator) -> bool {
+        self.visible.as_ref().is_none_or(|v| v.contains(&node.id()))
+    }
+
+    fn children<'t>(&self, node: tree::NodeIterator<'t>) -> Vec<tree::NodeIterator<'t>> {
+        if self.zoomed && !node.is_root() {
+            return vec![];
+        }
+        node.children_iter()
+            .filter(|child| self.is_visible(child))
+            .collect()
+    }
+}
+
+/// Draws the contents of the ancestors of `node` after it, on what is left of its last line.
+fn path_render(win: &mut dyn Window, node: tree::NodeIterator, raster: &mut Raster) {
+    let mut ancestors: Vec<&str> = std::iter::successors(node.clone().next_parent(), |n| {
+        n.clone().next_parent()
+    })
+    .take_while(|n| !n.is_root())
+    .map(|n| n.content().as_str())
+    .collect();
+    if ancestors.is_empty() {
+        return;
+    }
+    ancestors.reverse();
+    let left = (win.get_max_yx().1 - win.get_yx().1) as usize;
+    let path = format!("  {}", ancestors.join(" › "));
+    let path: String = path.chars().take(left.saturating_sub(1)).collect();
+    win.set_attr(n::A_DIM());
+    win.addstr(&path);
+    win.set_attr(n::A_NORMAL());
+    raster.push_multiple(PixelState::Filler(node.id()), path.chars().count() as u32);
 }
 
-pub fn subtree_render(
+/// Draws the heading of a zoomed view on a line of its own
+fn title_render(win: &mut dyn Window, title: &str, raster: &mut Raster) {
+    let width = win.get_max_yx().1 as usize;
+    let title: String = title.chars().take(width.saturating_sub(1)).collect();
+    win.set_attr(n::A_BOLD());
+    win.addstr(&title);
+    win.set_attr(n::A_NORMAL());
+    raster.push_multiple(PixelState::Empty, title.chars().count() as u32);
+    raster.push_multiple(PixelState::Empty, clear_remaining_line(win) as u32);
+}
+
+fn is_overdue(node: &tree::NodeIterator, today: Option<Date>) -> bool {
+    match today {
+        Some(today) => node.due().is_some_and(|due| due < today),
+        None => false,
+    }
+}
+
+fn subtree_render(
     win: &mut dyn Window,
     node: tree::NodeIterator,
     indentation_lvl: usize,
-    insert_offset: usize,
-    active_id: i32,
+    frame: &Frame,
     raster: &mut Raster,
-) -> Option<(i32, i32)> {
-    let is_active = node.id() == active_id;
-    let mut cursor_pos = render_bullet(
+) -> Vec<(i32, i32)> {
+    let is_active = node.id() == frame.active_id;
+    let options = frame.options;
+    let theme = &options.theme;
+    let look = BulletLook {
+        conceal: options.conceal_markup && !is_active,
+        glyph: theme.pair(match is_active {
+            true => Role::ActiveBullet,
+            false => Role::Bullet(indentation_lvl),
+        }),
+        content: match node.is_completed() {
+            true => theme.pair(Role::Completed),
+            false if is_overdue(&node, options.today) => theme.pair(Role::Overdue),
+            false => 0,
+        },
+        tag: theme.pair(Role::Tag),
+        link: theme.pair(Role::Link),
+        bullet: match node.mirror_of() {
+            Some(_) => CHAR_MIRROR,
+            None => CHAR_BULLET,
+        },
+        titles: match is_active {
+            true => None,
+            false => Some(node),
+        },
+    };
+    let mut cursor_pos: Vec<(i32, i32)> = render_bullet(
         win,
-        &node.content(),
+        node.content(),
         indentation_lvl,
         node.id(),
         match is_active {
-            true => Some(insert_offset),
+            true => Some(frame.insert_offset),
             false => None,
         },
+        look,
         raster,
-    );
+    )
+    .into_iter()
+    .collect();
+    if frame.paths {
+        path_render(win, node, raster);
+    }
     raster.push_multiple(PixelState::Empty, clear_remaining_line(win) as u32);
 
-    for child in node.children_iter() {
-        let subtree_pos = subtree_render(win, child, indentation_lvl + 1, insert_offset, active_id, raster);
-        cursor_pos = cursor_pos.or(subtree_pos);
+    for child in frame.children(node) {
+        cursor_pos.extend(subtree_render(win, child, indentation_lvl + 1, frame, raster));
     }
     cursor_pos
 }
@@ -186,18 +403,24 @@ fn render_bullet(
     win: &mut dyn Window,
     content: &str,
     indentation_lvl: usize,
-    node_id: i32,
+    node_id: Id,
     insert_offset: Option<usize>,
+    look: BulletLook,
     raster: &mut Raster,
 ) -> Option<(i32, i32)> {
-    let mut indentation_str = INDENTATION.repeat(indentation_lvl as usize);
-    win.addstr(&format!("{}{} ", indentation_str, CHAR_BULLET));
+    let mut indentation_str = INDENTATION.repeat(indentation_lvl);
+    win.addstr(&indentation_str);
+    win.set_color_pair(look.glyph);
+    win.addstr(&look.bullet.to_string());
+    win.set_color_pair(0);
+    win.addch(' ');
     raster.push_multiple(PixelState::Empty, indentation_str.len() as u32);
     raster.push(PixelState::Bullet(node_id));
     raster.push(PixelState::Filler(node_id));
 
     indentation_str.push_str("  "); // for filler and bullet
     let limit = (win.get_max_yx().1 - indentation_str.len() as i32) as usize;
+    let cells = content_cells(content, &look);
     if let Some(insert_offset) = insert_offset {
         let insert_index = content
             .len()
@@ -205,7 +428,7 @@ fn render_bullet(
             .expect("offset should not be larger than len, raster generation is probably wrong");
         Some(render_content_slices_active(
             win,
-            split_every_n(content, limit),
+            &cells,
             limit,
             &indentation_str,
             node_id,
@@ -213,43 +436,141 @@ fn render_bullet(
             raster,
         ))
     } else {
-        render_content_slices(
-            win,
-            split_every_n(content, limit),
-            limit,
-            &indentation_str,
-            node_id,
-            raster,
-        );
+        render_content_slices(win, &cells, limit, &indentation_str, node_id, raster);
         None
     }
 }
 
+/// How a bullet is drawn: whether its markup is concealed and the color pairs of its parts
+#[derive(Copy, Clone)]
+struct BulletLook<'a> {
+    conceal: bool,
+    bullet: char,
+    glyph: i16,
+    content: i16,
+    tag: i16,
+    link: i16,
+    /// Where links by id find the content of their bullet to show instead of the id, or None
+    /// to draw links as they are written, like on the active bullet
+    titles: Option<tree::NodeIterator<'a>>,
+}
+
+/// A character of content as it is drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Cell {
+    /// Byte offset in the content
+    offset: usize,
+    c: char,
+    attr: Attr,
+    pair: i16,
+}
+
+/// The characters of `content` with the attributes of their [markup], leaving out the markers if
+/// the look conceals them. Raster pixels get the offset of their cell, so motions still land on
+/// the right characters when markers are hidden. Links by id are drawn as the content of their
+/// bullet, with every cell at an offset in the id.
+fn content_cells(content: &str, look: &BulletLook) -> Vec<Cell> {
+    let mut cells = vec![];
+    for segment in markup::parse(content) {
+        if look.conceal && segment.is_marker {
+            continue;
+        }
+        let range = segment.range.clone();
+        let mut attr = style_attr(segment.style);
+        let pair = match segment.style {
+            style if style.link => {
+                attr |= n::A_UNDERLINE();
+                look.link
+            }
+            style if style.tag => look.tag,
+            _ => look.content,
+        };
+        let title = match (look.titles, segment.is_marker) {
+            (Some(node), false) if segment.style.link => link_title(node, &content[range.clone()]),
+            _ => None,
+        };
+        if let Some(title) = title {
+            for (i, c) in title.chars().enumerate() {
+                let offset = range.start + i.min(range.len() - 1);
+                cells.push(Cell { offset, c, attr, pair });
+            }
+            continue;
+        }
+        for (i, c) in content[range.clone()].char_indices() {
+            cells.push(Cell {
+                offset: range.start + i,
+                c,
+                attr,
+                pair,
+            });
+        }
+    }
+    cells
+}
+
+/// What a link with `text` shows instead, if it goes by id to a bullet that has content
+fn link_title(node: tree::NodeIterator, text: &str) -> Option<String> {
+    let target = node.find(text.trim().parse().ok()?)?;
+    let title = target.content().trim();
+    match title.is_empty() {
+        true => None,
+        false => Some(title.to_string()),
+    }
+}
+
+fn style_attr(style: Style) -> Attr {
+    let mut attr = n::A_NORMAL();
+    if style.bold {
+        attr |= n::A_BOLD();
+    }
+    if style.italic {
+        attr |= n::A_ITALIC();
+    }
+    if style.code {
+        attr |= n::A_REVERSE();
+    }
+    // There is no strikethrough attribute
+    if style.strike {
+        attr |= n::A_DIM();
+    }
+    attr
+}
+
+/// Draws one slice of cells, which is at most one line
+fn add_cells(win: &mut dyn Window, cells: &[Cell]) {
+    let mut buffer = [0; 4];
+    for cell in cells {
+        win.set_attr(cell.attr);
+        win.set_color_pair(cell.pair);
+        win.addstr(cell.c.encode_utf8(&mut buffer));
+    }
+    win.set_attr(n::A_NORMAL());
+    win.set_color_pair(0);
+}
+
 fn render_content_slices(
     win: &mut dyn Window,
-    slices: Vec<&str>,
+    cells: &[Cell],
     limit: usize,
     indentation_str: &str,
-    node_id: i32,
+    node_id: Id,
     raster: &mut Raster,
 ) {
-    if slices.is_empty() {
+    if cells.is_empty() {
         win.addch(' ');
         raster.push(PixelState::Placeholder(node_id));
         return;
     }
-    let mut offset = 0;
-    for slice in slices {
-        win.addstr(slice);
-        for _ in 0..slice.len() {
+    for slice in cells.chunks(limit.max(1)) {
+        add_cells(win, slice);
+        for cell in slice {
             raster.push(PixelState::Text {
                 id: node_id,
-                offset,
+                offset: cell.offset,
             });
-            offset += 1;
         }
         if slice.len() == limit {
-            win.addstr(&indentation_str);
+            win.addstr(indentation_str);
             raster.push_multiple(PixelState::Filler(node_id), indentation_str.len() as u32);
         }
     }
@@ -257,48 +578,46 @@ fn render_content_slices(
 
 fn render_content_slices_active(
     win: &mut dyn Window,
-    slices: Vec<&str>,
+    cells: &[Cell],
     limit: usize,
     indentation_str: &str,
-    node_id: i32,
+    node_id: Id,
     insert_index: usize,
     raster: &mut Raster,
 ) -> (i32, i32) {
-    if slices.is_empty() {
+    if cells.is_empty() {
         let active_pos = win.get_yx();
         win.addch(' ');
         raster.push(PixelState::Placeholder(node_id));
         return active_pos;
     }
     let mut insert_cursor = None;
-    let mut offset = 0;
-    for slice in slices {
+    for slice in cells.chunks(limit.max(1)) {
         // If the insertion index is in the current slice, we have to record the cursor position
-        if offset <= insert_index && insert_index < offset + slice.len() {
-            let before = &slice[0..insert_index - offset];
-            win.addstr(before);
-            insert_cursor = Some(win.get_yx());
-            win.addstr(&slice[insert_index - offset..slice.len()]);
-        } else {
-            win.addstr(slice);
+        match slice.iter().position(|cell| cell.offset == insert_index) {
+            Some(i) => {
+                add_cells(win, &slice[..i]);
+                insert_cursor = Some(win.get_yx());
+                add_cells(win, &slice[i..]);
+            }
+            None => add_cells(win, slice),
         }
-        for _ in 0..slice.len() {
+        for cell in slice {
             raster.push(PixelState::Text {
                 id: node_id,
-                offset,
+                offset: cell.offset,
             });
-            offset += 1;
         }
         if slice.len() == limit {
-            win.addstr(&indentation_str);
+            win.addstr(indentation_str);
             raster.push_multiple(PixelState::Filler(node_id), indentation_str.len() as u32);
         }
     }
-    // Allows an index == len of content which means we are inserting at the end of content
-    if insert_index == offset {
-        win.get_yx()
-    } else {
-        insert_cursor.expect("could not find cursor position in active node")
+    // Allows an index past the last character which means we are inserting at end of content
+    match insert_cursor {
+        Some(pos) => pos,
+        None if cells.last().is_some_and(|cell| insert_index > cell.offset) => win.get_yx(),
+        None => panic!("could not find cursor position in active node"),
     }
 }
 
@@ -322,6 +641,12 @@ pub struct TestWindow {
     pub max: Point,
     pub pos: Point,
     pub screen: Vec<Vec<char>>,
+    /// The attributes each character was drawn with
+    pub attrs: Vec<Vec<Attr>>,
+    /// The color pair each character was drawn with
+    pub colors: Vec<Vec<i16>>,
+    attr: Attr,
+    color: i16,
     pub print_on_refresh: bool,
 }
 
@@ -332,6 +657,10 @@ impl TestWindow {
             max,
             pos: (0, 0),
             screen: vec![vec![' '; max.1 as usize]; max.0 as usize],
+            attrs: vec![vec![n::A_NORMAL(); max.1 as usize]; max.0 as usize],
+            colors: vec![vec![0; max.1 as usize]; max.0 as usize],
+            attr: n::A_NORMAL(),
+            color: 0,
             print_on_refresh,
         }
     }
@@ -398,29 +727,41 @@ impl Window for TestWindow {
 
     fn addch(&mut self, c: char) {
         self.screen[self.pos.0 as usize][self.pos.1 as usize] = c;
+        self.attrs[self.pos.0 as usize][self.pos.1 as usize] = self.attr;
+        self.colors[self.pos.0 as usize][self.pos.1 as usize] = self.color;
         if !self.is_cursor_at_end() {
             self.pos = linear_move(self.pos, self.max, 1)
-                .unwrap_or_else(|| panic!("For character: {}\n{}", c, &self));
+                .unwrap_or_else(|| panic!("For character: {}\n{}", c, self));
         }
     }
 
     fn move_addstr(&mut self, pos: (i32, i32), s: &str) {
         if !is_in_bounds(pos, self.max) {
-            panic!("For pos: {:?}\n{}", pos, &self);
+            panic!("For pos: {:?}\n{}", pos, self);
         }
         self.pos = pos;
         self.addstr(s);
     }
 
+    fn set_attr(&mut self, attr: Attr) {
+        self.attr = attr;
+    }
+
+    fn set_color_pair(&mut self, pair: i16) {
+        self.color = pair;
+    }
+
     fn refresh(&self) {
         if self.print_on_refresh {
             self.print();
         }
     }
 
-    fn getch(&self) -> String {
+    fn getch(&self) -> Option<String> {
         panic!("test window has no function getch since it does not receive input")
     }
+
+    fn set_timeout(&mut self, _timeout: Option<Duration>) {}
 }
 
 impl PartialEq for TestWindow {
@@ -459,25 +800,8 @@ pub mod debug {
 
 #[cfg(test)]
 mod tests {
-    use std::cell::Cell;
-
     use super::*;
-
-    struct TestIdGen {
-        counter: Cell<i32>,
-    }
-
-    impl TestIdGen {
-        fn new() -> TestIdGen {
-            TestIdGen { counter: Cell::new(1) }
-        }
-    }
-
-    impl tree::IdGenerator for TestIdGen {
-        fn gen(&self) -> i32 {
-            (self.counter.get(), self.counter.set(self.counter.get() + 1)).0
-        }
-    }
+    use crate::tree::id::CounterGen;
 
     fn make_windows(max: Point) -> (TestWindow, TestWindow, Raster) {
         (
@@ -487,6 +811,19 @@ mod tests {
         )
     }
 
+    fn cells(content: &str) -> Vec<Cell> {
+        let look = BulletLook {
+            conceal: false,
+            bullet: CHAR_BULLET,
+            glyph: 0,
+            content: 0,
+            tag: 0,
+            link: 0,
+            titles: None,
+        };
+        content_cells(content, &look)
+    }
+
     #[test]
     fn add_indentation_test() {
         assert_eq!(split_every_n("12345", 3), ["123", "45"]);
@@ -505,21 +842,21 @@ mod tests {
     fn render_content_slices_works() {
         let (mut exp, mut win, mut raster) = make_windows((10, 10));
         exp.addstr("hello");
-        render_content_slices(&mut win, vec!["hello"], 10, "  ", 0, &mut raster);
+        render_content_slices(&mut win, &cells("hello"), 10, "  ", Id::ROOT, &mut raster);
         assert_eq!(win, exp);
 
         let (mut exp, mut win, mut raster) = make_windows((10, 10));
         exp.addstr("  ");
         exp.addstr("12345678  9123");
         win.addstr("  ");
-        render_content_slices(&mut win, vec!["12345678", "9123"], 8, "  ", 0, &mut raster);
+        render_content_slices(&mut win, &cells("123456789123"), 8, "  ", Id::ROOT, &mut raster);
         assert_eq!(win, exp);
 
         let (mut exp, mut win, mut raster) = make_windows((10, 10));
         exp.addstr("  ");
         exp.addstr("12345678  ");
         win.addstr("  ");
-        render_content_slices(&mut win, vec!["12345678"], 8, "  ", 0, &mut raster);
+        render_content_slices(&mut win, &cells("12345678"), 8, "  ", Id::ROOT, &mut raster);
         assert_eq!(win, exp);
     }
 
@@ -528,7 +865,7 @@ mod tests {
         let (mut exp, mut win, mut raster) = make_windows((10, 10));
         exp.addstr("hello");
         assert_eq!(
-            render_content_slices_active(&mut win, vec!["hello"], 10, "  ", 0, 0, &mut raster),
+            render_content_slices_active(&mut win, &cells("hello"), 10, "  ", Id::ROOT, 0, &mut raster),
             (0, 0)
         );
         assert_eq!(win, exp);
@@ -541,7 +878,7 @@ mod tests {
         // |insert_index| equal to len is allowed because during normal insertion, cursor is one
         // past the length of the string
         assert_eq!(
-            render_content_slices_active(&mut win, vec!["hello"], 10, "  ", 0, 5, &mut raster),
+            render_content_slices_active(&mut win, &cells("hello"), 10, "  ", Id::ROOT, 5, &mut raster),
             (0, 5)
         );
         assert_eq!(win, exp);
@@ -552,7 +889,7 @@ mod tests {
         let (mut exp, mut win, mut raster) = make_windows((10, 10));
         exp.addstr("hello");
         assert_eq!(
-            render_content_slices_active(&mut win, vec!["hello"], 10, "  ", 0, 2, &mut raster),
+            render_content_slices_active(&mut win, &cells("hello"), 10, "  ", Id::ROOT, 2, &mut raster),
             (0, 2)
         );
         assert_eq!(win, exp);
@@ -566,10 +903,10 @@ mod tests {
         assert_eq!(
             render_content_slices_active(
                 &mut win,
-                vec!["12345678", "1234"],
+                &cells("123456781234"),
                 8,
                 "  ",
-                0,
+                Id::ROOT,
                 0,
                 &mut raster
             ),
@@ -586,10 +923,10 @@ mod tests {
         assert_eq!(
             render_content_slices_active(
                 &mut win,
-                vec!["12345678", "1234"],
+                &cells("123456781234"),
                 8,
                 "  ",
-                0,
+                Id::ROOT,
                 12,
                 &mut raster
             ),
@@ -603,11 +940,82 @@ mod tests {
         let (mut exp, mut win, _raster) = make_windows((10, 10));
         exp.addch(CHAR_BULLET);
         clear_remaining(&mut exp);
-        let tree = tree::Tree::new(Box::new(TestIdGen::new()));
-        tree_render(&mut win, tree.root_iter(), tree.get_active_id(), 0);
+        let tree = tree::Tree::new(Box::new(CounterGen::new()));
+        tree_render(&mut win, tree.root_iter(), tree.get_active_id(), 0, &RenderOptions::default());
         assert_eq!(win, exp);
     }
 
+    #[test]
+    fn markup_is_drawn_with_attributes() {
+        let mut tree = tree::Tree::new(Box::new(CounterGen::new()));
+        tree.get_mut_active_content().push_str("a **b** `c`");
+        tree.create_sibling();
+        let options = RenderOptions {
+            conceal_markup: true,
+            ..RenderOptions::default()
+        };
+        let mut win = TestWindow::new((3, 20), false);
+        let (raster, _) = tree_render(&mut win, tree.root_iter(), Id::from(2), 0, &options);
+        let line: String = win.screen[0].iter().collect();
+        assert_eq!(line.trim_end(), "• a b c");
+        assert_eq!(win.attrs[0][2], n::A_NORMAL());
+        assert_eq!(win.attrs[0][4], n::A_BOLD());
+        assert_eq!(win.attrs[0][6], n::A_REVERSE());
+        let text = |offset| Some(PixelState::Text { id: Id::from(1), offset });
+        assert_eq!(raster.get((0, 4)), text(4));
+        assert_eq!(raster.get((0, 6)), text(9));
+
+        // The active bullet shows its markers
+        let (raster, pos) = tree_render(&mut win, tree.root_iter(), Id::from(1), 3, &options);
+        let line: String = win.screen[0].iter().collect();
+        assert_eq!(line.trim_end(), "• a **b** `c`");
+        assert_eq!(win.attrs[0][4], n::A_BOLD());
+        assert_eq!((pos, raster.get(pos)), ((0, 10), text(8)));
+    }
+
+    #[test]
+    fn links_show_their_bullet() {
+        let mut tree = tree::Tree::new(Box::new(CounterGen::new()));
+        tree.get_mut_active_content().push_str("target");
+        tree.create_sibling();
+        tree.get_mut_active_content().push_str("[[1]] [[none]]");
+        tree.create_sibling();
+        let options = RenderOptions::default();
+        let mut win = TestWindow::new((3, 30), false);
+        let (raster, _) = tree_render(&mut win, tree.root_iter(), Id::from(3), 0, &options);
+        let line: String = win.screen[1].iter().collect();
+        assert_eq!(line.trim_end(), "• [[target]] [[none]]");
+        assert_eq!(win.colors[1][4], options.theme.pair(Role::Link));
+        assert_eq!(win.attrs[1][4], n::A_UNDERLINE());
+        // Every character of the content shown is on the id
+        let text = |offset| Some(PixelState::Text { id: Id::from(2), offset });
+        assert_eq!(raster.get((1, 4)), text(2));
+        assert_eq!(raster.get((1, 9)), text(2));
+        assert_eq!(raster.get((1, 10)), text(3));
+        assert_eq!(raster.find_text(Id::from(2), 6, (0, 0)), Some((1, 13)));
+    }
+
+    #[test]
+    fn theme_colors_bullets() {
+        let mut tree = tree::Tree::new(Box::new(CounterGen::new()));
+        tree.get_mut_active_content().push_str("a #b");
+        tree.create_sibling();
+        tree.indent(true).unwrap();
+        tree.get_mut_active_content().push('c');
+        tree.set_completed(true);
+        tree.create_sibling();
+        let options = RenderOptions::default();
+        let theme = &options.theme;
+        let mut win = TestWindow::new((4, 20), false);
+        tree_render(&mut win, tree.root_iter(), Id::from(3), 0, &options);
+        assert_eq!(win.colors[0][0], theme.pair(Role::Bullet(0)));
+        assert_eq!(win.colors[0][2], 0);
+        assert_eq!(win.colors[0][4], theme.pair(Role::Tag));
+        assert_eq!(win.colors[1][2], theme.pair(Role::Bullet(1)));
+        assert_eq!(win.colors[1][4], theme.pair(Role::Completed));
+        assert_eq!(win.colors[2][2], theme.pair(Role::ActiveBullet));
+    }
+
     #[test]
     fn clear_remaining_line_test() {
         let mut win = TestWindow::new((10, 10), false);
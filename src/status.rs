@@ -1,17 +1,73 @@
+use ncurses as n;
+
 use crate::editor::Cursor;
 use crate::render;
 use crate::render::Window;
+use crate::theme::{Role, Theme};
 
-pub fn render_status(win: &mut dyn Window, cursor: Cursor, msg: &str) {
+/// Draws the mode followed by `info` about the active bullet, and `msg` on the right. When they
+/// do not both fit, `msg` is cut to the width after the mode and `info` to the space it leaves.
+pub fn render_status(win: &mut dyn Window, cursor: Cursor, theme: &Theme, info: &str, msg: &str) {
     let bounds = win.get_max_yx();
     win.move_addstr((0, 0), &" ".repeat(bounds.1 as usize));
-    win.move_addstr(
-        (0, 0),
-        match cursor {
-            Cursor::Command(_) => "COMMAND",
-            Cursor::Insert(_) => "INSERT",
-        },
-    );
-    render::addstr_right_aligned(&mut *win, msg);
+    let (mode, role) = match cursor {
+        Cursor::Command(_) => ("COMMAND", Role::CommandMode),
+        Cursor::Insert(_) => ("INSERT", Role::InsertMode),
+    };
+    win.set_attr(n::A_BOLD());
+    win.set_color_pair(theme.pair(role));
+    win.move_addstr((0, 0), mode);
+    win.set_attr(n::A_NORMAL());
+    win.set_color_pair(0);
+    let width = (bounds.1 as usize).saturating_sub(mode.len() + 2);
+    let msg: String = msg.chars().take(width).collect();
+    let room = width.saturating_sub(msg.chars().count() + 1);
+    let info: String = info.chars().take(room).collect();
+    if !info.is_empty() {
+        win.move_addstr((0, mode.len() as i32 + 2), &info);
+    }
+    render::addstr_right_aligned(&mut *win, &msg);
     win.refresh();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::InsertState;
+    use crate::render::TestWindow;
+
+    #[test]
+    fn colors_mode() {
+        let theme = Theme::default();
+        let mut win = TestWindow::new((1, 20), false);
+        render_status(
+            &mut win,
+            Cursor::Insert(InsertState {
+                pos: (0, 0),
+                offset: 0,
+            }),
+            &theme,
+            "info",
+            "msg",
+        );
+        assert_eq!(win.colors[0][0], theme.pair(Role::InsertMode));
+        assert_eq!(win.attrs[0][0], n::A_BOLD());
+        assert_eq!(win.screen[0][8..12].iter().collect::<String>(), "info");
+        assert_eq!(win.colors[0][19], 0);
+        assert_eq!(win.attrs[0][19], n::A_NORMAL());
+    }
+
+    #[test]
+    fn cuts_what_does_not_fit() {
+        let mut win = TestWindow::new((1, 20), false);
+        let cursor = Cursor::Insert(InsertState {
+            pos: (0, 0),
+            offset: 0,
+        });
+        let theme = Theme::default();
+        render_status(&mut win, cursor, &theme, "info", "a long message");
+        assert_eq!(win.screen[0].iter().collect::<String>(), "INSERT  a long messa");
+        render_status(&mut win, cursor, &theme, "info", "msg");
+        assert_eq!(win.screen[0].iter().collect::<String>(), "INSERT  info     msg");
+    }
+}
@@ -0,0 +1,137 @@
+use crate::editor::Mark;
+use crate::error::{EditorError, TreeError};
+use crate::handlers::SEPARATORS;
+use crate::tree::id::Id;
+use crate::tree::Tree;
+
+/// The part of the outline an operator like `d`, `c` or `y` acts on, typed after the operator
+/// or between `v` and the operator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextObject {
+    /// `iw`: the word or run of separators under the cursor
+    InnerWord,
+    /// `aw`: the word under the cursor and the separators around it
+    AWord,
+    /// `ib`: the content of the bullet
+    InnerBullet,
+    /// `ab`: the bullet and all of its children
+    ABullet,
+    /// `it`: the children of the bullet but not the bullet itself
+    InnerTree,
+}
+
+/// What a text object resolved to in the tree
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Range {
+    /// The bytes `start..end` of a bullet's content
+    Text { id: Id, start: usize, end: usize },
+    Subtree(Id),
+    Children(Id),
+}
+
+impl TextObject {
+    /// Parses the keys typed after an operator. Returns None while more keys are needed.
+    pub fn parse(keys: &[String]) -> Result<Option<TextObject>, EditorError> {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let object = match keys.as_slice() {
+            [] | ["i"] | ["a"] => return Ok(None),
+            ["i", "w"] => TextObject::InnerWord,
+            ["a", "w"] => TextObject::AWord,
+            ["i", "b"] => TextObject::InnerBullet,
+            ["a", "b"] => TextObject::ABullet,
+            ["i", "t"] => TextObject::InnerTree,
+            _ => return Err(EditorError::UnknownTextObject(keys.concat())),
+        };
+        Ok(Some(object))
+    }
+
+    /// Resolves the object around `mark`
+    pub fn resolve(self, tree: &Tree, mark: Mark) -> Result<Range, TreeError> {
+        let itr = match tree.node_iter(mark.id) {
+            Some(itr) => itr,
+            None => return Err(TreeError::NotFound(mark.id)),
+        };
+        let content = itr.content();
+        let (start, end) = match self {
+            TextObject::InnerWord => word_range(content, mark.offset, false),
+            TextObject::AWord => word_range(content, mark.offset, true),
+            TextObject::InnerBullet => (0, content.len()),
+            TextObject::ABullet => return Ok(Range::Subtree(mark.id)),
+            TextObject::InnerTree => return Ok(Range::Children(mark.id)),
+        };
+        Ok(Range::Text {
+            id: mark.id,
+            start,
+            end,
+        })
+    }
+}
+
+/// The bytes of the run of word or separator characters around the byte `offset`. With
+/// `around` a word also takes the separators after it, or before it if there are none after, and
+/// separators take the word after them.
+fn word_range(content: &str, offset: usize, around: bool) -> (usize, usize) {
+    let chars: Vec<(usize, char)> = content.char_indices().collect();
+    if chars.is_empty() {
+        return (0, 0);
+    }
+    // Indices below are of chars, and only turned into bytes at the end
+    let byte = |i: usize| chars.get(i).map_or(content.len(), |c| c.0);
+    let offset = chars.iter().rposition(|c| c.0 <= offset).unwrap_or(0);
+    let is_sep = |i: usize| SEPARATORS.contains(&chars[i].1);
+    let run_end = |from: usize| {
+        (from..chars.len())
+            .find(|&i| is_sep(i) != is_sep(from))
+            .unwrap_or(chars.len())
+    };
+    let start = (0..offset)
+        .rev()
+        .find(|&i| is_sep(i) != is_sep(offset))
+        .map_or(0, |i| i + 1);
+    let end = run_end(offset);
+    let (start, end) = match (around, is_sep(offset), end < chars.len()) {
+        (false, _, _) => (start, end),
+        (true, _, true) => (start, run_end(end)),
+        (true, false, false) => {
+            let before = (0..start).rev().find(|&i| !is_sep(i)).map_or(0, |i| i + 1);
+            (before, end)
+        }
+        (true, true, false) => (start, end),
+    };
+    (byte(start), byte(end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(s: &str) -> Vec<String> {
+        s.chars().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(TextObject::parse(&keys("i")), Ok(None));
+        assert_eq!(TextObject::parse(&keys("ab")), Ok(Some(TextObject::ABullet)));
+        assert_eq!(TextObject::parse(&keys("it")), Ok(Some(TextObject::InnerTree)));
+        assert!(TextObject::parse(&keys("x")).is_err());
+        assert!(TextObject::parse(&keys("ix")).is_err());
+    }
+
+    #[test]
+    fn word_range_test() {
+        assert_eq!(word_range("", 0, false), (0, 0));
+        assert_eq!(word_range("ab cd  ef", 4, false), (3, 5));
+        assert_eq!(word_range("ab cd  ef", 5, false), (5, 7));
+        assert_eq!(word_range("ab cd  ef", 0, true), (0, 3));
+        assert_eq!(word_range("ab cd  ef", 4, true), (3, 7));
+        // No separators after the last word, so the ones before are taken
+        assert_eq!(word_range("ab cd  ef", 8, true), (5, 9));
+        // Separators take the word after them
+        assert_eq!(word_range("ab cd  ef", 6, true), (5, 9));
+        // Offsets are in bytes, and `é` takes two
+        assert_eq!(word_range("éé ab", 6, false), (5, 7));
+        assert_eq!(word_range("éé ab", 2, true), (0, 5));
+        assert_eq!(word_range("ab éé", 3, true), (2, 7));
+    }
+}
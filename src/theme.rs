@@ -0,0 +1,147 @@
+use ncurses as n;
+
+/// An ncurses color number, or [DEFAULT] for the terminal's own color
+pub type Color = i16;
+
+pub const DEFAULT: Color = -1;
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// What a piece of the screen shows, which decides its color
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The bullet glyph of a bullet this deep, counting top level bullets as 0
+    Bullet(usize),
+    ActiveBullet,
+    Completed,
+    /// Content of bullets whose due date has passed
+    Overdue,
+    Tag,
+    /// A `[[link]]` to another bullet
+    Link,
+    CommandMode,
+    InsertMode,
+}
+
+/// Foreground colors for each [Role], read from the `[theme]` section of the config
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    /// Colors of bullet glyphs by depth. Deeper bullets start over from the first color.
+    pub bullets: Vec<Color>,
+    pub active: Color,
+    pub completed: Color,
+    pub overdue: Color,
+    pub tag: Color,
+    pub link: Color,
+    pub command_mode: Color,
+    pub insert_mode: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            bullets: vec![n::COLOR_BLUE, n::COLOR_MAGENTA, n::COLOR_CYAN],
+            active: n::COLOR_YELLOW,
+            completed: 8, // bright black
+            overdue: n::COLOR_RED,
+            tag: n::COLOR_GREEN,
+            link: n::COLOR_CYAN,
+            command_mode: n::COLOR_BLUE,
+            insert_mode: n::COLOR_GREEN,
+        }
+    }
+}
+
+impl Theme {
+    /// A theme that leaves everything in the terminal's colors
+    pub fn plain() -> Theme {
+        Theme {
+            bullets: vec![],
+            active: DEFAULT,
+            completed: DEFAULT,
+            overdue: DEFAULT,
+            tag: DEFAULT,
+            link: DEFAULT,
+            command_mode: DEFAULT,
+            insert_mode: DEFAULT,
+        }
+    }
+
+    /// The color pair to draw `role` with. Pair 0 is the terminal's colors.
+    pub fn pair(&self, role: Role) -> i16 {
+        match role {
+            Role::ActiveBullet => 1,
+            Role::Completed => 2,
+            Role::Tag => 3,
+            Role::CommandMode => 4,
+            Role::InsertMode => 5,
+            Role::Overdue => 6,
+            Role::Link => 7,
+            Role::Bullet(_) if self.bullets.is_empty() => 0,
+            Role::Bullet(depth) => 8 + (depth % self.bullets.len()) as i16,
+        }
+    }
+
+    /// Every color pair with its foreground color, to be set up with `init_pair`
+    pub fn pairs(&self) -> Vec<(i16, Color)> {
+        let mut pairs = vec![
+            (self.pair(Role::ActiveBullet), self.active),
+            (self.pair(Role::Completed), self.completed),
+            (self.pair(Role::Tag), self.tag),
+            (self.pair(Role::CommandMode), self.command_mode),
+            (self.pair(Role::InsertMode), self.insert_mode),
+            (self.pair(Role::Overdue), self.overdue),
+            (self.pair(Role::Link), self.link),
+        ];
+        for (depth, color) in self.bullets.iter().enumerate() {
+            pairs.push((self.pair(Role::Bullet(depth)), *color));
+        }
+        pairs
+    }
+}
+
+/// Reads a color name like `blue`, `bright-blue` or `default`, or a color number of terminals
+/// with 256 colors.
+pub fn parse_color(name: &str) -> Result<Color, String> {
+    let lower = name.to_lowercase();
+    if lower == "default" {
+        return Ok(DEFAULT);
+    }
+    let (base, offset) = match lower.strip_prefix("bright-") {
+        Some(base) => (base, 8),
+        None => (lower.as_str(), 0),
+    };
+    if let Some(i) = NAMES.iter().position(|&n| n == base) {
+        return Ok(i as Color + offset);
+    }
+    match lower.parse::<u8>() {
+        Ok(number) => Ok(number as Color),
+        Err(_) => Err(format!("unknown color: {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("Red"), Ok(n::COLOR_RED));
+        assert_eq!(parse_color("bright-black"), Ok(8));
+        assert_eq!(parse_color("default"), Ok(DEFAULT));
+        assert_eq!(parse_color("208"), Ok(208));
+        assert!(parse_color("bright-208").is_err());
+        assert!(parse_color("256").is_err());
+    }
+
+    #[test]
+    fn bullet_pairs_repeat_by_depth() {
+        let theme = Theme::default();
+        assert_eq!(theme.pair(Role::Bullet(0)), theme.pair(Role::Bullet(3)));
+        assert_ne!(theme.pair(Role::Bullet(0)), theme.pair(Role::Bullet(1)));
+        let pairs: Vec<i16> = theme.pairs().iter().map(|p| p.0).collect();
+        assert_eq!(pairs, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(Theme::plain().pair(Role::Bullet(2)), 0);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    cell::Cell,
+    fmt::{Display, Formatter},
+    rc::Rc,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+/// Short names of the days of the week, from Monday
+pub const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// A moment in UTC, to the second. Written like `2026-10-18T09:30:00Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+/// A day of the proleptic Gregorian calendar. Written like `2026-10-18`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// Where a tree gets the time of its edits and the time zone to show them in, so that tests
+/// can choose them. Times are stored in UTC, but days start at midnight local time.
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+    /// Seconds that local time is ahead of UTC at `time`
+    fn utc_offset(&self, time: Timestamp) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Timestamp(seconds as i64)
+    }
+
+    fn utc_offset(&self, time: Timestamp) -> i64 {
+        local_offset(time)
+    }
+}
+
+/// Stays at the time it is set to, which is enough for tests. Clones share the time, so a test
+/// can keep one to move the time of a clock it gave away. Local time is UTC unless an offset
+/// is given.
+#[derive(Clone)]
+pub struct FixedClock {
+    now: Rc<Cell<Timestamp>>,
+    offset: i64,
+}
+
+impl FixedClock {
+    pub fn new(now: Timestamp) -> FixedClock {
+        FixedClock {
+            now: Rc::new(Cell::new(now)),
+            offset: 0,
+        }
+    }
+
+    pub fn with_offset(self, offset: i64) -> FixedClock {
+        FixedClock { offset, ..self }
+    }
+
+    pub fn set(&self, now: Timestamp) {
+        self.now.set(now);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> Timestamp {
+        self.now.get()
+    }
+
+    fn utc_offset(&self, _: Timestamp) -> i64 {
+        self.offset
+    }
+}
+
+/// Seconds that local time is ahead of UTC at `time`, by the time zone of the system. UTC if
+/// the system cannot tell.
+pub fn local_offset(time: Timestamp) -> i64 {
+    let seconds = time.0 as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    match unsafe { libc::localtime_r(&seconds, &mut tm) }.is_null() {
+        true => 0,
+        false => tm.tm_gmtoff as i64,
+    }
+}
+
+impl Timestamp {
+    pub fn from_seconds(seconds: i64) -> Timestamp {
+        Timestamp(seconds)
+    }
+
+    pub fn seconds(self) -> i64 {
+        self.0
+    }
+
+    /// The start of `date`
+    pub fn at_midnight(date: Date) -> Timestamp {
+        Timestamp(date.days() * SECONDS_PER_DAY)
+    }
+
+    /// The day in UTC
+    pub fn date(self) -> Date {
+        Date::from_days(self.0.div_euclid(SECONDS_PER_DAY))
+    }
+
+    /// The day in a time zone `offset` seconds ahead of UTC
+    pub fn local_date(self, offset: i64) -> Date {
+        Timestamp(self.0 + offset).date()
+    }
+
+    /// The hour, minute and second of the day
+    pub fn time_of_day(self) -> (u32, u32, u32) {
+        let seconds = self.0.rem_euclid(SECONDS_PER_DAY) as u32;
+        (seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+
+    /// Like `2026-10-18 09:30`, for showing to the user in a time zone `offset` seconds ahead of
+    /// UTC
+    pub fn to_short_string(self, offset: i64) -> String {
+        let local = Timestamp(self.0 + offset);
+        let (hour, minute, _) = local.time_of_day();
+        format!("{} {:02}:{:02}", local.date(), hour, minute)
+    }
+}
+
+impl Date {
+    /// None if there is no such day, like February 30th
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Date> {
+        match (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month) {
+            true => Some(Date { year, month, day }),
+            false => None,
+        }
+    }
+
+    /// Days since 1970-01-01, from Howard Hinnant's `days_from_civil`
+    pub fn days(self) -> i64 {
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// The inverse of [days](Date::days)
+    pub fn from_days(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (year_of_era + era * 400 + (month <= 2) as i64) as i32;
+        Date { year, month, day }
+    }
+
+    pub fn add_days(self, days: i64) -> Date {
+        Date::from_days(self.days() + days)
+    }
+
+    /// The day of the week, from 0 for Monday to 6 for Sunday
+    pub fn weekday(self) -> usize {
+        // 1970-01-01 was a Thursday
+        (self.days() + 3).rem_euclid(7) as usize
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (hour, minute, second) = self.time_of_day();
+        write!(
+            f,
+            "{}T{:02}:{:02}:{:02}Z",
+            self.date(),
+            hour,
+            minute,
+            second
+        )
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Date, String> {
+        let invalid = || format!("invalid date: {}", s);
+        let mut parts = s.splitn(3, '-');
+        let mut next = |len: usize| {
+            parts
+                .next()
+                .filter(|p| p.len() == len && p.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|p| p.parse::<u32>().ok())
+        };
+        let (year, month, day) = match (next(4), next(2), next(2)) {
+            (Some(year), Some(month), Some(day)) => (year, month, day),
+            _ => return Err(invalid()),
+        };
+        Date::new(year as i32, month, day).ok_or_else(invalid)
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Timestamp, String> {
+        let invalid = || format!("invalid time: {}", s);
+        let (date, time) = s
+            .strip_suffix('Z')
+            .and_then(|s| s.split_once('T'))
+            .ok_or_else(invalid)?;
+        let date: Date = date.parse().map_err(|_| invalid())?;
+        let fields: Vec<u32> = time
+            .split(':')
+            .map(|f| match f.len() == 2 {
+                true => f.parse().ok(),
+                false => None,
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        match fields.as_slice() {
+            &[hour, minute, second] if hour < 24 && minute < 60 && second < 60 => {
+                let seconds = (hour * 3600 + minute * 60 + second) as i64;
+                Ok(Timestamp(Timestamp::at_midnight(date).0 + seconds))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_and_days() {
+        assert_eq!(Date::from_days(0), Date::new(1970, 1, 1).unwrap());
+        let date = Date::new(2024, 2, 29).unwrap();
+        assert_eq!(Date::from_days(date.days()), date);
+        assert_eq!(date.add_days(1), Date::new(2024, 3, 1).unwrap());
+        assert_eq!(WEEKDAYS[date.weekday()], "Thu");
+        assert_eq!(Date::new(1969, 12, 31).unwrap().days(), -1);
+        assert_eq!(Date::new(2023, 2, 29), None);
+        assert_eq!("2026-10-18".parse(), Ok(Date::new(2026, 10, 18).unwrap()));
+        assert!("2026-1-18".parse::<Date>().is_err());
+        assert!("2026-13-01".parse::<Date>().is_err());
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        let time: Timestamp = "2026-10-18T09:30:05Z".parse().unwrap();
+        assert_eq!(time.to_string(), "2026-10-18T09:30:05Z");
+        assert_eq!(time.to_short_string(0), "2026-10-18 09:30");
+        assert_eq!(time.date(), Date::new(2026, 10, 18).unwrap());
+        // Local time can be on another day than UTC
+        assert_eq!(time.to_short_string(15 * 3600), "2026-10-19 00:30");
+        assert_eq!(time.local_date(-10 * 3600), Date::new(2026, 10, 17).unwrap());
+        assert!(local_offset(time).abs() <= 14 * 3600);
+        assert_eq!(
+            Timestamp::from_seconds(-1).to_string(),
+            "1969-12-31T23:59:59Z"
+        );
+        assert!("2026-10-18T24:00:00Z".parse::<Timestamp>().is_err());
+        assert!("2026-10-18 09:30:05".parse::<Timestamp>().is_err());
+    }
+}
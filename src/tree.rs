@@ -1,18 +1,34 @@
+pub mod check;
+pub mod id;
+mod links;
 mod node;
-
-use self::node::{Link, Node};
+pub mod op;
+pub mod searches;
+pub mod sort;
+pub mod tags;
+
+use self::id::Id;
+use self::node::{Arena, Index, Node};
+use self::op::Op;
+use self::searches::SavedSearch;
+use self::tags::TagIndex;
+use crate::error::TreeError;
+use crate::due;
+use crate::time::{Clock, Date, SystemClock, Timestamp};
 use std::{
-    cell::{Ref, RefCell, RefMut},
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     fmt::{Display, Formatter},
-    ops::{Deref, DerefMut},
 };
 use Dir::*;
 
 pub trait IdGenerator {
-    fn gen(&self) -> i32;
+    fn gen(&self) -> Id;
+
+    /// Makes sure `id`, which came from somewhere else like a journal, is never generated.
+    fn reserve(&self, _id: Id) {}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dir {
     Above,
     Below,
@@ -24,191 +40,451 @@ pub enum Dir {
 /// - There is at least one root node and one child of the root node
 /// - No two nodes have the same id
 /// - All nodes but root nodes have a parent
+/// - `id_table` has an entry for every node in the tree and no others
+/// - Mirrors have no children and show an original that is in the tree and is not a mirror
+/// - No bullet is drawn under itself, counting what mirrors show
+///
+/// They can be verified with [check_invariants](Tree::check_invariants).
 pub struct Tree {
-    active: Link,
-    root: Link,
+    arena: Arena,
+    active: Index,
+    root: Index,
     generator: Box<dyn IdGenerator>,
-    id_table: HashMap<i32, Link>,
+    id_table: HashMap<Id, Index>,
+    /// Edits made since the last [take_ops](Tree::take_ops), if they are being recorded
+    ops: Option<Vec<Op>>,
+    /// Node whose content may have changed since it was last recorded
+    dirty: Option<Id>,
+    tags: TagIndex,
+    /// Node whose content may have changed since it was last indexed
+    stale_tags: Option<Id>,
+    searches: Vec<SavedSearch>,
+    clock: Box<dyn Clock>,
+    /// The time of the op being [applied](Tree::apply), used instead of the clock
+    pinned_time: Option<Timestamp>,
 }
 
 impl Tree {
     pub fn new(generator: Box<dyn IdGenerator>) -> Tree {
+        let mut arena = Arena::new();
         let mut id_table = HashMap::new();
 
-        let root = Node::new_link(0, None);
-        id_table.insert(root.borrow().id, root.clone());
+        let root = arena.alloc(Node::new(Id::ROOT));
+        id_table.insert(Id::ROOT, root);
 
-        let first = Node::new_link(generator.gen(), Some(root.clone()));
-        id_table.insert(first.borrow().id, first.clone());
-        root.borrow_mut().children.push(first.clone());
+        let clock = Box::new(SystemClock);
+        let first_id = generator.gen();
+        let now = Some(clock.now());
+        let first = arena.alloc(Node {
+            created: now,
+            modified: now,
+            ..Node::new(first_id)
+        });
+        id_table.insert(first_id, first);
+        arena.insert_child_last(root, first);
 
         Tree {
+            arena,
             active: first,
             root,
             generator,
             id_table,
+            ops: None,
+            dirty: None,
+            tags: TagIndex::default(),
+            stale_tags: None,
+            searches: vec![],
+            clock,
+            pinned_time: None,
+        }
+    }
+
+    /// Makes edits from now on take their time from `clock`.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    fn now(&self) -> Timestamp {
+        self.pinned_time.unwrap_or_else(|| self.clock.now())
+    }
+
+    /// The current day in local time, by the clock of the tree
+    pub fn today(&self) -> Date {
+        let now = self.clock.now();
+        now.local_date(self.clock.utc_offset(now))
+    }
+
+    /// `time` as it is shown to the user, in local time
+    pub fn local_time_string(&self, time: Timestamp) -> String {
+        time.to_short_string(self.clock.utc_offset(time))
+    }
+
+    /// Writes the relative dates in the active content as the days they are today, see
+    /// [resolve_dates](crate::due::resolve_dates). Returns whether anything was rewritten.
+    pub fn date_active(&mut self) -> bool {
+        match due::resolve_dates(self.get_active_content(), self.today()) {
+            Some(content) => {
+                *self.get_mut_active_content() = content;
+                true
+            }
+            None => false,
         }
     }
 
+    /// Marks the node at `index` as modified now.
+    fn touch(&mut self, index: Index) {
+        let now = self.now();
+        self.arena.get_mut(index).modified = Some(now);
+    }
+
     pub fn create_sibling_above(&mut self) {
-        let node = Node::new_link(self.generator.gen(), None);
-        self.insert_node(node.clone(), Above);
-        self.active = node;
+        let id = self.generator.gen();
+        self.insert_new(id, Above, None);
     }
 
     pub fn create_sibling(&mut self) {
-        let node = Node::new_link(self.generator.gen(), None);
-        self.insert_node(node.clone(), Below);
-        self.active = node;
+        let id = self.generator.gen();
+        self.insert_new(id, Below, None);
     }
 
-    pub fn insert_subtree(&mut self, subtree: Subtree, dir: Dir) {
-        let subtree = subtree
-            .make_unique(self.generator.as_ref());
-        let root_id = subtree.root.borrow().id;
+    /// Puts a mirror of the bullet `of` next to the active node and activates it. The mirror
+    /// shows the content and children of the original, so edits through either show in both.
+    /// A mirror of a mirror shows the same original.
+    pub fn create_mirror(&mut self, of: Id, dir: Dir) -> Result<(), TreeError> {
+        let original = self.source(self.get_node(of).ok_or(TreeError::NotFound(of))?);
+        let parent = self.arena.get(self.active).parent.unwrap();
+        if self.reaches(original, parent) {
+            return Err(TreeError::MirrorCycle);
+        }
+        let id = self.generator.gen();
+        self.insert_new(id, dir, Some(self.arena.get(original).id));
+        Ok(())
+    }
 
-        self.insert_node(subtree.root, dir);
-        self.activate(root_id)
-            .expect("could not find subtree root right after insertion");
+    /// Makes a node next to the active one, as a mirror of `mirror` if given.
+    fn insert_new(&mut self, id: Id, dir: Dir, mirror: Option<Id>) {
+        let now = self.now();
+        let at = self.get_active_id();
+        self.record(match mirror {
+            Some(of) => Op::CreateMirror {
+                at,
+                dir,
+                id,
+                of,
+                time: now,
+            },
+            None => Op::CreateSibling {
+                at,
+                dir,
+                id,
+                time: now,
+            },
+        });
+        let node = self.arena.alloc(Node {
+            created: Some(now),
+            modified: Some(now),
+            mirror,
+            ..Node::new(id)
+        });
+        self.id_table.insert(id, node);
+        self.arena.insert_relative(self.active, dir, node);
+        self.active = node;
+    }
 
-        for n in self.active_iter().traverse(TraversalType::Level) {
-            self.register_in_table(n.node);
+    pub fn insert_subtree(&mut self, subtree: Subtree, dir: Dir) {
+        let mut subtree = subtree.make_unique(self.generator.as_ref());
+        self.settle_mirrors(&mut subtree);
+        if self.ops.is_some() {
+            self.record(Op::InsertSubtree {
+                at: self.get_active_id(),
+                dir,
+                nodes: subtree.flatten(),
+            });
         }
+        self.graft(subtree, dir);
     }
 
-    fn insert_node(&mut self, node: Link, dir: Dir) {
-        let parent = self.active.borrow().parent.clone().unwrap();
-        node.borrow_mut().parent = Some(parent.clone());
-        parent
-            .borrow_mut()
-            .insert_child_relative(self.active.borrow().id, dir, node.clone())
-            .expect("child not found in its own parent");
-        self.register_in_table(node);
+    /// Keeps the mirrors of a subtree about to be put next to the active node if they can show
+    /// their original there. The others become plain bullets with the content they showed when
+    /// they were copied.
+    fn settle_mirrors(&self, subtree: &mut Subtree) {
+        let parent = self.arena.get(self.active).parent.unwrap();
+        let is_mirror: HashMap<Id, bool> = subtree
+            .root_itr()
+            .traverse(TraversalType::Level)
+            .map(|n| (n.id(), n.mirror_of().is_some()))
+            .collect();
+        let indices: Vec<Index> = subtree.arena.indices().collect();
+        for index in indices {
+            let node = subtree.arena.get_mut(index);
+            let of = match node.mirror {
+                Some(of) => of,
+                None => continue,
+            };
+            let keep = match self.get_node(of) {
+                Some(original) => {
+                    self.arena.get(original).mirror.is_none() && !self.reaches(original, parent)
+                }
+                None => is_mirror.get(&of) == Some(&false),
+            };
+            match keep {
+                true => {
+                    node.content.clear();
+                    node.completed = false;
+                }
+                false => node.mirror = None,
+            }
+        }
     }
 
-    fn register_in_table(&mut self, node: Link) {
-        let id = node.borrow().id;
-        self.id_table.insert(id, node);
+    /// Inserts a subtree whose ids are known not to be in the tree yet
+    fn graft(&mut self, subtree: Subtree, dir: Dir) {
+        let root = subtree.arena.copy_into(subtree.root, &mut self.arena);
+
+        self.arena.insert_relative(self.active, dir, root);
+        self.active = root;
+
+        let indices: Vec<Index> = NodeIterator::new(&self.arena, root)
+            .traverse(TraversalType::Level)
+            .map(|n| n.index)
+            .collect();
+        for index in indices {
+            self.id_table.insert(self.arena.get(index).id, index);
+        }
+        self.index_tags(root);
     }
 
     /// Indents the active node under its up sibling. Returns errors if there is no such sibling.
     /// If `first` then the active node will be placed as the first child of the sibling, otherwise
-    /// last.
-    pub fn indent(&mut self, first: bool) -> Result<(), String> {
-        let sibling = match self.active.borrow().get_sibling(Above) {
-            Some(x) => x,
-            None => return Err(String::from("already at max indentation level")),
+    /// last. Under a mirror means under its original.
+    pub fn indent(&mut self, first: bool) -> Result<(), TreeError> {
+        let sibling = match self.arena.get_sibling(self.active, Above) {
+            Some(x) => self.source(x),
+            None => return Err(TreeError::MaxIndent),
         };
-        // Remove from previous parent
-        let parent = self.active.borrow().parent.clone().unwrap();
-        parent.borrow_mut().remove_child(self.active.borrow().id);
-
-        // Establish parent-child relationship with former sibling
+        if self.reaches(self.active, sibling) {
+            return Err(TreeError::MirrorCycle);
+        }
+        self.record(Op::Indent {
+            at: self.get_active_id(),
+            first,
+            time: self.now(),
+        });
+        self.touch(self.active);
+        self.arena.detach(self.active);
         match first {
-            true => sibling.borrow_mut().insert_child_first(self.active.clone()),
-            false => sibling.borrow_mut().insert_child_last(self.active.clone()),
+            true => self.arena.insert_child_first(sibling, self.active),
+            false => self.arena.insert_child_last(sibling, self.active),
         }
-        self.active.borrow_mut().parent = Some(sibling);
         Ok(())
     }
 
-    pub fn unindent(&mut self) -> Result<(), String> {
-        // Break parent-child relationship
-        let parent = self.active.borrow().parent.clone().unwrap();
-        if parent.borrow().is_root() {
-            return Err(String::from("cannot unindent further"));
+    pub fn unindent(&mut self) -> Result<(), TreeError> {
+        let parent = self.arena.get(self.active).parent.unwrap();
+        if self.arena.get(parent).is_root() {
+            return Err(TreeError::MinIndent);
         }
-        parent.borrow_mut().remove_child(self.active.borrow().id);
-
-        // Reinsert in grandparent
-        let grandparent = parent.borrow().parent.clone().unwrap();
-        grandparent
-            .borrow_mut()
-            .insert_child_relative(parent.borrow().id, Below, self.active.clone())
-            .expect("could not find parent in grandparent while unindenting");
-        self.active.borrow_mut().parent = Some(grandparent);
+        self.record(Op::Unindent {
+            at: self.get_active_id(),
+            time: self.now(),
+        });
+        self.touch(self.active);
+        self.arena.detach(self.active);
+        self.arena.insert_relative(parent, Below, self.active);
         Ok(())
     }
 
-    pub fn activate(&mut self, id: i32) -> Result<(), String> {
-        self.active = self
-            .get_node(id)
-            .cloned()
-            .ok_or("could not find id to activate".to_string())?;
+    pub fn activate(&mut self, id: Id) -> Result<(), TreeError> {
+        self.active = self.get_node(id).ok_or(TreeError::NotFound(id))?;
         Ok(())
     }
 
-    pub fn delete(&mut self) -> Result<(), String> {
-        let active_link = self.active.clone();
-        let active = active_link.borrow();
-        let parent = active.parent.as_ref().unwrap();
+    /// Deletes the active node and its descendants. A mirror is deleted on its own, without what
+    /// it shows. Bullets that have mirrors elsewhere are not deleted but move to the place of
+    /// their first mirror, which they replace.
+    pub fn delete(&mut self) -> Result<(), TreeError> {
+        let deleted = self.active;
+        let parent = self.arena.get(deleted).parent.unwrap();
+        let is_root_parent = self.arena.get(parent).is_root();
 
         match (
-            parent.borrow(),
-            active.get_sibling(Above),
-            active.get_sibling(Below),
+            self.arena.get_sibling(deleted, Above),
+            self.arena.get_sibling(deleted, Below),
         ) {
-            (p, _, _) if p.is_root() && p.children.len() == 1 => {
-                return Err(String::from("cannot delete last node"))
-            }
-            (p, None, None) if !p.is_root() => self.active = parent.clone(),
-            (_, _, Some(below)) => self.active = below,
-            (_, Some(above), None) => self.active = above,
-            _ => panic!(),
+            (None, None) if is_root_parent => return Err(TreeError::LastNode),
+            (None, None) => self.active = parent,
+            (_, Some(below)) => self.active = below,
+            (Some(above), None) => self.active = above,
         }
+        self.record(Op::Delete {
+            at: self.arena.get(deleted).id,
+        });
 
         // Get rid of old node and children
-        parent.borrow_mut().remove_child(active.id);
-        let ids: Vec<i32> = NodeIterator::new(active_link.clone())
+        self.arena.detach(deleted);
+        self.rescue_mirrored(deleted);
+        if self.arena.get(deleted).parent.is_some() {
+            return Ok(());
+        }
+        let indices: Vec<Index> = NodeIterator::new(&self.arena, deleted)
             .traverse(TraversalType::PostOrder)
-            .map(|n| n.id())
+            .map(|n| n.index)
             .collect();
-        for id in ids {
+        for index in indices {
+            let id = self.arena.remove(index).id;
+            self.unindex_tags(id);
             self.id_table
                 .remove(&id)
-                .expect(&format!("could not find node to remove: {}", id));
+                .unwrap_or_else(|| panic!("could not find node to remove: {}", id));
         }
 
         Ok(())
     }
 
+    /// Moves the bullets under the detached node `deleted` that are mirrored in the tree into the
+    /// place of their first mirror, removing the mirror.
+    fn rescue_mirrored(&mut self, deleted: Index) {
+        let mut first_mirrors: HashMap<Id, Index> = HashMap::new();
+        for node in NodeIterator::new(&self.arena, self.root).traverse(TraversalType::PreOrder) {
+            if let Some(of) = node.node().mirror {
+                first_mirrors.entry(of).or_insert(node.index);
+            }
+        }
+        if first_mirrors.is_empty() {
+            return;
+        }
+        let mut stack = vec![deleted];
+        while let Some(index) = stack.pop() {
+            let mirror = match first_mirrors.get(&self.arena.get(index).id) {
+                Some(&mirror) => mirror,
+                None => {
+                    stack.extend(self.arena.children(index));
+                    continue;
+                }
+            };
+            self.arena.detach(index);
+            self.arena.insert_relative(mirror, Below, index);
+            self.arena.detach(mirror);
+            let id = self.arena.remove(mirror).id;
+            self.id_table.remove(&id);
+            if self.active == mirror {
+                self.active = index;
+            }
+        }
+    }
+
     fn get_id_gen(&self) -> &dyn IdGenerator {
         self.generator.as_ref()
     }
 
+    /// Copies the active node and its descendants. Mirrors in the copy keep the content they
+    /// show, for when they are pasted where their original is gone.
     pub fn get_subtree(&self) -> Subtree {
-        let active = self.active.borrow();
-        let sibling = active.get_sibling(Above);
-        let parent = active.parent.clone();
-        Subtree {
-            root: self.active.clone(),
-            parent,
-            above_sibling: sibling,
+        let mut arena = Arena::new();
+        let root = self.arena.copy_into(self.active, &mut arena);
+        let mirrors: Vec<(Index, Index)> = arena
+            .indices()
+            .filter_map(|i| Some((i, self.get_node(arena.get(i).mirror?)?)))
+            .collect();
+        for (copy, original) in mirrors {
+            let original = self.arena.get(original);
+            let copy = arena.get_mut(copy);
+            copy.content = original.content.clone();
+            copy.completed = original.completed;
         }
+        Subtree { arena, root }
+    }
+
+    /// The content of the active node, which is marked as modified. For a mirror it is the
+    /// content of its original.
+    pub fn get_mut_active_content(&mut self) -> &mut String {
+        self.mark_dirty();
+        self.mark_tags_stale();
+        let source = self.source(self.active);
+        self.touch(source);
+        &mut self.arena.get_mut(source).content
+    }
+
+    pub fn set_completed(&mut self, completed: bool) {
+        let source = self.source(self.active);
+        self.record(Op::SetCompleted {
+            id: self.arena.get(source).id,
+            completed,
+            time: self.now(),
+        });
+        self.touch(source);
+        self.arena.get_mut(source).completed = completed;
     }
 
-    pub fn get_mut_active_content(&mut self) -> impl DerefMut<Target = String> + '_ {
-        RefMut::map(self.active.borrow_mut(), |n| &mut n.content)
+    pub fn get_active_content(&self) -> &String {
+        &self.arena.get(self.source(self.active)).content
     }
 
-    pub fn get_active_content(&self) -> impl Deref<Target = String> + '_ {
-        Ref::map(self.active.borrow(), |n| &n.content)
+    pub fn get_active_id(&self) -> Id {
+        self.arena.get(self.active).id
     }
 
-    pub fn get_active_id(&self) -> i32 {
-        self.active.borrow().id
+    /// The id of the node whose content is edited through the active one, see
+    /// [source](Tree::source)
+    fn get_active_source_id(&self) -> Id {
+        self.arena.get(self.source(self.active)).id
     }
 
-    fn get_node(&self, id: i32) -> Option<&Link> {
-        self.id_table.get(&id)
+    /// The node whose content and children the node at `index` shows: its original if it is a
+    /// mirror, or itself
+    fn source(&self, index: Index) -> Index {
+        match self.arena.get(index).mirror {
+            Some(id) => self.get_node(id).unwrap_or(index),
+            None => index,
+        }
     }
 
-    pub fn root_iter(&self) -> NodeIterator {
-        NodeIterator::new(self.root.clone())
+    /// Whether `to` is `from` or is drawn somewhere under it, following mirrors to what they
+    /// show. Putting `from` under `to` would then draw it under itself forever.
+    fn reaches(&self, from: Index, to: Index) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(index) = stack.pop() {
+            if index == to {
+                return true;
+            }
+            if !seen.insert(index) {
+                continue;
+            }
+            match self.source(index) {
+                source if source != index => stack.push(source),
+                _ => stack.extend(self.arena.children(index)),
+            }
+        }
+        false
     }
 
-    pub fn active_iter(&self) -> NodeIterator {
-        NodeIterator::new(self.active.clone())
+    fn get_node(&self, id: Id) -> Option<Index> {
+        self.id_table.get(&id).copied()
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        self.get_node(id).is_some()
+    }
+
+    pub fn root_iter(&self) -> NodeIterator<'_> {
+        self.iter_at(self.root)
+    }
+
+    pub fn active_iter(&self) -> NodeIterator<'_> {
+        self.iter_at(self.active)
+    }
+
+    pub fn node_iter(&self, id: Id) -> Option<NodeIterator<'_>> {
+        self.get_node(id).map(|index| self.iter_at(index))
+    }
+
+    /// An iterator that reads mirrors as what they show
+    fn iter_at(&self, index: Index) -> NodeIterator<'_> {
+        NodeIterator {
+            id_table: Some(&self.id_table),
+            ..NodeIterator::new(&self.arena, index)
+        }
     }
 }
 
@@ -221,21 +497,20 @@ impl Display for Tree {
 fn fmt_tree(
     itr: NodeIterator,
     indent: usize,
-    active_id: i32,
+    active_id: Id,
     f: &mut Formatter<'_>,
 ) -> std::fmt::Result {
-    let node = itr.node.borrow();
-    let active_str = match node.id == active_id {
+    let active_str = match itr.id() == active_id {
         true => "ACTIVE ",
         false => "",
     };
-    write!(
+    writeln!(
         f,
-        "{}{}. {}{}\n",
+        "{}{}. {}{}",
         "\t".repeat(indent),
-        node.id,
+        itr.id(),
         active_str,
-        itr.node.borrow().content
+        itr.content()
     )?;
     for child in itr.children_iter() {
         fmt_tree(child, indent + 1, active_id, f)?;
@@ -243,112 +518,191 @@ fn fmt_tree(
     Ok(())
 }
 
+/// A copy of a node and its descendants, independent of the tree it was taken from.
 #[derive(Debug, Clone)]
 pub struct Subtree {
-    root: Link,
-    parent: Option<Link>,
-    above_sibling: Option<Link>,
+    arena: Arena,
+    root: Index,
 }
 
 impl Subtree {
-    pub fn root_itr(&self) -> NodeIterator {
-        NodeIterator::new(self.root.clone())
+    pub fn root_itr(&self) -> NodeIterator<'_> {
+        NodeIterator::new(&self.arena, self.root)
     }
 
-    pub fn ids(&self) -> Vec<i32> {
+    pub fn ids(&self) -> Vec<Id> {
         self.root_itr()
             .traverse(TraversalType::Level)
             .map(|n| n.id())
             .collect()
     }
 
+    /// Gives every node a new id. Mirrors of nodes in the subtree follow them to their new ids.
     fn make_unique(mut self, id_gen: &dyn IdGenerator) -> Subtree {
-        self.root = make_unique_links(self.root, None);
-        for node_itr in self.root_itr().traverse(TraversalType::PostOrder) {
-            node_itr.node.borrow_mut().id = id_gen.gen();
+        let indices: Vec<Index> = self
+            .root_itr()
+            .traverse(TraversalType::PostOrder)
+            .map(|n| n.index)
+            .collect();
+        let mut new_ids = HashMap::new();
+        for &index in &indices {
+            let id = id_gen.gen();
+            new_ids.insert(std::mem::replace(&mut self.arena.get_mut(index).id, id), id);
+        }
+        for index in indices {
+            let node = self.arena.get_mut(index);
+            if let Some(new_id) = node.mirror.and_then(|id| new_ids.get(&id)) {
+                node.mirror = Some(*new_id);
+            }
         }
         self
     }
 }
 
-fn make_unique_links(node: Link, parent: Option<Link>) -> Link {
-    let new_link = Link::new(RefCell::new(node.borrow().clone()));
-    new_link.borrow_mut().parent = parent;
-    new_link.borrow_mut().children = node.borrow()
-        .children
-        .iter()
-        .map(|n| {
-            make_unique_links(n.clone(), Some(new_link.clone()))
-        })
-        .collect();
-    new_link
+/// A view of one node that can move to its relatives. Those of a tree read a mirror as its
+/// original, with the content and children of the original, while keeping the id and place of
+/// the mirror.
+#[derive(Copy, Clone)]
+pub struct NodeIterator<'a> {
+    arena: &'a Arena,
+    index: Index,
+    /// Where to find the originals of mirrors. Without it, nodes are read as they are stored.
+    id_table: Option<&'a HashMap<Id, Index>>,
 }
 
-pub struct NodeIterator {
-    node: Link,
-}
+impl<'a> NodeIterator<'a> {
+    fn new(arena: &'a Arena, index: Index) -> NodeIterator<'a> {
+        NodeIterator {
+            arena,
+            index,
+            id_table: None,
+        }
+    }
+
+    /// Another node of the same tree
+    fn at(&self, index: Index) -> NodeIterator<'a> {
+        NodeIterator { index, ..*self }
+    }
+
+    fn node(&self) -> &'a Node {
+        self.arena.get(self.index)
+    }
+
+    /// The index of the node whose content and children are read, see [Tree::source]
+    fn source_index(&self) -> Index {
+        match (self.node().mirror, self.id_table) {
+            (Some(id), Some(table)) => table.get(&id).copied().unwrap_or(self.index),
+            _ => self.index,
+        }
+    }
+
+    fn source(&self) -> &'a Node {
+        self.arena.get(self.source_index())
+    }
+
+    pub fn content(&self) -> &'a String {
+        &self.source().content
+    }
+
+    pub fn id(&self) -> Id {
+        self.node().id
+    }
+
+    /// Another bullet of the tree by its id, for iterators that came from a [Tree]
+    pub fn find(&self, id: Id) -> Option<NodeIterator<'a>> {
+        let index = *self.id_table?.get(&id)?;
+        Some(self.at(index))
+    }
 
-impl NodeIterator {
-    fn new(node: Link) -> NodeIterator {
-        NodeIterator { node }
+    /// The id of the original if this is a mirror
+    pub fn mirror_of(&self) -> Option<Id> {
+        self.node().mirror
     }
 
-    pub fn content(&self) -> impl Deref<Target = String> + '_ {
-        Ref::map(self.node.borrow(), |n| &n.content)
+    pub fn is_completed(&self) -> bool {
+        self.source().completed
     }
 
-    pub fn id(&self) -> i32 {
-        self.node.borrow().id
+    pub fn created(&self) -> Option<Timestamp> {
+        self.source().created
     }
 
-    pub fn children_iter(&self) -> impl Iterator<Item = NodeIterator> {
-        self.node
-            .borrow()
-            .children
-            .clone()
-            .into_iter()
-            .map(|n| Self::new(n))
+    pub fn modified(&self) -> Option<Timestamp> {
+        self.source().modified
     }
 
-    pub fn traverse(self, traversal: TraversalType) -> impl Iterator<Item = NodeIterator> {
+    /// The [due date](crate::due::due_date) written in the content
+    pub fn due(&self) -> Option<Date> {
+        due::due_date(self.content())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.node().is_root()
+    }
+
+    /// The children of the node, or of the original of a mirror
+    pub fn children_iter(&self) -> impl Iterator<Item = NodeIterator<'a>> {
+        let this = *self;
+        self.arena.children(self.source_index()).map(move |i| this.at(i))
+    }
+
+    /// Visits the node and what is drawn under it, so bullets under mirrors are visited again
+    /// under each mirror.
+    pub fn traverse(self, traversal: TraversalType) -> impl Iterator<Item = NodeIterator<'a>> {
         TreeTraversalIterator::new(self, traversal)
     }
 
-    pub fn next_parent(&mut self) -> Option<NodeIterator> {
-        self.node
-            .borrow()
-            .parent
-            .clone()
-            .map(|n| NodeIterator::new(n))
+    /// The descendants of the node in pre-order, each once: mirrors are visited but not what
+    /// they show.
+    pub fn bullets(self) -> impl Iterator<Item = NodeIterator<'a>> {
+        let id_table = self.id_table;
+        NodeIterator {
+            id_table: None,
+            ..self
+        }
+        .traverse(TraversalType::PreOrder)
+        .skip(1)
+        .map(move |n| NodeIterator { id_table, ..n })
+    }
+
+    /// The parent of the node itself, so not a mirror it is drawn under
+    pub fn next_parent(&mut self) -> Option<NodeIterator<'a>> {
+        self.node().parent.map(|i| self.at(i))
     }
 
-    pub fn next_sibling(&mut self, dir: Dir) -> Option<NodeIterator> {
-        self.node
-            .borrow()
-            .get_sibling(dir)
-            .map(|n| NodeIterator::new(n.clone()))
+    pub fn next_sibling(&mut self, dir: Dir) -> Option<NodeIterator<'a>> {
+        self.arena.get_sibling(self.index, dir).map(|i| self.at(i))
     }
 }
 
-struct TreeTraversalIterator {
-    deque: VecDeque<(NodeIterator, bool)>,
+struct TreeTraversalIterator<'a> {
+    deque: VecDeque<(NodeIterator<'a>, bool)>,
     traversal: TraversalType,
 }
 
 pub enum TraversalType {
+    /// Nodes before their descendants, in the order they are drawn
+    PreOrder,
     PostOrder,
     Level,
 }
 
-impl TreeTraversalIterator {
-    fn new(itr: NodeIterator, traversal: TraversalType) -> TreeTraversalIterator {
+impl<'a> TreeTraversalIterator<'a> {
+    fn new(itr: NodeIterator<'a>, traversal: TraversalType) -> TreeTraversalIterator<'a> {
         TreeTraversalIterator {
             deque: vec![(itr, false)].into_iter().collect(),
             traversal,
         }
     }
 
-    fn post_order(&mut self) -> Option<NodeIterator> {
+    fn pre_order(&mut self) -> Option<NodeIterator<'a>> {
+        let (node, _) = self.deque.pop_back()?;
+        let children: Vec<NodeIterator> = node.children_iter().collect();
+        self.deque.extend(children.into_iter().rev().map(|n| (n, false)));
+        Some(node)
+    }
+
+    fn post_order(&mut self) -> Option<NodeIterator<'a>> {
         let node = match self.deque.pop_back() {
             None => return None,
             Some((itr, true)) => return Some(itr),
@@ -362,7 +716,7 @@ impl TreeTraversalIterator {
         self.post_order()
     }
 
-    fn level(&mut self) -> Option<NodeIterator> {
+    fn level(&mut self) -> Option<NodeIterator<'a>> {
         let node = match self.deque.pop_front() {
             None => return None,
             Some((itr, true)) => return Some(itr),
@@ -379,11 +733,12 @@ impl TreeTraversalIterator {
     }
 }
 
-impl Iterator for TreeTraversalIterator {
-    type Item = NodeIterator;
+impl<'a> Iterator for TreeTraversalIterator<'a> {
+    type Item = NodeIterator<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.traversal {
+            TraversalType::PreOrder => self.pre_order(),
             TraversalType::PostOrder => self.post_order(),
             TraversalType::Level => self.level(),
         }
@@ -392,45 +747,184 @@ impl Iterator for TreeTraversalIterator {
 
 #[cfg(test)]
 mod tests {
-    use std::cell::Cell;
-
+    use super::id::CounterGen;
     use super::*;
+    use crate::time::FixedClock;
 
-    struct TestGen {
-        current: Cell<i32>,
+    pub(super) fn new_test_tree() -> Tree {
+        Tree::new(Box::new(CounterGen::new()))
     }
 
-    impl TestGen {
-        fn new() -> TestGen {
-            TestGen {
-                current: Cell::new(1),
-            }
-        }
+    /// The id a [CounterGen] hands out `n`th
+    pub(super) fn id(n: u128) -> Id {
+        Id::from(n)
     }
 
-    impl IdGenerator for TestGen {
-        fn gen(&self) -> i32 {
-            (self.current.get(), self.current.set(self.current.get() + 1)).0
-        }
+    fn get_children_ids(tree: &Tree, id: u128) -> Vec<u128> {
+        tree.node_iter(id.into()).unwrap().children_iter().map(get_itr_id).collect()
     }
 
-    fn new_test_tree() -> Tree {
-        Tree::new(Box::new(TestGen::new()))
+    fn get_parent_id(tree: &Tree, id: u128) -> Option<u128> {
+        tree.node_iter(id.into()).unwrap().next_parent().map(get_itr_id)
     }
 
-    fn get_id(link: &Link) -> i32 {
-        link.borrow().id
+    fn get_sibling_id(tree: &Tree, id: u128, dir: Dir) -> Option<u128> {
+        tree.node_iter(id.into()).unwrap().next_sibling(dir).map(get_itr_id)
     }
 
-    fn get_children_ids(link: &Link) -> Vec<i32> {
-        link.borrow().children.iter().map(get_id).collect()
+    #[test]
+    fn edits_set_times() {
+        let time = |s: &str| Some(s.parse::<Timestamp>().unwrap());
+        let clock = FixedClock::new(time("2026-10-18T09:00:00Z").unwrap());
+        let mut tree = new_test_tree();
+        tree.set_clock(Box::new(clock.clone()));
+        tree.create_sibling(); // id = 2
+        let times = |tree: &Tree, n| {
+            let node = tree.node_iter(id(n)).unwrap();
+            (node.created(), node.modified())
+        };
+        let created = time("2026-10-18T09:00:00Z");
+        assert_eq!(times(&tree, 2), (created, created));
+
+        clock.set(time("2026-10-18T10:00:00Z").unwrap());
+        tree.get_mut_active_content().push('a');
+        assert_eq!(times(&tree, 2), (created, time("2026-10-18T10:00:00Z")));
+        clock.set(time("2026-10-18T11:00:00Z").unwrap());
+        tree.indent(false).unwrap();
+        assert_eq!(times(&tree, 2), (created, time("2026-10-18T11:00:00Z")));
+        clock.set(time("2026-10-18T12:00:00Z").unwrap());
+        tree.set_completed(true);
+        assert_eq!(times(&tree, 2), (created, time("2026-10-18T12:00:00Z")));
+        assert_ne!(times(&tree, 1).1, time("2026-10-18T12:00:00Z"));
     }
 
-    fn get_itr_id(itr: NodeIterator) -> i32 {
-        itr.id()
+    #[test]
+    fn mirrors_show_original() {
+        // 1. a
+        //   2. b
+        // 3. mirror of 1
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content().push('a');
+        tree.create_sibling(); // id = 2
+        tree.get_mut_active_content().push('b');
+        tree.indent(false).unwrap();
+        tree.activate(id(1)).unwrap();
+        tree.create_mirror(id(1), Below).unwrap(); // id = 3
+        let mirror = tree.active_iter();
+        assert_eq!(mirror.mirror_of(), Some(id(1)));
+        assert_eq!(mirror.content(), "a");
+        assert_eq!(get_children_ids(&tree, 3), [2]);
+        let shown: Vec<u128> = tree
+            .root_iter()
+            .traverse(TraversalType::PreOrder)
+            .map(get_itr_id)
+            .collect();
+        assert_eq!(shown, [0, 1, 2, 3, 2]);
+        let once: Vec<u128> = tree.root_iter().bullets().map(get_itr_id).collect();
+        assert_eq!(once, [1, 2, 3]);
+
+        // Edits through the mirror go to the original
+        tree.get_mut_active_content().push('!');
+        tree.set_completed(true);
+        let original = tree.node_iter(id(1)).unwrap();
+        assert_eq!((original.content().as_str(), original.is_completed()), ("a!", true));
+
+        // New children go under the original
+        tree.create_sibling(); // id = 4
+        tree.indent(false).unwrap();
+        assert_eq!(get_children_ids(&tree, 1), [2, 4]);
+        assert_eq!(get_children_ids(&tree, 3), [2, 4]);
+        assert_eq!(tree.check_invariants(), []);
     }
 
-    fn get_tree_ids(tree: &Tree) -> Vec<i32> {
+    #[test]
+    fn mirrors_cannot_show_themselves() {
+        // 1.
+        //   2.
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap();
+        assert_eq!(tree.create_mirror(id(1), Below), Err(TreeError::MirrorCycle));
+        tree.activate(id(1)).unwrap();
+        tree.create_mirror(id(2), Below).unwrap(); // id = 3
+        tree.create_mirror(id(3), Below).unwrap(); // id = 4, of 2
+        assert_eq!(tree.active_iter().mirror_of(), Some(id(2)));
+        tree.create_mirror(id(1), Below).unwrap(); // id = 5
+
+        // A mirror of 1 under a mirror of its child 2, and 1 under a mirror of itself
+        assert_eq!(tree.indent(false), Err(TreeError::MirrorCycle));
+        tree.activate(id(1)).unwrap();
+        tree.create_mirror(id(1), Above).unwrap(); // id = 6
+        tree.activate(id(1)).unwrap();
+        assert_eq!(tree.indent(false), Err(TreeError::MirrorCycle));
+        assert_eq!(get_children_ids(&tree, 0), [6, 1, 3, 4, 5]);
+        assert_eq!(tree.check_invariants(), []);
+    }
+
+    #[test]
+    fn deleting_mirrored_bullets() {
+        // 1. a
+        //   2. b
+        // 3. mirror of 1
+        // 4. mirror of 2
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content().push('a');
+        tree.create_sibling(); // id = 2
+        tree.get_mut_active_content().push('b');
+        tree.indent(false).unwrap();
+        tree.activate(id(1)).unwrap();
+        tree.create_mirror(id(1), Below).unwrap(); // id = 3
+        tree.create_mirror(id(2), Below).unwrap(); // id = 4
+
+        // A mirror is deleted alone
+        tree.delete().unwrap();
+        assert!(tree.contains(id(2)));
+        assert_eq!(get_children_ids(&tree, 0), [1, 3]);
+
+        // The original moves to its mirror
+        tree.activate(id(1)).unwrap();
+        tree.delete().unwrap();
+        assert_eq!(get_children_ids(&tree, 0), [1]);
+        assert_eq!(get_children_ids(&tree, 1), [2]);
+        assert!(!tree.contains(id(3)));
+        assert_eq!(tree.get_active_id(), id(1));
+        assert_eq!(tree.check_invariants(), []);
+    }
+
+    #[test]
+    fn pasted_mirrors() {
+        // 1. a
+        // 2. mirror of 1
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content().push('a');
+        tree.create_mirror(id(1), Below).unwrap(); // id = 2
+        let mirror = tree.get_subtree();
+        assert_eq!(mirror.root_itr().content(), "a");
+
+        tree.insert_subtree(mirror.clone(), Below); // id = 3
+        assert_eq!(tree.active_iter().mirror_of(), Some(id(1)));
+        tree.get_mut_active_content().push('!');
+        assert_eq!(tree.node_iter(id(2)).unwrap().content(), "a!");
+
+        // Under its original it would show itself, and without it there is nothing to show
+        tree.activate(id(1)).unwrap();
+        tree.create_sibling(); // id = 4
+        tree.indent(false).unwrap();
+        tree.insert_subtree(mirror.clone(), Below); // id = 5
+        assert_eq!(tree.active_iter().mirror_of(), None);
+        assert_eq!(tree.get_active_content(), "a");
+        tree.activate(id(1)).unwrap();
+        tree.delete().unwrap();
+        tree.insert_subtree(mirror, Below); // id = 6
+        assert_eq!(tree.active_iter().mirror_of(), Some(id(1)));
+        assert_eq!(tree.check_invariants(), []);
+    }
+
+    fn get_itr_id(itr: NodeIterator) -> u128 {
+        itr.id().into()
+    }
+
+    fn get_tree_ids(tree: &Tree) -> Vec<u128> {
         tree.root_iter()
             .traverse(TraversalType::Level)
             .map(get_itr_id)
@@ -440,75 +934,49 @@ mod tests {
     #[test]
     fn new_tree_has_active() {
         let tree = new_test_tree();
-        assert_eq!(tree.get_active_id(), 1);
+        assert_eq!(tree.get_active_id(), id(1));
     }
 
     #[test]
-    fn make_unique_links_test() {
-        let init_root = Node::new_link(0, None);
-        let init_first = Node::new_link(1, Some(init_root.clone()));
-        init_root.borrow_mut().insert_child_last(init_first.clone());
-
-        let final_root = make_unique_links(init_root.clone(), None);
-        let final_first = final_root.borrow().children[0].clone();
-        // Init tree
-        // 5.
-        //   6.
-        // Final tree
-        // 0.
-        //   1.
-        init_first.borrow_mut().id = 5;
-        init_root.borrow_mut().id = 6;
+    fn get_subtree_copies_nodes() {
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap(); // 2 under 1
+        tree.activate(id(1)).unwrap();
+        let subtree = tree.get_subtree();
 
-        assert_eq!(init_root.borrow().children[0].borrow().id, 5);
-        assert_eq!(init_first.borrow().parent.clone().unwrap().borrow().id, 6);
+        tree.get_mut_active_content().push_str("changed");
+        tree.activate(id(2)).unwrap();
+        tree.delete().unwrap();
 
-        assert_eq!(final_root.borrow().children[0].borrow().id, 1);
-        assert_eq!(final_first.borrow().parent.clone().unwrap().borrow().id, 0);
+        assert_eq!(subtree.ids(), [id(1), id(2)]);
+        assert_eq!(subtree.root_itr().content(), "");
     }
 
     #[test]
     fn subtree_make_unique_ids_test() {
-        let test_gen = TestGen::new();
-        let node = Node::new_link(test_gen.gen(), None);
-        let first = Node::new_link(test_gen.gen(), Some(node.clone()));
-        node.borrow_mut().insert_child_last(first.clone());
-
-        let subtree = Subtree {
-            root: node,
-            parent: None,
-            above_sibling: None,
-        }
-        .make_unique(&test_gen);
+        let test_gen = CounterGen::new();
+        let mut arena = Arena::new();
+        let node = arena.alloc(Node::new(test_gen.gen()));
+        let first = arena.alloc(Node::new(test_gen.gen()));
+        arena.insert_child_last(node, first);
+
+        let subtree = Subtree { arena, root: node }.make_unique(&test_gen);
 
-        assert!(subtree.ids().into_iter().all(|i| i != 0 && i != 1));
+        assert!(subtree.ids().into_iter().all(|i| i != id(0) && i != id(1)));
     }
 
     #[test]
     fn siblings_test() {
         let mut tree = new_test_tree();
 
-        assert_eq!(tree.get_active_id(), 1);
+        assert_eq!(tree.get_active_id(), id(1));
         tree.create_sibling();
-        assert_eq!(tree.get_active_id(), 2);
-
-        assert_eq!(tree.active.borrow().parent.as_ref().unwrap().borrow().id, 0);
-        assert_eq!(
-            tree.active.borrow().get_sibling(Above).unwrap().borrow().id,
-            1
-        );
+        assert_eq!(tree.get_active_id(), id(2));
 
-        let root_node = tree.get_node(0).unwrap();
-        assert!(root_node
-            .borrow()
-            .children
-            .iter()
-            .any(|n| n.borrow().id == 1));
-        assert!(root_node
-            .borrow()
-            .children
-            .iter()
-            .any(|n| n.borrow().id == 2));
+        assert_eq!(get_parent_id(&tree, 2), Some(0));
+        assert_eq!(get_sibling_id(&tree, 2, Above), Some(1));
+        assert_eq!(get_children_ids(&tree, 0), [1, 2]);
     }
 
     #[test]
@@ -524,18 +992,11 @@ mod tests {
         tree.indent(false).unwrap(); // id 3 under 2
         tree.create_sibling(); // id 4 under 2
         tree.create_sibling(); // id 5 under 2
-        tree.activate(4).unwrap();
+        tree.activate(id(4)).unwrap();
         tree.create_sibling(); // id 6 under 2 (after 4, before 5)
 
-        let children = &tree.get_node(2).unwrap().borrow().children;
-        assert_eq!(children.get(2).unwrap().borrow().id, 6);
-        assert_eq!(children.get(3).unwrap().borrow().id, 5);
-
-        let six = tree.get_node(6).unwrap();
-        assert_eq!(
-            six.borrow().get_sibling(Below).map(|s| s.borrow().id),
-            Some(5)
-        );
+        assert_eq!(get_children_ids(&tree, 2), [3, 4, 6, 5]);
+        assert_eq!(get_sibling_id(&tree, 6, Below), Some(5));
     }
 
     #[test]
@@ -546,16 +1007,9 @@ mod tests {
         tree.create_sibling();
         assert!(tree.indent(false).is_ok());
 
-        let active_node = tree.active.borrow();
-        assert_eq!(active_node.parent.as_ref().map(get_id), Some(1));
-        assert_eq!(active_node.id, 2);
-
-        let parent_node = tree.get_node(1).unwrap();
-        assert!(parent_node
-            .borrow()
-            .children
-            .iter()
-            .any(|n| n.borrow().id == 2));
+        assert_eq!(tree.get_active_id(), id(2));
+        assert_eq!(get_parent_id(&tree, 2), Some(1));
+        assert_eq!(get_children_ids(&tree, 1), [2]);
     }
 
     #[test]
@@ -566,9 +1020,7 @@ mod tests {
         tree.create_sibling(); // id = 2
         assert!(tree.indent(false).is_ok()); // (2 under 1)
         assert!(tree.unindent().is_ok()); // (2 under root)
-        let two = tree.get_node(2).unwrap();
-        assert_eq!(two.borrow().parent.as_ref().map(get_id), Some(0));
-        // TODO figure out why printing a Link causes stack overflow
+        assert_eq!(get_parent_id(&tree, 2), Some(0));
 
         assert!(tree.indent(false).is_ok());
         tree.create_sibling(); // id = 3 (under 1)
@@ -576,8 +1028,8 @@ mod tests {
         tree.create_sibling(); // id = 5 (under 1)
         assert!(tree.unindent().is_ok()); // (5 under root)
         assert!(tree.indent(false).is_ok()); // (5 under 1)
-        let five = tree.get_node(5).unwrap();
-        assert_eq!(five.borrow().parent.as_ref().map(get_id), Some(1));
+        assert_eq!(get_parent_id(&tree, 5), Some(1));
+        assert_eq!(get_children_ids(&tree, 1), [2, 3, 4, 5]);
     }
 
     #[test]
@@ -590,41 +1042,52 @@ mod tests {
         assert!(tree.indent(false).is_ok()); // (4 under 3)
         tree.create_sibling(); // id = 5 (under 3)
 
-        let root_exp_children = vec![1, 2, 3];
+        let root_exp_children = [1, 2, 3];
         let root_itr = tree.root_iter();
         let root_children: Vec<NodeIterator> = root_itr.children_iter().collect();
         let mut three_itr = None;
 
         assert_eq!(root_exp_children.len(), root_children.len());
         for child in &root_children {
-            assert!(root_exp_children.iter().any(|&x| x == child.id()));
-            if child.id() == 3 {
+            assert!(root_exp_children.iter().any(|&x| x == get_itr_id(*child)));
+            if child.id() == id(3) {
                 three_itr = Some(child);
             }
         }
 
-        let three_exp_children = vec![4, 5];
+        let three_exp_children = [4, 5];
         let three_children: Vec<NodeIterator> = three_itr.unwrap().children_iter().collect();
         assert_eq!(three_children.len(), three_exp_children.len());
         for child in three_children {
-            assert!(three_exp_children.iter().any(|&x| x == child.id()));
+            assert!(three_exp_children.iter().any(|&x| x == get_itr_id(child)));
         }
     }
 
+    #[test]
+    fn pre_order_traversal() {
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap(); // 2 under 1
+        tree.create_sibling(); // id = 3 under 1
+        tree.indent(false).unwrap(); // 3 under 2
+        tree.activate(id(1)).unwrap();
+        tree.create_sibling(); // id = 4
+        let ids: Vec<u128> = tree
+            .root_iter()
+            .traverse(TraversalType::PreOrder)
+            .map(get_itr_id)
+            .collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+    }
+
     #[test]
     fn delete_simple() {
         let mut tree = new_test_tree();
         tree.create_sibling(); // id = 2
         tree.create_sibling(); // id = 3
         tree.delete().unwrap(); // id 3 deleted
-        assert!(tree.get_node(3).is_none());
-        assert!(tree
-            .get_node(0)
-            .unwrap()
-            .borrow()
-            .children
-            .iter()
-            .all(|n| n.borrow().id != 3));
+        assert!(tree.get_node(id(3)).is_none());
+        assert_eq!(get_children_ids(&tree, 0), [1, 2]);
     }
 
     #[test]
@@ -632,16 +1095,10 @@ mod tests {
         let mut tree = new_test_tree();
         tree.create_sibling(); // id = 2
         tree.create_sibling(); // id = 3
-        tree.activate(2).unwrap();
+        tree.activate(id(2)).unwrap();
         tree.delete().unwrap();
-        assert!(tree.get_node(2).is_none());
-        assert!(tree
-            .get_node(0)
-            .unwrap()
-            .borrow()
-            .children
-            .iter()
-            .all(|n| n.borrow().id != 2));
+        assert!(tree.get_node(id(2)).is_none());
+        assert!(get_children_ids(&tree, 0).iter().all(|&id| id != 2));
     }
 
     #[test]
@@ -657,21 +1114,15 @@ mod tests {
         tree.create_sibling(); // id = 7
         tree.indent(false).unwrap(); // 7 under 6
 
-        tree.activate(2).unwrap();
+        tree.activate(id(2)).unwrap();
         tree.delete().unwrap();
-        assert!(tree.get_node(2).is_none());
-        assert!(tree.get_node(3).is_none());
-        assert!(tree.get_node(4).is_none());
-        assert!(tree.get_node(5).is_none());
-        assert!(tree.get_node(6).is_none());
-        assert!(tree.get_node(7).is_none());
-        assert!(tree
-            .get_node(0)
-            .unwrap()
-            .borrow()
-            .children
-            .iter()
-            .all(|n| n.borrow().id != 2));
+        assert!(tree.get_node(id(2)).is_none());
+        assert!(tree.get_node(id(3)).is_none());
+        assert!(tree.get_node(id(4)).is_none());
+        assert!(tree.get_node(id(5)).is_none());
+        assert!(tree.get_node(id(6)).is_none());
+        assert!(tree.get_node(id(7)).is_none());
+        assert!(get_children_ids(&tree, 0).iter().all(|&id| id != 2));
     }
 
     #[test]
@@ -692,20 +1143,20 @@ mod tests {
         tree.indent(false).unwrap(); // 2 under 1
         tree.create_sibling(); // id = 3
         tree.delete().unwrap(); // delete 3
-        assert_eq!(tree.get_active_id(), 2);
+        assert_eq!(tree.get_active_id(), id(2));
 
         // With no sibling
         tree.delete().unwrap(); // delete 2
-        assert_eq!(tree.get_active_id(), 1);
+        assert_eq!(tree.get_active_id(), id(1));
 
         // 1.
         // 4. <-- deleted
         // 5.
         tree.create_sibling(); // id = 4
         tree.create_sibling(); // id = 5
-        tree.activate(4).unwrap();
+        tree.activate(id(4)).unwrap();
         tree.delete().unwrap();
-        assert_eq!(tree.get_active_id(), 5);
+        assert_eq!(tree.get_active_id(), id(5));
     }
 
     #[test]
@@ -715,7 +1166,7 @@ mod tests {
         tree.create_sibling_above(); // id = 2
         tree.create_sibling_above(); // id = 3
         tree.create_sibling_above(); // id = 4
-        tree.activate(1).unwrap();
+        tree.activate(id(1)).unwrap();
         tree.indent(false).unwrap(); // 1 under 2
         tree.create_sibling_above(); // id = 5
         tree.create_sibling(); // id = 6
@@ -727,10 +1178,8 @@ mod tests {
         //      6. --
         //      1. --
 
-        let root = tree.get_node(0).unwrap();
-        assert_eq!(get_children_ids(&root), [4, 3, 2]);
-        let two = tree.get_node(2).unwrap();
-        assert_eq!(get_children_ids(&two), [5, 6, 1]);
+        assert_eq!(get_children_ids(&tree, 0), [4, 3, 2]);
+        assert_eq!(get_children_ids(&tree, 2), [5, 6, 1]);
     }
 
     #[test]
@@ -747,16 +1196,16 @@ mod tests {
         tree.create_sibling(); // id = 3 under 1
         tree.create_sibling(); // id = 4 under 1
         tree.create_sibling(); // id = 5 under 1
-        tree.activate(3).unwrap();
+        tree.activate(id(3)).unwrap();
         tree.indent(false).unwrap();
 
-        tree.activate(1).unwrap();
+        tree.activate(id(1)).unwrap();
         let subtree = tree.get_subtree();
 
-        let level_ids: Vec<i32> = subtree
+        let level_ids: Vec<u128> = subtree
             .root_itr()
             .traverse(TraversalType::Level)
-            .map(|n| n.id())
+            .map(get_itr_id)
             .collect();
         assert_eq!(level_ids, [1, 2, 4, 5, 3]);
     }
@@ -793,10 +1242,10 @@ mod tests {
     #[test]
     fn post_order_traversal() {
         let tree = new_deep_tree();
-        let post_order_ids: Vec<i32> = tree
+        let post_order_ids: Vec<u128> = tree
             .root_iter()
             .traverse(TraversalType::PostOrder)
-            .map(|n| n.id())
+            .map(get_itr_id)
             .collect();
         assert_eq!(post_order_ids, [1, 3, 5, 4, 6, 2, 7, 9, 10, 8, 0]);
     }
@@ -804,10 +1253,10 @@ mod tests {
     #[test]
     fn level_traversal() {
         let tree = new_deep_tree();
-        let in_order_ids: Vec<i32> = tree
+        let in_order_ids: Vec<u128> = tree
             .root_iter()
             .traverse(TraversalType::Level)
-            .map(|n| n.id())
+            .map(get_itr_id)
             .collect();
         assert_eq!(in_order_ids, [0, 1, 2, 7, 8, 3, 4, 6, 9, 10, 5]);
     }
@@ -828,10 +1277,10 @@ mod tests {
         subtree_maker.create_sibling();
         subtree_maker.create_sibling();
 
-        subtree_maker.activate(1).unwrap();
+        subtree_maker.activate(id(1)).unwrap();
         let subtree = subtree_maker.get_subtree();
 
-        tree.activate(7).unwrap();
+        tree.activate(id(7)).unwrap();
         tree.insert_subtree(subtree, Below);
         assert_eq!(
             get_tree_ids(&tree),
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+};
+
+use super::id::Id;
+use super::node::{Index, Node};
+use super::Tree;
+
+/// A way in which a [Tree] breaks one of its invariants. Nodes are named by id.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Violation {
+    /// The active node is the root or is not part of the tree
+    InvalidActive,
+    /// The root has no children
+    Empty,
+    DuplicateId(Id),
+    /// The node is linked from a parent that it does not point back to
+    DanglingParent(Id),
+    /// The sibling or child links of the node disagree with each other
+    BrokenSiblings(Id),
+    /// A node of the tree has no entry in the id table
+    MissingFromTable(Id),
+    /// An entry of the id table points to a node that is not part of the tree
+    OrphanedTableEntry(Id),
+    /// The mirror has children, or its original is missing, is a mirror or is drawn under it
+    BrokenMirror(Id),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::InvalidActive => write!(f, "active node is not a bullet in the tree"),
+            Violation::Empty => write!(f, "tree has no bullets"),
+            Violation::DuplicateId(id) => write!(f, "id {} is used by several nodes", id),
+            Violation::DanglingParent(id) => write!(f, "node {} has the wrong parent", id),
+            Violation::BrokenSiblings(id) => write!(f, "node {} has broken sibling links", id),
+            Violation::MissingFromTable(id) => write!(f, "node {} is missing from the id table", id),
+            Violation::OrphanedTableEntry(id) => {
+                write!(f, "id table entry {} is not in the tree", id)
+            }
+            Violation::BrokenMirror(id) => write!(f, "mirror {} cannot show its original", id),
+        }
+    }
+}
+
+/// A node reached from the root along with its children, as found by following the child and
+/// sibling links
+struct Visit {
+    index: Index,
+    children: Vec<Index>,
+    /// Whether the sibling links ended in a removed node or looped back to a seen one
+    cut: bool,
+}
+
+impl Tree {
+    /// Checks every invariant listed on [Tree]. An empty list means the tree is consistent.
+    pub fn check_invariants(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        let visits = self.walk();
+        let reachable: HashSet<Index> = visits.iter().map(|v| v.index).collect();
+        let mut ids = HashSet::new();
+        for visit in &visits {
+            let node = self.arena.get(visit.index);
+            if !ids.insert(node.id) {
+                violations.push(Violation::DuplicateId(node.id));
+            } else if self.id_table.get(&node.id) != Some(&visit.index) {
+                violations.push(Violation::MissingFromTable(node.id));
+            }
+            if visit.cut || node.last_child != visit.children.last().copied() {
+                violations.push(Violation::BrokenSiblings(node.id));
+            }
+            let mut prev = None;
+            for &child in &visit.children {
+                let child_node = self.arena.get(child);
+                if child_node.parent != Some(visit.index) {
+                    violations.push(Violation::DanglingParent(child_node.id));
+                } else if child_node.prev_sibling != prev {
+                    violations.push(Violation::BrokenSiblings(child_node.id));
+                }
+                prev = Some(child);
+            }
+        }
+        for (&id, &index) in &self.id_table {
+            let is_node = self.arena.try_get(index).map(|n| n.id) == Some(id);
+            if !is_node || !reachable.contains(&index) {
+                violations.push(Violation::OrphanedTableEntry(id));
+            }
+        }
+        if self.active == self.root || !reachable.contains(&self.active) {
+            violations.push(Violation::InvalidActive);
+        }
+        if self.arena.get(self.root).first_child.is_none() {
+            violations.push(Violation::Empty);
+        }
+        // Following the originals of mirrors needs sound links and ids
+        if violations.is_empty() {
+            violations.extend(
+                self.broken_mirrors()
+                    .into_iter()
+                    .map(|i| Violation::BrokenMirror(self.arena.get(i).id)),
+            );
+        }
+        violations.sort();
+        violations
+    }
+
+    /// Mirrors that break the invariants, in pre-order
+    fn broken_mirrors(&self) -> Vec<Index> {
+        self.walk()
+            .into_iter()
+            .map(|visit| visit.index)
+            .filter(|&index| {
+                let node = self.arena.get(index);
+                let original = match node.mirror.and_then(|id| self.get_node(id)) {
+                    Some(original) => original,
+                    None => return node.mirror.is_some(),
+                };
+                node.first_child.is_some()
+                    || self.arena.get(original).mirror.is_some()
+                    || self.reaches(original, index)
+            })
+            .collect()
+    }
+
+    /// Fixes the violations found by [check_invariants](Tree::check_invariants) and returns
+    /// them. Links are rebuilt from what is reachable from the root, duplicate ids are replaced
+    /// by new ones, the id table is rebuilt and unreachable nodes are dropped. Broken mirrors
+    /// become plain bullets with the content of their original if it is still there.
+    pub fn repair(&mut self) -> Vec<Violation> {
+        let violations = self.check_invariants();
+        if violations.is_empty() {
+            return violations;
+        }
+        let visits = self.walk();
+        for visit in &visits {
+            let mut prev = None;
+            for &child in &visit.children {
+                let node = self.arena.get_mut(child);
+                node.parent = Some(visit.index);
+                node.prev_sibling = prev;
+                node.next_sibling = None;
+                if let Some(prev) = prev {
+                    self.arena.get_mut(prev).next_sibling = Some(child);
+                }
+                prev = Some(child);
+            }
+            let node = self.arena.get_mut(visit.index);
+            node.first_child = visit.children.first().copied();
+            node.last_child = prev;
+        }
+        self.arena.get_mut(self.root).parent = None;
+
+        self.id_table.clear();
+        let reachable: HashSet<Index> = visits.iter().map(|v| v.index).collect();
+        for visit in &visits {
+            let mut id = self.arena.get(visit.index).id;
+            if self.id_table.contains_key(&id) {
+                id = self.generator.gen();
+                self.arena.get_mut(visit.index).id = id;
+            }
+            self.id_table.insert(id, visit.index);
+        }
+        let unreachable: Vec<Index> = self
+            .arena
+            .indices()
+            .filter(|i| !reachable.contains(i))
+            .collect();
+        for index in unreachable {
+            self.arena.remove(index);
+        }
+
+        if self.arena.get(self.root).first_child.is_none() {
+            let id = self.generator.gen();
+            let first = self.arena.alloc(Node::new(id));
+            self.arena.insert_child_last(self.root, first);
+            self.id_table.insert(id, first);
+        }
+        if self.active == self.root || !reachable.contains(&self.active) {
+            self.active = self.arena.get(self.root).first_child.unwrap();
+        }
+        // One at a time, since making one mirror plain can fix a cycle through others
+        while let Some(&index) = self.broken_mirrors().first() {
+            let original = self.arena.get(index).mirror.and_then(|id| self.get_node(id));
+            let (content, completed) = match original {
+                Some(original) => {
+                    let original = self.arena.get(original);
+                    (original.content.clone(), original.completed)
+                }
+                None => {
+                    let node = self.arena.get(index);
+                    (node.content.clone(), node.completed)
+                }
+            };
+            let node = self.arena.get_mut(index);
+            node.mirror = None;
+            node.content = content;
+            node.completed = completed;
+        }
+        self.reindex_tags();
+        violations
+    }
+
+    /// Visits the nodes reachable from the root through child and sibling links, each once.
+    fn walk(&self) -> Vec<Visit> {
+        let mut seen = HashSet::new();
+        seen.insert(self.root);
+        let mut visits = vec![];
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let mut children = vec![];
+            let mut cut = false;
+            let mut next = self.arena.get(index).first_child;
+            while let Some(child) = next {
+                match self.arena.try_get(child) {
+                    Some(node) if seen.insert(child) => {
+                        children.push(child);
+                        next = node.next_sibling;
+                    }
+                    _ => {
+                        cut = true;
+                        break;
+                    }
+                }
+            }
+            stack.extend(children.iter().rev());
+            visits.push(Visit {
+                index,
+                children,
+                cut,
+            });
+        }
+        visits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{id, new_test_tree};
+    use super::super::Dir::*;
+    use super::*;
+
+    fn index(tree: &Tree, id: Id) -> Index {
+        tree.get_node(id).unwrap()
+    }
+
+    #[test]
+    fn edited_tree_is_consistent() {
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap();
+        tree.create_sibling(); // id = 3
+        tree.create_sibling_above(); // id = 4
+        tree.unindent().unwrap();
+        tree.activate(id(1)).unwrap();
+        let subtree = tree.get_subtree();
+        tree.insert_subtree(subtree, Below);
+        tree.activate(id(2)).unwrap();
+        tree.delete().unwrap();
+        assert_eq!(tree.check_invariants(), []);
+        assert_eq!(tree.repair(), []);
+    }
+
+    #[test]
+    fn repairs_dangling_parent() {
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        tree.indent(false).unwrap(); // 2 under 1
+        let two = index(&tree, id(2));
+        tree.arena.get_mut(two).parent = Some(tree.root);
+        assert_eq!(tree.check_invariants(), [Violation::DanglingParent(id(2))]);
+        assert_eq!(tree.repair(), [Violation::DanglingParent(id(2))]);
+        assert_eq!(tree.check_invariants(), []);
+        assert_eq!(tree.node_iter(id(2)).unwrap().next_parent().unwrap().id(), id(1));
+    }
+
+    #[test]
+    fn repairs_broken_mirror() {
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content().push('a');
+        tree.create_mirror(id(1), Below).unwrap(); // id = 2
+        tree.create_sibling(); // id = 3
+        let (two, three) = (index(&tree, id(2)), index(&tree, id(3)));
+        tree.arena.get_mut(three).mirror = Some(id(9));
+        tree.arena.get_mut(two).mirror = Some(id(3));
+        assert_eq!(
+            tree.check_invariants(),
+            [Violation::BrokenMirror(id(2)), Violation::BrokenMirror(id(3))]
+        );
+        tree.repair();
+        assert_eq!(tree.check_invariants(), []);
+        assert_eq!(tree.node_iter(id(2)).unwrap().mirror_of(), None);
+        assert_eq!(tree.node_iter(id(3)).unwrap().mirror_of(), None);
+    }
+
+    #[test]
+    fn repairs_duplicate_ids_and_table() {
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        tree.create_sibling(); // id = 3
+        let three = index(&tree, id(3));
+        tree.arena.get_mut(three).id = id(2);
+        tree.id_table.insert(id(9), 0);
+        assert_eq!(
+            tree.check_invariants(),
+            [
+                Violation::DuplicateId(id(2)),
+                Violation::OrphanedTableEntry(id(3)),
+                Violation::OrphanedTableEntry(id(9))
+            ]
+        );
+        tree.repair();
+        assert_eq!(tree.check_invariants(), []);
+        let ids: Vec<Id> = tree.root_iter().children_iter().map(|n| n.id()).collect();
+        assert_eq!(ids, [id(1), id(2), id(4)]);
+        assert!(!tree.contains(id(9)));
+    }
+
+    #[test]
+    fn repairs_active_and_sibling_cycle() {
+        let mut tree = new_test_tree();
+        tree.create_sibling(); // id = 2
+        let (one, two) = (index(&tree, id(1)), index(&tree, id(2)));
+        tree.arena.get_mut(two).next_sibling = Some(one);
+        tree.active = tree.root;
+        assert_eq!(
+            tree.check_invariants(),
+            [Violation::InvalidActive, Violation::BrokenSiblings(id(0))]
+        );
+        tree.repair();
+        assert_eq!(tree.check_invariants(), []);
+        assert_eq!(tree.get_active_id(), id(1));
+    }
+}
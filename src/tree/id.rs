@@ -0,0 +1,173 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    fmt::{Display, Formatter},
+    hash::{BuildHasher, Hash, Hasher},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::IdGenerator;
+
+/// Crockford's base 32, which leaves out letters that look like digits
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const RANDOM_BITS: u32 = 80;
+
+/// Identifies a node for as long as it exists, including across saves, so it must not collide
+/// with the ids of other outlines it could be merged with. Written in base 32 without leading
+/// zeros, so small ids like the ones of [CounterGen] read as plain numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id(u128);
+
+impl Id {
+    /// The id of the root node of every tree
+    pub const ROOT: Id = Id(0);
+}
+
+impl From<u128> for Id {
+    fn from(raw: u128) -> Id {
+        Id(raw)
+    }
+}
+
+impl From<Id> for u128 {
+    fn from(id: Id) -> u128 {
+        id.0
+    }
+}
+
+impl Display for Id {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut digits = vec![];
+        let mut rest = self.0;
+        loop {
+            digits.push(ALPHABET[(rest % 32) as usize] as char);
+            rest /= 32;
+            if rest == 0 {
+                break;
+            }
+        }
+        let s: String = digits.into_iter().rev().collect();
+        f.pad(&s)
+    }
+}
+
+impl FromStr for Id {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Id, String> {
+        if s.is_empty() {
+            return Err("empty id".to_string());
+        }
+        let mut raw: u128 = 0;
+        for c in s.chars() {
+            let digit = ALPHABET
+                .iter()
+                .position(|&a| a as char == c.to_ascii_uppercase())
+                .ok_or_else(|| format!("invalid id: {}", s))?;
+            raw = raw
+                .checked_mul(32)
+                .and_then(|r| r.checked_add(digit as u128))
+                .ok_or_else(|| format!("id is too long: {}", s))?;
+        }
+        Ok(Id(raw))
+    }
+}
+
+/// Hands out 1, 2, 3 and so on. The ids are only unique within one tree, which is enough for
+/// tests.
+pub struct CounterGen {
+    current: Cell<u128>,
+}
+
+impl CounterGen {
+    pub fn new() -> CounterGen {
+        CounterGen {
+            current: Cell::new(1),
+        }
+    }
+}
+
+impl IdGenerator for CounterGen {
+    fn gen(&self) -> Id {
+        let id = self.current.get();
+        self.current.set(id + 1);
+        Id(id)
+    }
+
+    fn reserve(&self, id: Id) {
+        self.current.set(self.current.get().max(id.0 + 1));
+    }
+}
+
+/// ULID style ids: the milliseconds since the epoch in the top 48 bits and 80 random bits below
+/// them. Ids made within the same millisecond count up from the first one, so they still sort
+/// in the order they were made.
+pub struct UlidGen {
+    random: RandomState,
+    last: Cell<u128>,
+}
+
+impl UlidGen {
+    pub fn new() -> UlidGen {
+        UlidGen {
+            random: RandomState::new(),
+            last: Cell::new(0),
+        }
+    }
+
+    fn random_bits(&self, seed: u128) -> u128 {
+        let mut hasher = self.random.build_hasher();
+        seed.hash(&mut hasher);
+        let high = hasher.finish();
+        high.hash(&mut hasher);
+        let low = hasher.finish();
+        ((high as u128) << 64 | low as u128) & ((1 << RANDOM_BITS) - 1)
+    }
+}
+
+impl IdGenerator for UlidGen {
+    fn gen(&self) -> Id {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let millis = now.as_millis() & ((1 << 48) - 1);
+        let mut raw = millis << RANDOM_BITS | self.random_bits(now.as_nanos() ^ self.last.get());
+        if raw >> RANDOM_BITS == self.last.get() >> RANDOM_BITS || raw <= self.last.get() {
+            raw = self.last.get() + 1;
+        }
+        self.last.set(raw);
+        Id(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn display_round_trip() {
+        assert_eq!(Id::ROOT.to_string(), "0");
+        assert_eq!(Id(31).to_string(), "Z");
+        assert_eq!(Id(32).to_string(), "10");
+        let max = Id(u128::MAX);
+        assert_eq!(max.to_string().parse(), Ok(max));
+        assert_eq!("7zz".parse(), Ok(Id(7 * 1024 + 31 * 32 + 31)));
+        assert!("".parse::<Id>().is_err());
+        assert!("I".parse::<Id>().is_err());
+        assert!("8".repeat(27).parse::<Id>().is_err());
+    }
+
+    #[test]
+    fn ulids_are_unique_and_ordered() {
+        let gen = UlidGen::new();
+        let ids: Vec<Id> = (0..1000).map(|_| gen.gen()).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+        // Another generator is seeded differently
+        let other = UlidGen::new();
+        assert!(!ids.contains(&other.gen()));
+    }
+}
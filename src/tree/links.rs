@@ -0,0 +1,86 @@
+use super::id::Id;
+use super::Tree;
+use crate::markup;
+
+impl Tree {
+    /// The bullet that a `[[link]]` with `text` goes to: the one with that id, or else the first
+    /// whose content is `text` ignoring case. Mirrors are only linked to by id.
+    pub fn link_target(&self, text: &str) -> Option<Id> {
+        let text = text.trim();
+        if let Some(id) = text.parse().ok().filter(|&id| self.contains(id)) {
+            return Some(id);
+        }
+        let title = text.to_lowercase();
+        self.root_iter()
+            .bullets()
+            .filter(|node| node.mirror_of().is_none())
+            .find(|node| node.content().trim().to_lowercase() == title)
+            .map(|node| node.id())
+    }
+
+    /// The bullets with a link to `id`, in pre-order
+    pub fn backlinks(&self, id: Id) -> Vec<Id> {
+        self.root_iter()
+            .bullets()
+            .filter(|node| node.mirror_of().is_none())
+            .filter(|node| {
+                let content = node.content();
+                markup::links(content).any(|range| self.link_target(&content[range]) == Some(id))
+            })
+            .map(|node| node.id())
+            .collect()
+    }
+
+    /// Rewrites the links of the active content that go to a bullet by its content so that they
+    /// go by its id instead, which stays the same when that content changes. Returns whether
+    /// anything was rewritten.
+    pub fn link_active_by_id(&mut self) -> bool {
+        let content = self.get_active_content();
+        let by_id = |text: &str| text.trim().parse().is_ok_and(|id| self.contains(id));
+        let rewrites: Vec<_> = markup::links(content)
+            .filter(|range| !by_id(&content[range.clone()]))
+            .filter_map(|range| {
+                let id = self.link_target(&content[range.clone()])?;
+                Some((range, id.to_string()))
+            })
+            .collect();
+        if rewrites.is_empty() {
+            return false;
+        }
+        let content = self.get_mut_active_content();
+        for (range, id) in rewrites.into_iter().rev() {
+            content.replace_range(range, &id);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{id, new_test_tree};
+
+    #[test]
+    fn links_resolve_by_id_and_title() {
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content().push_str("Groceries");
+        tree.create_sibling(); // id = 2
+        tree.get_mut_active_content()
+            .push_str("buy [[ groceries ]] and [[1]], not [[x]]");
+        tree.create_sibling(); // id = 3
+        tree.get_mut_active_content().push_str("[[2]]");
+        assert_eq!(tree.link_target("GROCERIES"), Some(id(1)));
+        assert_eq!(tree.link_target("2"), Some(id(2)));
+        assert_eq!(tree.link_target("x"), None);
+        assert_eq!(tree.backlinks(id(1)), [id(2)]);
+        assert_eq!(tree.backlinks(id(2)), [id(3)]);
+
+        // Links by title keep working after they are stored by id
+        tree.activate(id(2)).unwrap();
+        assert!(tree.link_active_by_id());
+        assert_eq!(tree.get_active_content(), "buy [[1]] and [[1]], not [[x]]");
+        assert!(!tree.link_active_by_id());
+        tree.activate(id(1)).unwrap();
+        tree.get_mut_active_content().push_str(" list");
+        assert_eq!(tree.backlinks(id(1)), [id(2)]);
+    }
+}
@@ -1,160 +1,264 @@
+use super::id::Id;
+use crate::time::Timestamp;
 use super::Dir::{self, *};
-use std::{cell::RefCell, rc::Rc};
 
-pub type Link = Rc<RefCell<Node>>;
+/// Position of a node in its [Arena]
+pub type Index = usize;
 
+/// A node links to its relatives by their index in the arena, so every move through the tree
+/// is a lookup instead of a search through the parent's children.
 #[derive(Debug, Clone)]
 pub struct Node {
-    pub id: i32,
-    pub parent: Option<Link>,
-    pub children: Vec<Link>,
+    pub id: Id,
+    pub parent: Option<Index>,
+    pub first_child: Option<Index>,
+    pub last_child: Option<Index>,
+    pub prev_sibling: Option<Index>,
+    pub next_sibling: Option<Index>,
     pub content: String,
+    pub completed: bool,
+    /// None for nodes read from somewhere that did not know it
+    pub created: Option<Timestamp>,
+    /// When the content, completion or place of the node last changed
+    pub modified: Option<Timestamp>,
+    /// The original whose content and children a mirror shows in place of its own. Mirrors
+    /// have no children of their own.
+    pub mirror: Option<Id>,
 }
 
 impl Node {
-    pub fn new(id: i32, parent: Option<Link>) -> Node {
+    pub fn new(id: Id) -> Node {
         Node {
             id,
-            parent,
-            children: vec![],
+            parent: None,
+            first_child: None,
+            last_child: None,
+            prev_sibling: None,
+            next_sibling: None,
             content: String::new(),
+            completed: false,
+            created: None,
+            modified: None,
+            mirror: None,
         }
     }
 
-    pub fn new_link(id: i32, parent: Option<Link>) -> Link {
-        Link::new(RefCell::new(Self::new(id, parent)))
+    pub fn is_root(&self) -> bool {
+        self.id == Id::ROOT
     }
+}
 
-    pub fn new_link_from_other(link: &Link) -> Link {
-        Link::new(RefCell::new(link.borrow().clone()))
+/// Storage for the nodes of a tree. Slots of removed nodes are reused by later allocations.
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    nodes: Vec<Option<Node>>,
+    free: Vec<Index>,
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena::default()
     }
 
-    /// Inserts a `child` above or below an existing child with an id of `relative_id` (if it exists).
-    pub fn insert_child_relative(
-        &mut self,
-        relative_id: i32,
-        dir: Dir,
-        child: Link,
-    ) -> Result<(), ()> {
-        let index = match (
-            self.children
-                .iter()
-                .position(|l| l.borrow().id == relative_id),
-            dir,
-        ) {
-            (Some(index), Below) => index + 1,
-            (Some(index), Above) => index,
-            (None, _) => return Err(()),
-        };
-        self.children.insert(index, child);
-        Ok(())
+    pub fn alloc(&mut self, node: Node) -> Index {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = Some(node);
+                index
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        }
     }
 
-    /// Inserts a child node but does not update the parent field of the child
-    pub fn insert_child_last(&mut self, child: Link) {
-        self.children.push(child);
+    /// Frees the slot of a node which has already been [detached](Arena::detach).
+    pub fn remove(&mut self, index: Index) -> Node {
+        let node = self.nodes[index].take().expect("node was removed twice");
+        self.free.push(index);
+        node
     }
 
-    pub fn insert_child_first(&mut self, child: Link) {
-        self.children.insert(0, child);
+    pub fn get(&self, index: Index) -> &Node {
+        self.nodes[index].as_ref().expect("index of removed node was used")
     }
 
-    /// Removes the child with the given id. Will borrow every child Link.
-    pub fn remove_child(&mut self, child_id: i32) {
-        self.children.retain(|l| l.borrow().id != child_id);
+    /// Like [get](Arena::get) but for indices that might not hold a node
+    pub fn try_get(&self, index: Index) -> Option<&Node> {
+        self.nodes.get(index)?.as_ref()
     }
 
-    /// Gets the sibling above or below the current node. This will borrow the parent to access
-    /// its children and will borrow a Link to itself. Siblings are nodes on the same layer as
-    /// the current node.
-    pub fn get_sibling(&self, dir: Dir) -> Option<Link> {
-        let parent = match self.parent {
-            Some(ref parent) => parent.borrow(),
-            None => return None,
+    /// Indices of all nodes, whether they are linked to each other or not
+    pub fn indices(&self) -> impl Iterator<Item = Index> + '_ {
+        (0..self.nodes.len()).filter(move |&i| self.nodes[i].is_some())
+    }
+
+    pub fn get_mut(&mut self, index: Index) -> &mut Node {
+        self.nodes[index].as_mut().expect("index of removed node was used")
+    }
+
+    pub fn get_sibling(&self, index: Index, dir: Dir) -> Option<Index> {
+        match dir {
+            Above => self.get(index).prev_sibling,
+            Below => self.get(index).next_sibling,
+        }
+    }
+
+    pub fn children(&self, index: Index) -> impl Iterator<Item = Index> + '_ {
+        std::iter::successors(self.get(index).first_child, move |&i| self.get(i).next_sibling)
+    }
+
+    /// Inserts `child` above or below `relative`, under the same parent.
+    pub fn insert_relative(&mut self, relative: Index, dir: Dir, child: Index) {
+        let parent = self.get(relative).parent;
+        let (prev, next) = match dir {
+            Above => (self.get(relative).prev_sibling, Some(relative)),
+            Below => (Some(relative), self.get(relative).next_sibling),
         };
-        if let Some(index) = parent
-            .children
-            .iter()
-            .position(|l| l.borrow().id == self.id)
+        self.link(parent.expect("cannot insert next to the root"), prev, next, child);
+    }
+
+    pub fn insert_child_first(&mut self, parent: Index, child: Index) {
+        let first = self.get(parent).first_child;
+        self.link(parent, None, first, child);
+    }
+
+    pub fn insert_child_last(&mut self, parent: Index, child: Index) {
+        let last = self.get(parent).last_child;
+        self.link(parent, last, None, child);
+    }
+
+    fn link(&mut self, parent: Index, prev: Option<Index>, next: Option<Index>, child: Index) {
         {
-            let index = match dir {
-                Below => index + 1,
-                Above => match index.checked_sub(1) {
-                    Some(index) => index,
-                    None => return None,
-                },
-            };
-            parent.children.get(index).cloned()
-        } else {
-            None
+            let node = self.get_mut(child);
+            node.parent = Some(parent);
+            node.prev_sibling = prev;
+            node.next_sibling = next;
+        }
+        match prev {
+            Some(prev) => self.get_mut(prev).next_sibling = Some(child),
+            None => self.get_mut(parent).first_child = Some(child),
+        }
+        match next {
+            Some(next) => self.get_mut(next).prev_sibling = Some(child),
+            None => self.get_mut(parent).last_child = Some(child),
         }
     }
 
-    pub fn is_root(&self) -> bool {
-        self.id == 0
+    /// Unlinks a node from its parent and siblings. Its children stay attached to it.
+    pub fn detach(&mut self, index: Index) {
+        let (parent, prev, next) = {
+            let node = self.get_mut(index);
+            let links = (node.parent, node.prev_sibling, node.next_sibling);
+            node.parent = None;
+            node.prev_sibling = None;
+            node.next_sibling = None;
+            links
+        };
+        if let Some(parent) = parent {
+            match prev {
+                Some(prev) => self.get_mut(prev).next_sibling = next,
+                None => self.get_mut(parent).first_child = next,
+            }
+            match next {
+                Some(next) => self.get_mut(next).prev_sibling = prev,
+                None => self.get_mut(parent).last_child = prev,
+            }
+        }
+    }
+
+    /// Copies the node at `index` and all of its descendants into `other`. Returns the index of
+    /// the copy, which has no parent.
+    pub fn copy_into(&self, index: Index, other: &mut Arena) -> Index {
+        let node = self.get(index);
+        let copy = other.alloc(Node {
+            content: node.content.clone(),
+            completed: node.completed,
+            mirror: node.mirror,
+            ..Node::new(node.id)
+        });
+        for child in self.children(index) {
+            let child_copy = self.copy_into(child, other);
+            other.insert_child_last(copy, child_copy);
+        }
+        copy
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::tests::id;
     use super::*;
 
-    fn two_node_setup() -> Link {
-        let node = Node::new_link(0, None);
-        let first = Node::new_link(1, Some(node.clone()));
-        node.borrow_mut().insert_child_last(first.clone());
-        node
-    }
-
-    fn get_id(link: &Link) -> i32 {
-        link.borrow().id
+    fn two_node_setup() -> (Arena, Index) {
+        let mut arena = Arena::new();
+        let node = arena.alloc(Node::new(Id::ROOT));
+        let first = arena.alloc(Node::new(id(1)));
+        arena.insert_child_last(node, first);
+        (arena, node)
     }
 
-    fn get_children_ids(link: &Link) -> Vec<i32> {
-        link.borrow().children.iter().map(get_id).collect()
+    fn get_children_ids(arena: &Arena, index: Index) -> Vec<u128> {
+        arena.children(index).map(|i| arena.get(i).id.into()).collect()
     }
 
     #[test]
     fn get_sibling_test() {
-        let node = Node::new_link(0, None);
-        assert!(node.borrow().get_sibling(Above).is_none());
-        assert!(node.borrow().get_sibling(Below).is_none());
+        let mut arena = Arena::new();
+        let node = arena.alloc(Node::new(Id::ROOT));
+        assert!(arena.get_sibling(node, Above).is_none());
+        assert!(arena.get_sibling(node, Below).is_none());
 
-        let first = Node::new_link(1, Some(node.clone()));
-        node.borrow_mut().insert_child_last(first.clone());
+        let first = arena.alloc(Node::new(id(1)));
+        arena.insert_child_last(node, first);
 
-        let second = Node::new_link(2, Some(node.clone()));
-        node.borrow_mut().insert_child_last(second.clone());
+        let second = arena.alloc(Node::new(id(2)));
+        arena.insert_child_last(node, second);
 
-        assert_eq!(
-            first.borrow().get_sibling(Below).map(|s| s.borrow().id),
-            Some(2)
-        );
-        assert_eq!(
-            second.borrow().get_sibling(Above).map(|s| s.borrow().id),
-            Some(1)
-        );
+        assert_eq!(arena.get_sibling(first, Below), Some(second));
+        assert_eq!(arena.get_sibling(second, Above), Some(first));
     }
 
     #[test]
     fn insert_child_relative_test() {
-        let node = two_node_setup();
-        let child = Node::new_link(2, Some(node.clone()));
-        node.borrow_mut().insert_child_relative(1, Below, child).unwrap();
-        assert_eq!(get_children_ids(&node), [1, 2]);
+        let (mut arena, node) = two_node_setup();
+        let first = arena.get(node).first_child.unwrap();
+        let two = arena.alloc(Node::new(id(2)));
+        arena.insert_relative(first, Below, two);
+        assert_eq!(get_children_ids(&arena, node), [1, 2]);
 
-        let child = Node::new_link(3, Some(node.clone()));
-        node.borrow_mut().insert_child_relative(2, Above, child).unwrap();
-        assert_eq!(get_children_ids(&node), [1, 3, 2]);
+        let three = arena.alloc(Node::new(id(3)));
+        arena.insert_relative(two, Above, three);
+        assert_eq!(get_children_ids(&arena, node), [1, 3, 2]);
 
-        let child = Node::new_link(4, Some(node.clone()));
-        assert!(node.borrow_mut().insert_child_relative(123123123, Above, child).is_err()); 
+        let four = arena.alloc(Node::new(id(4)));
+        arena.insert_child_first(node, four);
+        assert_eq!(get_children_ids(&arena, node), [4, 1, 3, 2]);
+        assert_eq!(arena.get(node).last_child, Some(two));
     }
 
     #[test]
     fn remove_child_test() {
-        let node = two_node_setup();
-        node.borrow_mut().remove_child(1);
-        assert_eq!(get_children_ids(&node), []);
+        let (mut arena, node) = two_node_setup();
+        let first = arena.get(node).first_child.unwrap();
+        arena.detach(first);
+        assert_eq!(get_children_ids(&arena, node), []);
+        assert!(arena.get(first).parent.is_none());
+
+        // The freed slot is reused
+        arena.remove(first);
+        assert_eq!(arena.alloc(Node::new(id(5))), first);
+    }
+
+    #[test]
+    fn copy_into_test() {
+        let (arena, node) = two_node_setup();
+        let mut other = Arena::new();
+        other.alloc(Node::new(id(9)));
+        let copy = arena.copy_into(node, &mut other);
+        assert_eq!(other.get(copy).id, Id::ROOT);
+        assert_eq!(get_children_ids(&other, copy), [1]);
+        assert!(other.get(copy).parent.is_none());
     }
 }
@@ -0,0 +1,440 @@
+use super::check::Violation;
+use super::id::Id;
+use super::node::{Arena, Index, Node};
+use super::sort::Sort;
+use super::{Dir, IdGenerator, NodeIterator, Subtree, Tree};
+use crate::error::TreeError;
+use crate::time::{SystemClock, Timestamp};
+
+/// An edit of a [Tree], recorded as it happens so that it can be applied again to a copy of the
+/// tree as it was before. `at` is the id of the node that was active when the edit was made, and
+/// `time` is when it was made, which becomes the time the nodes it changed were modified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    CreateSibling {
+        at: Id,
+        dir: Dir,
+        id: Id,
+        time: Timestamp,
+    },
+    /// See [create_mirror](Tree::create_mirror)
+    CreateMirror {
+        at: Id,
+        dir: Dir,
+        id: Id,
+        of: Id,
+        time: Timestamp,
+    },
+    /// The nodes are those of the subtree after it was given new ids
+    InsertSubtree {
+        at: Id,
+        dir: Dir,
+        nodes: Vec<FlatNode>,
+    },
+    Indent {
+        at: Id,
+        first: bool,
+        time: Timestamp,
+    },
+    Unindent {
+        at: Id,
+        time: Timestamp,
+    },
+    Delete {
+        at: Id,
+    },
+    /// See [sort](Tree::sort)
+    Sort {
+        at: Id,
+        sort: Sort,
+    },
+    /// See [reorder](Tree::reorder)
+    Reorder {
+        parent: Id,
+        children: Vec<Id>,
+    },
+    SetContent {
+        id: Id,
+        content: String,
+        time: Timestamp,
+    },
+    SetCompleted {
+        id: Id,
+        completed: bool,
+        time: Timestamp,
+    },
+    /// See [save_search](Tree::save_search)
+    SaveSearch {
+        name: String,
+        query: String,
+    },
+    DeleteSearch {
+        name: String,
+    },
+}
+
+/// A node in a pre-order listing of a tree, where the structure is given by the depths. Mirrors
+/// are listed with their own content rather than what they show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatNode {
+    pub depth: usize,
+    pub id: Id,
+    pub content: String,
+    pub completed: bool,
+    pub created: Option<Timestamp>,
+    pub modified: Option<Timestamp>,
+    /// The original, if the node is a mirror
+    pub mirror: Option<Id>,
+}
+
+impl Op {
+    /// When the edit was made, if it changes nodes that were already in the tree
+    pub fn time(&self) -> Option<Timestamp> {
+        match self {
+            Op::CreateSibling { time, .. }
+            | Op::CreateMirror { time, .. }
+            | Op::Indent { time, .. }
+            | Op::Unindent { time, .. }
+            | Op::SetContent { time, .. }
+            | Op::SetCompleted { time, .. } => Some(*time),
+            _ => None,
+        }
+    }
+}
+
+impl Tree {
+    /// Starts recording edits, to be collected with [take_ops](Tree::take_ops).
+    pub fn record_ops(&mut self) {
+        self.ops.get_or_insert_with(Vec::new);
+    }
+
+    /// The edits made since the last call. Content changes are recorded as the whole content of
+    /// the node at the time it stopped being edited.
+    pub fn take_ops(&mut self) -> Vec<Op> {
+        self.flush_content();
+        self.ops.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub(super) fn record(&mut self, op: Op) {
+        if self.ops.is_some() {
+            self.flush_content();
+            self.ops.as_mut().unwrap().push(op);
+        }
+    }
+
+    pub(super) fn mark_dirty(&mut self) {
+        if self.ops.is_none() {
+            return;
+        }
+        let id = self.get_active_source_id();
+        if self.dirty != Some(id) {
+            self.flush_content();
+            self.dirty = Some(id);
+        }
+    }
+
+    fn flush_content(&mut self) {
+        let id = match self.dirty.take() {
+            Some(id) => id,
+            None => return,
+        };
+        let (content, time) = match self.get_node(id) {
+            Some(index) => {
+                let node = self.arena.get(index);
+                (node.content.clone(), node.modified.unwrap_or_else(|| self.now()))
+            }
+            None => return,
+        };
+        if let Some(ops) = &mut self.ops {
+            ops.push(Op::SetContent { id, content, time });
+        }
+    }
+
+    /// Makes a recorded edit again. Nodes get the ids and times they had when the edit was
+    /// recorded.
+    pub fn apply(&mut self, op: &Op) -> Result<(), TreeError> {
+        self.pinned_time = op.time();
+        let applied = self.apply_op(op);
+        self.pinned_time = None;
+        applied
+    }
+
+    fn apply_op(&mut self, op: &Op) -> Result<(), TreeError> {
+        match op {
+            Op::CreateSibling { at, dir, id, .. } => {
+                self.activate(*at)?;
+                self.claim_id(*id)?;
+                self.insert_new(*id, *dir, None);
+            }
+            Op::CreateMirror { at, dir, id, of, .. } => {
+                self.activate(*at)?;
+                self.claim_id(*id)?;
+                let original = self.get_node(*of).ok_or(TreeError::NotFound(*of))?;
+                if self.arena.get(original).mirror.is_some() {
+                    return Err(TreeError::Inconsistent(Violation::BrokenMirror(*id)));
+                }
+                self.insert_new(*id, *dir, Some(*of));
+            }
+            Op::InsertSubtree { at, dir, nodes } => {
+                self.activate(*at)?;
+                let subtree =
+                    Subtree::from_flat(nodes).ok_or(TreeError::Inconsistent(Violation::Empty))?;
+                for id in subtree.ids() {
+                    self.claim_id(id)?;
+                }
+                if self.ops.is_some() {
+                    self.record(op.clone());
+                }
+                self.graft(subtree, *dir);
+            }
+            Op::Indent { at, first, .. } => {
+                self.activate(*at)?;
+                self.indent(*first)?;
+            }
+            Op::Unindent { at, .. } => {
+                self.activate(*at)?;
+                self.unindent()?;
+            }
+            Op::Delete { at } => {
+                self.activate(*at)?;
+                self.delete()?;
+            }
+            Op::Sort { at, sort } => {
+                self.activate(*at)?;
+                self.sort(*sort);
+            }
+            Op::Reorder { parent, children } => self.reorder(*parent, children)?,
+            Op::SetContent { id, content, .. } => {
+                self.activate(*id)?;
+                *self.get_mut_active_content() = content.clone();
+            }
+            Op::SetCompleted { id, completed, .. } => {
+                self.activate(*id)?;
+                self.set_completed(*completed);
+            }
+            Op::SaveSearch { name, query } => self.save_search(name, query),
+            Op::DeleteSearch { name } => {
+                self.delete_search(name);
+            }
+        }
+        Ok(())
+    }
+
+    fn claim_id(&self, id: Id) -> Result<(), TreeError> {
+        if id == Id::ROOT || self.contains(id) {
+            return Err(TreeError::Inconsistent(Violation::DuplicateId(id)));
+        }
+        self.generator.reserve(id);
+        Ok(())
+    }
+
+    /// All nodes but the root, in pre-order. Top level nodes have depth 0.
+    pub fn flatten(&self) -> Vec<FlatNode> {
+        let mut nodes = vec![];
+        for child in NodeIterator::new(&self.arena, self.root).children_iter() {
+            flatten_into(child, 0, &mut nodes);
+        }
+        nodes
+    }
+
+    /// Builds a tree from a listing made by [flatten](Tree::flatten), activating the node with
+    /// id `active`. Whatever in the listing breaks the invariants is
+    /// [repaired](Tree::repair) and returned.
+    pub fn from_flat(
+        generator: Box<dyn IdGenerator>,
+        nodes: &[FlatNode],
+        active: Id,
+    ) -> (Tree, Vec<Violation>) {
+        let mut arena = Arena::new();
+        let root = arena.alloc(Node::new(Id::ROOT));
+        for top in link_flat(&mut arena, nodes) {
+            arena.insert_child_last(root, top);
+        }
+        let id_table = arena.indices().map(|i| (arena.get(i).id, i)).collect();
+        for node in nodes {
+            generator.reserve(node.id);
+        }
+        let mut tree = Tree {
+            arena,
+            active: root,
+            root,
+            generator,
+            id_table,
+            ops: None,
+            dirty: None,
+            tags: Default::default(),
+            stale_tags: None,
+            searches: vec![],
+            clock: Box::new(SystemClock),
+            pinned_time: None,
+        };
+        tree.id_table.insert(Id::ROOT, root);
+        if let Some(index) = tree.get_node(active) {
+            tree.active = index;
+        }
+        let violations = tree.repair();
+        tree.reindex_tags();
+        (tree, violations)
+    }
+}
+
+impl Subtree {
+    /// The nodes of the subtree in pre-order, with the root at depth 0
+    pub fn flatten(&self) -> Vec<FlatNode> {
+        let mut nodes = vec![];
+        flatten_into(self.root_itr(), 0, &mut nodes);
+        nodes
+    }
+
+    /// Builds a subtree from a listing made by [flatten](Subtree::flatten). Returns None if the
+    /// listing is empty.
+    pub fn from_flat(nodes: &[FlatNode]) -> Option<Subtree> {
+        let mut arena = Arena::new();
+        let mut tops = link_flat(&mut arena, nodes).into_iter();
+        let root = tops.next()?;
+        for top in tops {
+            arena.insert_child_last(root, top);
+        }
+        Some(Subtree { arena, root })
+    }
+}
+
+fn flatten_into(itr: NodeIterator, depth: usize, nodes: &mut Vec<FlatNode>) {
+    nodes.push(FlatNode {
+        depth,
+        id: itr.id(),
+        content: itr.content().clone(),
+        completed: itr.is_completed(),
+        created: itr.created(),
+        modified: itr.modified(),
+        mirror: itr.mirror_of(),
+    });
+    for child in itr.children_iter() {
+        flatten_into(child, depth + 1, nodes);
+    }
+}
+
+/// Allocates the listed nodes and links them to each other. A node deeper than one below the
+/// node before it is treated as a child of that node. Returns the nodes at depth 0.
+fn link_flat(arena: &mut Arena, nodes: &[FlatNode]) -> Vec<Index> {
+    let mut stack: Vec<Index> = vec![];
+    let mut tops = vec![];
+    for flat in nodes {
+        stack.truncate(flat.depth);
+        let index = arena.alloc(Node {
+            content: flat.content.clone(),
+            completed: flat.completed,
+            created: flat.created,
+            modified: flat.modified,
+            mirror: flat.mirror,
+            ..Node::new(flat.id)
+        });
+        match stack.last() {
+            Some(&parent) => arena.insert_child_last(parent, index),
+            None => tops.push(index),
+        }
+        stack.push(index);
+    }
+    tops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::id::CounterGen;
+    use super::super::tests::{id, new_test_tree};
+    use super::super::Dir::*;
+    use super::*;
+
+    /// Makes some edits to a tree that records them
+    fn edited_tree() -> Tree {
+        let mut tree = new_test_tree();
+        tree.record_ops();
+        tree.get_mut_active_content().push_str("one");
+        tree.create_sibling(); // id = 2
+        tree.get_mut_active_content().push_str("two");
+        tree.indent(false).unwrap();
+        tree.create_sibling_above(); // id = 3
+        tree.get_mut_active_content().push_str("three");
+        tree.activate(id(1)).unwrap();
+        let subtree = tree.get_subtree();
+        tree.insert_subtree(subtree, Below); // ids = 4, 5, 6
+        tree.activate(id(3)).unwrap();
+        tree.unindent().unwrap();
+        tree.get_mut_active_content().push('!');
+        tree.activate(id(5)).unwrap();
+        tree.delete().unwrap();
+        tree
+    }
+
+    #[test]
+    fn replay_matches_original() {
+        let mut tree = edited_tree();
+        let ops = tree.take_ops();
+        assert!(tree.take_ops().is_empty());
+
+        let mut replayed = new_test_tree();
+        for op in &ops {
+            replayed.apply(op).unwrap();
+        }
+        assert_eq!(replayed.to_string(), tree.to_string());
+        assert_eq!(replayed.flatten(), tree.flatten());
+        assert_eq!(replayed.check_invariants(), []);
+        // New ids do not clash with replayed ones
+        replayed.create_sibling();
+        tree.create_sibling();
+        assert_eq!(replayed.get_active_id(), tree.get_active_id());
+    }
+
+    #[test]
+    fn apply_rejects_duplicate_ids() {
+        let mut tree = new_test_tree();
+        let op = Op::CreateSibling {
+            at: id(1),
+            dir: Below,
+            id: id(1),
+            time: Timestamp::from_seconds(0),
+        };
+        assert_eq!(
+            tree.apply(&op),
+            Err(TreeError::Inconsistent(Violation::DuplicateId(id(1))))
+        );
+    }
+
+    #[test]
+    fn flat_round_trip() {
+        let tree = edited_tree();
+        let (copy, violations) = Tree::from_flat(
+            Box::new(CounterGen::new()),
+            &tree.flatten(),
+            tree.get_active_id(),
+        );
+        assert_eq!(violations, []);
+        assert_eq!(copy.to_string(), tree.to_string());
+
+        let subtree = tree.get_subtree();
+        let copy = Subtree::from_flat(&subtree.flatten()).unwrap();
+        assert_eq!(copy.ids(), subtree.ids());
+        assert!(Subtree::from_flat(&[]).is_none());
+    }
+
+    #[test]
+    fn from_flat_repairs_listing() {
+        let flat = |depth, id| FlatNode {
+            depth,
+            id,
+            content: String::new(),
+            completed: false,
+            created: None,
+            modified: None,
+            mirror: None,
+        };
+        // Too deep, and a duplicate id
+        let nodes = [flat(0, id(1)), flat(3, id(2)), flat(0, id(2))];
+        let (tree, violations) = Tree::from_flat(Box::new(CounterGen::new()), &nodes, id(9));
+        assert!(violations.contains(&Violation::InvalidActive));
+        assert_eq!(tree.check_invariants(), []);
+        assert_eq!(tree.get_active_id(), id(1));
+        let parent = tree.node_iter(id(2)).unwrap().next_parent().unwrap().id();
+        assert_eq!(parent, id(1));
+    }
+}
@@ -0,0 +1,74 @@
+use super::op::Op;
+use super::Tree;
+
+/// A [Query](crate::query::Query) kept with the outline under a name, to be opened again as a
+/// view of whatever matches it at the time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedSearch {
+    /// A single word
+    pub name: String,
+    /// The query as it was typed
+    pub query: String,
+}
+
+impl Tree {
+    /// The saved searches in the order they were first saved
+    pub fn saved_searches(&self) -> &[SavedSearch] {
+        &self.searches
+    }
+
+    pub fn saved_search(&self, name: &str) -> Option<&SavedSearch> {
+        self.searches.iter().find(|s| s.name == name)
+    }
+
+    /// Saves `query` as `name`, replacing the query of a search with that name.
+    pub fn save_search(&mut self, name: &str, query: &str) {
+        self.record(Op::SaveSearch {
+            name: name.to_string(),
+            query: query.to_string(),
+        });
+        match self.searches.iter_mut().find(|s| s.name == name) {
+            Some(search) => search.query = query.to_string(),
+            None => self.searches.push(SavedSearch {
+                name: name.to_string(),
+                query: query.to_string(),
+            }),
+        }
+    }
+
+    /// Returns the search that was deleted, if there was one called `name`.
+    pub fn delete_search(&mut self, name: &str) -> Option<SavedSearch> {
+        let i = self.searches.iter().position(|s| s.name == name)?;
+        self.record(Op::DeleteSearch {
+            name: name.to_string(),
+        });
+        Some(self.searches.remove(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::new_test_tree;
+
+    #[test]
+    fn save_replace_and_delete() {
+        let mut tree = new_test_tree();
+        tree.save_search("urgent", "tag:urgent");
+        tree.save_search("done", "is:completed");
+        tree.save_search("urgent", "tag:urgent NOT is:completed");
+        let names: Vec<&str> = tree
+            .saved_searches()
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, ["urgent", "done"]);
+        assert_eq!(
+            tree.saved_search("urgent").unwrap().query,
+            "tag:urgent NOT is:completed"
+        );
+
+        assert!(tree.delete_search("done").is_some());
+        assert!(tree.delete_search("done").is_none());
+        assert_eq!(tree.saved_searches().len(), 1);
+    }
+}
@@ -0,0 +1,286 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+use super::id::Id;
+use super::node::Index;
+use super::op::Op;
+use super::{NodeIterator, Tree};
+use crate::error::TreeError;
+use crate::time::Timestamp;
+
+/// What [sort](Tree::sort) compares bullets by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// The content, ignoring case
+    Alphabetical,
+    /// Like [Alphabetical](SortKey::Alphabetical), but runs of digits compare as numbers so that
+    /// `2` comes before `10`
+    Natural,
+    /// Oldest first, then bullets without a time
+    Created,
+    Modified,
+    /// Open bullets first
+    Completed,
+}
+
+/// How [sort](Tree::sort) orders the children of a bullet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    /// Turns the order around, though bullets that compare equal still keep theirs
+    pub reverse: bool,
+    /// Sorts the children of the children too, and so on
+    pub recursive: bool,
+}
+
+impl Sort {
+    pub fn new(key: SortKey) -> Sort {
+        Sort {
+            key,
+            reverse: false,
+            recursive: false,
+        }
+    }
+
+    fn compare(&self, a: NodeIterator, b: NodeIterator) -> Ordering {
+        let order = match self.key {
+            SortKey::Alphabetical => a.content().to_lowercase().cmp(&b.content().to_lowercase()),
+            SortKey::Natural => natural_cmp(a.content(), b.content()),
+            SortKey::Created => time_cmp(a.created(), b.created()),
+            SortKey::Modified => time_cmp(a.modified(), b.modified()),
+            SortKey::Completed => a.is_completed().cmp(&b.is_completed()),
+        };
+        match self.reverse {
+            true => order.reverse(),
+            false => order,
+        }
+    }
+}
+
+impl Tree {
+    /// Sorts the children of the active node, or of its original if it is a mirror. Bullets that
+    /// compare equal keep their order. Sorting does not count as modifying the bullets, so that
+    /// sorting them by time again leaves them as they are.
+    ///
+    /// Returns the children of each sorted bullet in the order they had before.
+    pub fn sort(&mut self, sort: Sort) -> Vec<(Id, Vec<Id>)> {
+        self.record(Op::Sort {
+            at: self.get_active_id(),
+            sort,
+        });
+        let mut before = vec![];
+        let mut parents = vec![self.source(self.active)];
+        while let Some(parent) = parents.pop() {
+            let mut children: Vec<Index> = self.arena.children(parent).collect();
+            if children.is_empty() {
+                continue;
+            }
+            let ids = children.iter().map(|&i| self.arena.get(i).id).collect();
+            before.push((self.arena.get(parent).id, ids));
+            children.sort_by(|&a, &b| sort.compare(self.iter_at(a), self.iter_at(b)));
+            for &child in &children {
+                self.arena.detach(child);
+                self.arena.insert_child_last(parent, child);
+            }
+            // Mirrors have no children of their own, so each bullet is sorted once
+            if sort.recursive {
+                parents.extend(children);
+            }
+        }
+        before
+    }
+
+    /// Puts the children of `parent` back in an order returned by [sort](Tree::sort). Children
+    /// that are not listed go after the listed ones, and listed bullets that are no longer
+    /// children of `parent` stay where they are.
+    pub fn reorder(&mut self, parent: Id, children: &[Id]) -> Result<(), TreeError> {
+        let index = self.get_node(parent).ok_or(TreeError::NotFound(parent))?;
+        self.record(Op::Reorder {
+            parent,
+            children: children.to_vec(),
+        });
+        let listed: Vec<Index> = children
+            .iter()
+            .filter_map(|&id| self.get_node(id))
+            .filter(|&child| self.arena.get(child).parent == Some(index))
+            .collect();
+        for &child in listed.iter().rev() {
+            self.arena.detach(child);
+            self.arena.insert_child_first(index, child);
+        }
+        Ok(())
+    }
+}
+
+fn time_cmp(a: Option<Timestamp>, b: Option<Timestamp>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+/// Compares lowercase text, except that runs of digits compare by their value
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let order = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(&y))
+            }
+            (Some(_), Some(_)) => {
+                let x = a.next().unwrap().to_lowercase();
+                x.cmp(b.next().unwrap().to_lowercase())
+            }
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// The run of digits at the start of `chars`, without leading zeros
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        if !(number.is_empty() && digit == '0') {
+            number.push(digit);
+        }
+    }
+    number
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SortKey::Alphabetical => "alphabetical",
+            SortKey::Natural => "natural",
+            SortKey::Created => "created",
+            SortKey::Modified => "modified",
+            SortKey::Completed => "completed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s {
+            "alphabetical" | "alpha" => Ok(SortKey::Alphabetical),
+            "natural" => Ok(SortKey::Natural),
+            "created" => Ok(SortKey::Created),
+            "modified" => Ok(SortKey::Modified),
+            "completed" => Ok(SortKey::Completed),
+            _ => Err(format!("unknown sort: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{id, new_test_tree};
+    use super::*;
+    use crate::time::FixedClock;
+
+    fn children(tree: &Tree, parent: u128) -> Vec<String> {
+        tree.node_iter(id(parent))
+            .unwrap()
+            .children_iter()
+            .map(|n| n.content().clone())
+            .collect()
+    }
+
+    #[test]
+    fn compares_numbers_by_value() {
+        assert_eq!(natural_cmp("item 2", "Item 10"), Ordering::Less);
+        assert_eq!(natural_cmp("a10b", "a010c"), Ordering::Less);
+        assert_eq!(natural_cmp("b", "A"), Ordering::Greater);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+    }
+
+    #[test]
+    fn sorts_children_of_active() {
+        // 1.
+        //   2. b10
+        //   3. b9
+        //     4. z
+        //     5. y
+        //   6. A
+        let clock = FixedClock::new(Timestamp::from_seconds(100));
+        let mut tree = new_test_tree();
+        tree.set_clock(Box::new(clock.clone()));
+        for (i, content) in ["b10", "b9", "z", "y", "A"].iter().enumerate() {
+            clock.set(Timestamp::from_seconds(100 - i as i64));
+            tree.create_sibling();
+            tree.get_mut_active_content().push_str(content);
+        }
+        for i in 2..=6 {
+            tree.activate(id(i)).unwrap();
+            tree.indent(false).unwrap();
+            if i == 4 || i == 5 {
+                tree.indent(false).unwrap();
+            }
+        }
+        tree.activate(id(3)).unwrap();
+        tree.set_completed(true);
+        tree.activate(id(1)).unwrap();
+
+        let before = tree.sort(Sort::new(SortKey::Natural));
+        assert_eq!(before, [(id(1), vec![id(2), id(3), id(6)])]);
+        assert_eq!(children(&tree, 1), ["A", "b9", "b10"]);
+        assert_eq!(children(&tree, 3), ["z", "y"]);
+
+        tree.sort(Sort::new(SortKey::Alphabetical));
+        assert_eq!(children(&tree, 1), ["A", "b10", "b9"]);
+        tree.sort(Sort::new(SortKey::Created));
+        assert_eq!(children(&tree, 1), ["A", "b9", "b10"]);
+        tree.sort(Sort::new(SortKey::Completed));
+        assert_eq!(children(&tree, 1), ["A", "b10", "b9"]);
+
+        let mut sort = Sort {
+            key: SortKey::Natural,
+            reverse: false,
+            recursive: true,
+        };
+        tree.sort(sort);
+        assert_eq!(children(&tree, 3), ["y", "z"]);
+        sort.reverse = true;
+        tree.sort(sort);
+        assert_eq!(children(&tree, 1), ["b10", "b9", "A"]);
+        assert_eq!(children(&tree, 3), ["z", "y"]);
+        assert_eq!(tree.get_active_id(), id(1));
+        assert_eq!(tree.check_invariants(), []);
+    }
+
+    #[test]
+    fn reorder_restores_sorted_children() {
+        let mut tree = new_test_tree();
+        for content in ["b", "c", "a"].iter() {
+            tree.create_sibling();
+            tree.get_mut_active_content().push_str(content);
+        }
+        for i in 2..=4 {
+            tree.activate(id(i)).unwrap();
+            tree.indent(false).unwrap();
+        }
+        tree.activate(id(1)).unwrap();
+        let before = tree.sort(Sort::new(SortKey::Alphabetical));
+        assert_eq!(children(&tree, 1), ["a", "b", "c"]);
+        tree.activate(id(2)).unwrap();
+        tree.create_sibling(); // id = 5
+        tree.get_mut_active_content().push_str("new");
+        for (parent, order) in &before {
+            tree.reorder(*parent, order).unwrap();
+        }
+        assert_eq!(children(&tree, 1), ["b", "c", "a", "new"]);
+        assert_eq!(tree.reorder(id(9), &[]), Err(TreeError::NotFound(id(9))));
+        assert_eq!(tree.check_invariants(), []);
+    }
+}
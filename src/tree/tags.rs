@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::id::Id;
+use super::node::Index;
+use super::{NodeIterator, TraversalType, Tree};
+use crate::markup;
+
+/// Which nodes mention each `#tag` and `@person`. Tags are kept with their sigil and are case
+/// sensitive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagIndex {
+    nodes: BTreeMap<String, BTreeSet<Id>>,
+    tags: HashMap<Id, Vec<String>>,
+}
+
+impl TagIndex {
+    /// Every tag with the number of nodes that mention it, in alphabetical order
+    pub fn counts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.nodes
+            .iter()
+            .map(|(tag, ids)| (tag.as_str(), ids.len()))
+    }
+
+    /// The nodes that mention `tag`
+    pub fn nodes(&self, tag: &str) -> impl Iterator<Item = Id> + '_ {
+        self.nodes.get(tag).into_iter().flatten().copied()
+    }
+
+    /// Tags that start with `prefix`, which should include the sigil
+    pub fn completions<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        self.nodes
+            .range(prefix.to_string()..)
+            .map(|(tag, _)| tag.as_str())
+            .take_while(move |tag| tag.starts_with(prefix))
+    }
+
+    fn set(&mut self, id: Id, content: &str) {
+        self.remove(id);
+        let mut tags: Vec<String> = markup::tags(content).map(String::from).collect();
+        tags.sort();
+        tags.dedup();
+        for tag in &tags {
+            self.nodes.entry(tag.clone()).or_default().insert(id);
+        }
+        if !tags.is_empty() {
+            self.tags.insert(id, tags);
+        }
+    }
+
+    fn remove(&mut self, id: Id) {
+        for tag in self.tags.remove(&id).unwrap_or_default() {
+            if let Some(ids) = self.nodes.get_mut(&tag) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.nodes.remove(&tag);
+                }
+            }
+        }
+    }
+}
+
+impl Tree {
+    /// The tags of the tree as of now, including edits of the active content.
+    pub fn tag_index(&mut self) -> &TagIndex {
+        self.flush_tags();
+        &self.tags
+    }
+
+    /// Notes that the content of the active node is about to change. It is indexed again once
+    /// another node changes or the index is read.
+    pub(super) fn mark_tags_stale(&mut self) {
+        let id = self.get_active_source_id();
+        if self.stale_tags != Some(id) {
+            self.flush_tags();
+            self.stale_tags = Some(id);
+        }
+    }
+
+    fn flush_tags(&mut self) {
+        if let Some(id) = self.stale_tags.take() {
+            match self.get_node(id) {
+                Some(index) => self.tags.set(id, &self.arena.get(index).content),
+                None => self.tags.remove(id),
+            }
+        }
+    }
+
+    /// Indexes the node at `index` and its descendants, which were just added to the tree.
+    pub(super) fn index_tags(&mut self, index: Index) {
+        let nodes: Vec<(Id, Index)> = NodeIterator::new(&self.arena, index)
+            .traverse(TraversalType::Level)
+            .map(|n| (n.id(), n.index))
+            .collect();
+        for (id, index) in nodes {
+            self.tags.set(id, &self.arena.get(index).content);
+        }
+    }
+
+    pub(super) fn unindex_tags(&mut self, id: Id) {
+        self.tags.remove(id);
+    }
+
+    /// Builds the index again from every node in the tree.
+    pub(super) fn reindex_tags(&mut self) {
+        self.tags = TagIndex::default();
+        self.stale_tags = None;
+        self.index_tags(self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{id, new_test_tree};
+    use super::super::Dir;
+
+    fn counts(tree: &mut super::Tree) -> Vec<(String, usize)> {
+        let index = tree.tag_index();
+        index
+            .counts()
+            .map(|(tag, n)| (tag.to_string(), n))
+            .collect()
+    }
+
+    #[test]
+    fn index_follows_edits() {
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content().push_str("#a @b #a");
+        tree.create_sibling(); // id = 2
+        tree.get_mut_active_content().push_str("#a #c");
+        assert_eq!(
+            counts(&mut tree),
+            [("#a".into(), 2), ("#c".into(), 1), ("@b".into(), 1)]
+        );
+
+        tree.get_mut_active_content().truncate(2);
+        let subtree = tree.get_subtree();
+        tree.insert_subtree(subtree, Dir::Below); // id = 3
+        assert_eq!(
+            tree.tag_index().nodes("#a").collect::<Vec<_>>(),
+            [id(1), id(2), id(3)]
+        );
+
+        tree.activate(id(1)).unwrap();
+        tree.delete().unwrap();
+        assert_eq!(counts(&mut tree), [("#a".into(), 2)]);
+    }
+
+    #[test]
+    fn completes_by_prefix() {
+        let mut tree = new_test_tree();
+        tree.get_mut_active_content()
+            .push_str("#pro #project #progress #p @pro");
+        let completions: Vec<&str> = tree.tag_index().completions("#pro").collect();
+        assert_eq!(completions, ["#pro", "#progress", "#project"]);
+    }
+}